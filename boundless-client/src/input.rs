@@ -0,0 +1,121 @@
+use anyhow::{bail, ensure, Context};
+use borsh::BorshDeserialize;
+use risc0_to_bitvm2_core::{
+    header_chain::{
+        network_constants, BlockHeaderCircuitOutput, CircuitBlockHeader, HeaderChainCircuitInput,
+        HeaderChainPrevProofType, NetworkConstants,
+    },
+    headers_file::HeadersFile,
+};
+use risc0_zkvm::{compute_image_id, Receipt};
+use std::{fs, path::Path};
+
+/// The Bitcoin network of the header chain guest to prove.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Network {
+    Mainnet,
+    Testnet4,
+    /// Needs the `testnet3` feature, as its guest is not checked in.
+    #[cfg(feature = "testnet3")]
+    Testnet3,
+    Signet,
+    Regtest,
+}
+
+impl Network {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Network::Mainnet => "mainnet",
+            Network::Testnet4 => "testnet4",
+            #[cfg(feature = "testnet3")]
+            Network::Testnet3 => "testnet3",
+            Network::Signet => "signet",
+            Network::Regtest => "regtest",
+        }
+    }
+
+    pub fn constants(&self) -> &'static NetworkConstants {
+        network_constants(self.name()).expect("Every network has constants")
+    }
+
+    /// The reproducibly built header chain guest of the network.
+    pub fn header_chain_elf(&self) -> &'static [u8] {
+        match self {
+            Network::Mainnet => include_bytes!("../../elfs/mainnet-header-chain-guest.bin"),
+            Network::Testnet4 => include_bytes!("../../elfs/testnet4-header-chain-guest.bin"),
+            #[cfg(feature = "testnet3")]
+            Network::Testnet3 => include_bytes!("../../elfs/testnet3-header-chain-guest.bin"),
+            Network::Signet => include_bytes!("../../elfs/signet-header-chain-guest.bin"),
+            Network::Regtest => include_bytes!("../../elfs/regtest-header-chain-guest.bin"),
+        }
+    }
+
+    pub fn header_chain_method_id(&self) -> [u32; 8] {
+        compute_image_id(self.header_chain_elf())
+            .unwrap()
+            .as_words()
+            .try_into()
+            .unwrap()
+    }
+}
+
+/// Reads a legacy or versioned headers file of the network, starting from the genesis block.
+pub fn read_headers(path: &Path, network: Network) -> anyhow::Result<Vec<CircuitBlockHeader>> {
+    let bytes = fs::read(path).with_context(|| format!("failed to read {:?}", path))?;
+    let file = HeadersFile::decode(&bytes, network.constants().magic)
+        .with_context(|| format!("invalid headers file {:?}", path))?;
+    ensure!(
+        file.start_height == 0,
+        "{:?} does not start at the genesis block",
+        path
+    );
+    Ok(file.headers)
+}
+
+/// Prepares the header chain input for the next `batch_size` headers after the previous receipt.
+///
+/// Boundless provers cannot be given assumptions, so a batch that continues a previous receipt
+/// is requested as a range proof on top of the state in its journal. The previous receipt is
+/// verified here, and the range is composed with it by `host aggregate`.
+pub fn header_chain_input(
+    network: Network,
+    prev_receipt: Option<&Receipt>,
+    headers: &[CircuitBlockHeader],
+    batch_size: usize,
+) -> anyhow::Result<HeaderChainCircuitInput> {
+    let method_id = network.header_chain_method_id();
+    let (start, prev_proof) = match prev_receipt {
+        Some(receipt) => {
+            let output = BlockHeaderCircuitOutput::try_from_slice(&receipt.journal.bytes)
+                .context("the previous receipt is not a header chain proof")?;
+            if output.method_id != method_id {
+                bail!(
+                    "the previous receipt was proven with method ID {:?}, not the {} header chain guest {:?}",
+                    output.method_id,
+                    network.name(),
+                    method_id
+                );
+            }
+            receipt
+                .verify(method_id)
+                .context("the previous receipt is invalid")?;
+            (
+                output.chain_state.block_height as usize + 1,
+                HeaderChainPrevProofType::UnknownPrevState(output.chain_state),
+            )
+        }
+        None => (0, HeaderChainPrevProofType::GenesisBlock),
+    };
+    ensure!(
+        start + batch_size <= headers.len(),
+        "only {} headers are available, {} needed",
+        headers.len(),
+        start + batch_size
+    );
+
+    Ok(HeaderChainCircuitInput {
+        method_id,
+        prev_proof,
+        block_headers: headers[start..start + batch_size].to_vec(),
+    })
+}
@@ -0,0 +1,3 @@
+pub mod input;
+pub mod market;
+pub mod receipt;
@@ -1,47 +1,64 @@
-use std::time::{Duration, Instant};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
 
-use alloy::signers::local::PrivateKeySigner;
-use alloy_primitives::{utils::parse_ether, Address};
 use anyhow::{bail, ensure};
 use borsh::BorshDeserialize;
-use boundless_market::{
-    client::ClientBuilder,
-    contracts::{Input, Offer, Predicate, ProofRequest, Requirements},
-    storage::StorageProviderConfig,
+use boundless_client::{
+    input::{self, Network},
+    market::{self, BoundlessMarket, ProofMarket},
+    receipt::{self, SavedProof},
 };
-use clap::Parser;
-use header_chain::header_chain::{CircuitBlockHeader, HeaderChainCircuitInput};
-use risc0_zkvm::sha::Digestible;
-use risc0_zkvm::{compute_image_id, default_executor, ExecutorEnv};
-use url::Url;
-
-const ELF: &[u8] = include_bytes!("../../elfs/mainnet-header-chain-guest");
+use clap::{Parser, Subcommand};
+use risc0_to_bitvm2_core::header_chain::{
+    BlockHeaderCircuitOutput, CircuitBlockHeader, HeaderChainPrevProofType,
+};
+use risc0_zkvm::Receipt;
 
-/// Arguments of the publisher CLI.
+/// Arguments of the Boundless client CLI.
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
 struct Args {
-    /// URL of the Ethereum RPC endpoint.
-    #[clap(short, long, env)]
-    rpc_url: Url,
-    /// Private key used to interact with the EvenNumber contract.
-    #[clap(short, long, env)]
-    wallet_private_key: PrivateKeySigner,
-    /// Submit the request offchain via the provided order stream service url.
-    #[clap(short, long, requires = "order_stream_url")]
-    offchain: bool,
-    /// Offchain order stream service URL to submit offchain requests to.
-    #[clap(long, env)]
-    order_stream_url: Option<Url>,
-    /// Storage provider to use
-    #[clap(flatten)]
-    storage_config: Option<StorageProviderConfig>,
-    /// Address of the RiscZeroSetVerifier contract.
-    #[clap(short, long, env)]
-    set_verifier_address: Address,
-    /// Address of the BoundlessfMarket contract.
-    #[clap(short, long, env)]
-    boundless_market_address: Address,
+    /// The network of the header chain guest.
+    #[clap(long, value_enum, default_value = "mainnet", global = true)]
+    network: Network,
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Request a proof of the next batch of Bitcoin headers from the market.
+    Prove {
+        /// The previous proof file path. If omitted, the batch starts from genesis.
+        #[clap(long)]
+        input_proof: Option<PathBuf>,
+        /// The headers file, consecutive 80 byte headers from genesis. Defaults to
+        /// `data/headers/<network>-headers.bin`.
+        #[clap(long)]
+        headers: Option<PathBuf>,
+        /// The number of headers to prove.
+        #[clap(long)]
+        batch_size: usize,
+        /// The output proof file path.
+        #[clap(long)]
+        output: PathBuf,
+        #[clap(flatten)]
+        market: Box<BoundlessMarket>,
+    },
+    /// Resolve a set inclusion proof into a succinct receipt, by proving its input again.
+    Resolve {
+        /// The set inclusion proof file path.
+        proof: PathBuf,
+        /// The output receipt file path.
+        output: PathBuf,
+    },
+    /// Verify a saved receipt or set inclusion proof against the header chain guest.
+    Verify {
+        /// The proof file path.
+        proof: PathBuf,
+    },
 }
 
 #[tokio::main]
@@ -57,101 +74,328 @@ async fn main() -> Result<(), anyhow::Error> {
     }
     let args = Args::parse();
 
-    let image_id = compute_image_id(ELF).unwrap();
-
-    let headers = include_bytes!("../../mainnet-headers.bin");
-    let headers = headers
-        .chunks(80)
-        .map(|header| CircuitBlockHeader::try_from_slice(header).unwrap())
-        .collect::<Vec<CircuitBlockHeader>>();
-
-    let input = HeaderChainCircuitInput {
-        method_id: [0; 8],
-        prev_proof: header_chain::header_chain::HeaderChainPrevProofType::GenesisBlock,
-        block_headers: headers[0..50].to_vec(),
-    };
-
-    // Create a Boundless client from the provided parameters.
-    let boundless_client = ClientBuilder::default()
-        .with_rpc_url(args.rpc_url)
-        .with_boundless_market_address(args.boundless_market_address)
-        .with_set_verifier_address(args.set_verifier_address)
-        .with_order_stream_url(args.offchain.then_some(args.order_stream_url).flatten())
-        .with_storage_provider_config(args.storage_config)
-        .with_private_key(args.wallet_private_key)
-        .build()
-        .await?;
-
-    ensure!(
-        boundless_client.storage_provider.is_some(),
-        "a storage provider is required to upload the zkVM guest ELF"
+    match args.command {
+        Command::Prove {
+            input_proof,
+            headers,
+            batch_size,
+            output,
+            market,
+        } => {
+            let prev_receipt = match input_proof.as_deref().map(SavedProof::load).transpose()? {
+                Some(SavedProof::Receipt(receipt)) => Some(*receipt),
+                Some(SavedProof::SetInclusion(_)) => {
+                    bail!("the previous proof has to be resolved into a receipt first")
+                }
+                None => None,
+            };
+            let headers_path = headers.unwrap_or_else(|| {
+                PathBuf::from(format!("data/headers/{}-headers.bin", args.network.name()))
+            });
+            let headers = input::read_headers(&headers_path, args.network)?;
+
+            let proof = prove_headers(
+                market.as_ref(),
+                args.network,
+                prev_receipt.as_ref(),
+                &headers,
+                batch_size,
+            )
+            .await?;
+            match proof {
+                SavedProof::Receipt(receipt) => {
+                    save_receipt(&receipt, &output)?;
+                    if let Some(input_proof) = input_proof {
+                        println!(
+                            "Compose it with the previous proof with `host aggregate {:?} <output> {:?}`",
+                            input_proof, output
+                        );
+                    }
+                }
+                SavedProof::SetInclusion(proof) => {
+                    proof.save(&output)?;
+                    println!(
+                        "Set inclusion proof saved to {:?}. Resolve it into a receipt with `resolve`",
+                        output
+                    );
+                }
+            }
+        }
+        Command::Resolve {
+            proof: path,
+            output,
+        } => {
+            let SavedProof::SetInclusion(proof) = SavedProof::load(&path)? else {
+                bail!("{:?} is already a receipt", path);
+            };
+            ensure!(
+                proof.image_id == args.network.header_chain_method_id(),
+                "the proof is not of the {} header chain guest",
+                args.network.name()
+            );
+            let receipt = receipt::resolve(&proof, args.network.header_chain_elf())?;
+            save_receipt(&receipt, &output)?;
+        }
+        Command::Verify { proof: path } => {
+            verify(&SavedProof::load(&path)?, args.network)?;
+            println!("{:?} is valid", path);
+        }
+    }
+    Ok(())
+}
+
+/// Requests a proof of the next `batch_size` headers from the market and returns the fulfilled
+/// proof. The batch is executed locally first, to price the request and to fix its journal.
+async fn prove_headers(
+    market: &impl ProofMarket,
+    network: Network,
+    prev_receipt: Option<&Receipt>,
+    headers: &[CircuitBlockHeader],
+    batch_size: usize,
+) -> anyhow::Result<SavedProof> {
+    let input = input::header_chain_input(network, prev_receipt, headers, batch_size)?;
+    let is_range = matches!(
+        input.prev_proof,
+        HeaderChainPrevProofType::UnknownPrevState(_)
     );
-    let image_url = boundless_client.upload_image(ELF).await?;
-    tracing::info!("Uploaded image to {}", image_url);
-
-    let input_bytes = borsh::to_vec(&input).unwrap();
-
-    // If the input exceeds 2 kB, upload the input and provide its URL instead, as a rule of thumb.
-    let input_url = boundless_client.upload_input(&input_bytes).await?;
-    tracing::info!("Uploaded input to {}", input_url);
-
-    let env = ExecutorEnv::builder().write_slice(&input_bytes).build()?;
-    let session_info = default_executor().execute(env, ELF)?;
-    let mcycles_count = session_info
-        .segments
-        .iter()
-        .map(|segment| 1 << segment.po2)
-        .sum::<u64>()
-        .div_ceil(1_000_000);
-    println!("{} mcycles", mcycles_count);
-    let journal = session_info.journal;
-    println!("Journal: {:#?}", journal);
-
-    let request = ProofRequest::default()
-        .with_image_url(&image_url)
-        .with_input(Input::url(&input_url))
-        .with_requirements(Requirements::new(
-            image_id,
-            Predicate::digest_match(journal.digest()),
-        ))
-        .with_offer(
-            Offer::default()
-                // The market uses a reverse Dutch auction mechanism to match requests with provers.
-                // Each request has a price range that a prover can bid on. One way to set the price
-                // is to choose a desired (min and max) price per million cycles and multiply it
-                // by the number of cycles. Alternatively, you can use the `with_min_price` and
-                // `with_max_price` methods to set the price directly.
-                .with_min_price_per_mcycle(parse_ether("0.001")?, mcycles_count)
-                // NOTE: If your offer is not being accepted, try increasing the max price.
-                .with_max_price_per_mcycle(parse_ether("0.002")?, mcycles_count)
-                // The timeout is the maximum number of blocks the request can stay
-                // unfulfilled in the market before it expires. If a prover locks in
-                // the request and does not fulfill it before the timeout, the prover can be
-                // slashed.
-                .with_timeout(1000),
+    let (proof, journal) =
+        market::prove(market, network.header_chain_elf(), borsh::to_vec(&input)?).await?;
+
+    if is_range {
+        println!("Proved a range of {} headers", batch_size);
+    } else {
+        let output = BlockHeaderCircuitOutput::try_from_slice(&journal)?;
+        println!("Proved up to height {}", output.chain_state.block_height);
+    }
+    Ok(proof)
+}
+
+/// Verifies a saved proof of the header chain guest of `network`.
+fn verify(proof: &SavedProof, network: Network) -> anyhow::Result<()> {
+    let method_id = network.header_chain_method_id();
+    match proof {
+        SavedProof::Receipt(receipt) => receipt.verify(method_id)?,
+        SavedProof::SetInclusion(proof) => {
+            ensure!(
+                proof.image_id == method_id,
+                "the proof is not of the {} header chain guest",
+                network.name()
+            );
+            proof.verify()?;
+        }
+    }
+    Ok(())
+}
+
+/// Saves the receipt in the format `host` reads.
+fn save_receipt(receipt: &Receipt, path: &Path) -> anyhow::Result<()> {
+    fs::write(path, borsh::to_vec(receipt)?)?;
+    println!("Receipt saved to {:?}", path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use boundless_client::{market::MockMarket, receipt::SetInclusionProof};
+    use hex_literal::hex;
+    use risc0_to_bitvm2_core::header_chain::{HeaderChainRangeOutput, RANGE_OUTPUT_TAG};
+    use risc0_zkvm::{sha::Digestible, ReceiptClaim};
+
+    const MAINNET_FIRST_10: &[u8] =
+        include_bytes!("../../data/proofs/mainnet/mainnet_first_10.bin");
+
+    // Mainnet block headers from 0 to 11
+    const MAINNET_BLOCK_HEADERS: [[u8; 80]; 12] = [
+        hex!("0100000000000000000000000000000000000000000000000000000000000000000000003ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a29ab5f49ffff001d1dac2b7c"),
+        hex!("010000006fe28c0ab6f1b372c1a6a246ae63f74f931e8365e15a089c68d6190000000000982051fd1e4ba744bbbe680e1fee14677ba1a3c3540bf7b1cdb606e857233e0e61bc6649ffff001d01e36299"),
+        hex!("010000004860eb18bf1b1620e37e9490fc8a427514416fd75159ab86688e9a8300000000d5fdcc541e25de1c7a5addedf24858b8bb665c9f36ef744ee42c316022c90f9bb0bc6649ffff001d08d2bd61"),
+        hex!("01000000bddd99ccfda39da1b108ce1a5d70038d0a967bacb68b6b63065f626a0000000044f672226090d85db9a9f2fbfe5f0f9609b387af7be5b7fbb7a1767c831c9e995dbe6649ffff001d05e0ed6d"),
+        hex!("010000004944469562ae1c2c74d9a535e00b6f3e40ffbad4f2fda3895501b582000000007a06ea98cd40ba2e3288262b28638cec5337c1456aaf5eedc8e9e5a20f062bdf8cc16649ffff001d2bfee0a9"),
+        hex!("0100000085144a84488ea88d221c8bd6c059da090e88f8a2c99690ee55dbba4e00000000e11c48fecdd9e72510ca84f023370c9a38bf91ac5cae88019bee94d24528526344c36649ffff001d1d03e477"),
+        hex!("01000000fc33f596f822a0a1951ffdbf2a897b095636ad871707bf5d3162729b00000000379dfb96a5ea8c81700ea4ac6b97ae9a9312b2d4301a29580e924ee6761a2520adc46649ffff001d189c4c97"),
+        hex!("010000008d778fdc15a2d3fb76b7122a3b5582bea4f21f5a0c693537e7a03130000000003f674005103b42f984169c7d008370967e91920a6a5d64fd51282f75bc73a68af1c66649ffff001d39a59c86"),
+        hex!("010000004494c8cf4154bdcc0720cd4a59d9c9b285e4b146d45f061d2b6c967100000000e3855ed886605b6d4a99d5fa2ef2e9b0b164e63df3c4136bebf2d0dac0f1f7a667c86649ffff001d1c4b5666"),
+        hex!("01000000c60ddef1b7618ca2348a46e868afc26e3efc68226c78aa47f8488c4000000000c997a5e56e104102fa209c6a852dd90660a20b2d9c352423edce25857fcd37047fca6649ffff001d28404f53"),
+        hex!("010000000508085c47cc849eb80ea905cc7800a3be674ffc57263cf210c59d8d00000000112ba175a1e04b14ba9e7ea5f76ab640affeef5ec98173ac9799a852fa39add320cd6649ffff001d1e2de565"),
+        hex!("01000000e915d9a478e3adf3186c07c61a22228b10fd87df343c92782ecc052c000000006e06373c80de397406dc3d19c90d71d230058d28293614ea58d6a57f8f5d32f8b8ce6649ffff001d173807f8"),
+    ];
+
+    fn regtest_headers() -> Vec<CircuitBlockHeader> {
+        input::read_headers(
+            Path::new("../data/headers/regtest-headers.bin"),
+            Network::Regtest,
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_prove_headers_with_mock_market() {
+        let headers = regtest_headers();
+        let proof = prove_headers(&MockMarket, Network::Regtest, None, &headers, 10)
+            .await
+            .unwrap();
+        let SavedProof::Receipt(receipt) = proof else {
+            panic!("The mock market returns fake receipts");
+        };
+
+        let output = BlockHeaderCircuitOutput::try_from_slice(&receipt.journal.bytes).unwrap();
+        assert_eq!(output.method_id, Network::Regtest.header_chain_method_id());
+        assert_eq!(output.chain_state.block_height, 9);
+        assert_eq!(
+            receipt.claim().unwrap().digest(),
+            ReceiptClaim::ok(output.method_id, receipt.journal.bytes.clone()).digest()
         );
-    println!("Request: {:#?}", request);
 
-    // Send the request and wait for it to be completed.
-    let start_time = Instant::now();
-    let (request_id, expires_at) = boundless_client.submit_request(&request).await?;
-    tracing::info!("Request 0x{request_id:x} submitted at {:?}", start_time);
+        // The saved receipt can be read back by `host`.
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("regtest_first_9.bin");
+        save_receipt(&receipt, &path).unwrap();
+        let SavedProof::Receipt(saved) = SavedProof::load(&path).unwrap() else {
+            panic!("Expected a receipt");
+        };
+        assert_eq!(saved.journal.bytes, receipt.journal.bytes);
+        assert_eq!(
+            saved.claim().unwrap().digest(),
+            receipt.claim().unwrap().digest()
+        );
+    }
 
-    // Wait for the request to be fulfilled by the market, returning the journal and seal.
-    tracing::info!("Waiting for 0x{request_id:x} to be fulfilled");
-    let (journal, seal) = boundless_client
-        .wait_for_request_fulfillment(request_id, Duration::from_secs(10), expires_at)
-        .await?;
+    fn mainnet_headers() -> Vec<CircuitBlockHeader> {
+        MAINNET_BLOCK_HEADERS
+            .iter()
+            .map(|header| CircuitBlockHeader::try_from_slice(header).unwrap())
+            .collect()
+    }
 
-    let end_time = Instant::now();
-    let duration = end_time.duration_since(start_time);
+    #[test]
+    fn test_header_chain_input_after_receipt() {
+        let headers = mainnet_headers();
+        let prev_receipt = Receipt::try_from_slice(MAINNET_FIRST_10).unwrap();
+        let prev_output =
+            BlockHeaderCircuitOutput::try_from_slice(&prev_receipt.journal.bytes).unwrap();
 
-    tracing::info!("Request 0x{request_id:x} fulfilled");
-    tracing::info!("End time: {:?}", end_time);
-    tracing::info!("Time taken: {:?}", duration);
-    tracing::info!("Request 0x{request_id:x} fulfilled");
-    tracing::info!("Journal: {:#?}", journal);
-    tracing::info!("Seal: {:#?}", seal);
+        // A batch after a previous receipt is requested as a range on top of its state.
+        let input =
+            input::header_chain_input(Network::Mainnet, Some(&prev_receipt), &headers, 1).unwrap();
+        assert_eq!(input.method_id, Network::Mainnet.header_chain_method_id());
+        assert_eq!(
+            input.prev_proof,
+            HeaderChainPrevProofType::UnknownPrevState(prev_output.chain_state)
+        );
+        assert_eq!(input.block_headers, headers[11..].to_vec());
 
-    Ok(())
+        assert!(
+            input::header_chain_input(Network::Mainnet, Some(&prev_receipt), &headers, 2).is_err()
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "the embedded ELFs predate range proofs, rebuild them with REPR_GUEST_BUILD=1"]
+    async fn test_prove_range_with_mock_market() {
+        let headers = mainnet_headers();
+        let prev_receipt = Receipt::try_from_slice(MAINNET_FIRST_10).unwrap();
+        let prev_output =
+            BlockHeaderCircuitOutput::try_from_slice(&prev_receipt.journal.bytes).unwrap();
+
+        let proof = prove_headers(
+            &MockMarket,
+            Network::Mainnet,
+            Some(&prev_receipt),
+            &headers,
+            1,
+        )
+        .await
+        .unwrap();
+        let SavedProof::Receipt(receipt) = proof else {
+            panic!("The mock market returns fake receipts");
+        };
+
+        // The range proof is what `host aggregate` composes with the previous receipt.
+        let range = HeaderChainRangeOutput::try_from_slice(&receipt.journal.bytes).unwrap();
+        assert_eq!(range.tag, RANGE_OUTPUT_TAG);
+        assert_eq!(range.method_id, Network::Mainnet.header_chain_method_id());
+        assert_eq!(range.initial_state_digest, prev_output.chain_state.digest());
+        assert_eq!(range.chain_state.block_height, 11);
+        assert_eq!(
+            range.chain_state.best_block_hash,
+            headers[11].compute_block_hash()
+        );
+    }
+
+    #[test]
+    fn test_header_chain_input() {
+        let headers = regtest_headers();
+        let input = input::header_chain_input(Network::Regtest, None, &headers, 10).unwrap();
+        assert_eq!(input.method_id, Network::Regtest.header_chain_method_id());
+        assert_eq!(input.prev_proof, HeaderChainPrevProofType::GenesisBlock);
+        assert_eq!(input.block_headers, headers[..10].to_vec());
+
+        assert!(input::header_chain_input(Network::Regtest, None, &headers, 1000).is_err());
+    }
+
+    #[test]
+    fn test_header_chain_input_wrong_network() {
+        let headers = regtest_headers();
+        let prev_receipt: Receipt = Receipt::try_from_slice(include_bytes!(
+            "../../data/proofs/signet/signet_first_9.bin"
+        ))
+        .unwrap();
+        let err = input::header_chain_input(Network::Regtest, Some(&prev_receipt), &headers, 10)
+            .unwrap_err();
+        assert!(err.to_string().contains("method ID"));
+    }
+
+    #[test]
+    fn test_verify_receipt() {
+        let receipt = Receipt::try_from_slice(MAINNET_FIRST_10).unwrap();
+        let proof = SavedProof::Receipt(Box::new(receipt));
+        verify(&proof, Network::Mainnet).unwrap();
+        assert!(verify(&proof, Network::Signet).is_err());
+    }
+
+    #[test]
+    fn test_set_inclusion_proof_roundtrip() {
+        let proof = SetInclusionProof {
+            image_id: Network::Regtest.header_chain_method_id(),
+            journal: vec![1, 2, 3],
+            seal: vec![0xab; 100],
+            input: vec![4, 5, 6],
+            set_builder_image_id: [7; 8],
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("proof.json");
+        proof.save(&path).unwrap();
+        let SavedProof::SetInclusion(loaded) = SavedProof::load(&path).unwrap() else {
+            panic!("Expected a set inclusion proof");
+        };
+        assert_eq!(loaded, proof);
+
+        // A seal that is not a valid set inclusion proof is rejected.
+        assert!(verify(&SavedProof::SetInclusion(proof), Network::Regtest).is_err());
+    }
+
+    #[test]
+    fn test_groth16_fulfillment_is_rejected() {
+        let job = market::ProofJob {
+            elf: Network::Regtest.header_chain_elf(),
+            image_id: Network::Regtest.header_chain_method_id(),
+            input: vec![],
+            journal_digest: [0; 32],
+            mcycles: 1,
+        };
+        let mut seal = receipt::groth16_selector().to_vec();
+        seal.extend_from_slice(&[0; 256]);
+        let err = receipt::decode_fulfillment(
+            &job,
+            market::Fulfillment {
+                journal: vec![1, 2, 3],
+                seal,
+                set_builder_image_id: None,
+            },
+        )
+        .err()
+        .unwrap();
+        assert!(err.to_string().contains("Groth16"));
+    }
 }
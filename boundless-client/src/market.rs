@@ -0,0 +1,229 @@
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use alloy::signers::local::PrivateKeySigner;
+use alloy_primitives::{utils::parse_ether, Address};
+use anyhow::ensure;
+use boundless_market::{
+    client::ClientBuilder,
+    contracts::{Input, Offer, Predicate, ProofRequest, Requirements},
+    storage::StorageProviderConfig,
+};
+use risc0_zkvm::{
+    compute_image_id, default_executor, sha::Digestible, Digest, ExecutorEnv, ReceiptClaim,
+};
+use url::Url;
+
+use crate::receipt::{self, SavedProof};
+
+/// Requests a proof of the guest on the input from the market, and returns the fulfilled proof
+/// and its journal. The guest is executed locally first, to price the request and to fix its
+/// journal, so it must not need assumptions.
+pub async fn prove(
+    market: &impl ProofMarket,
+    elf: &[u8],
+    input: Vec<u8>,
+) -> anyhow::Result<(SavedProof, Vec<u8>)> {
+    let env = ExecutorEnv::builder().write_slice(&input).build()?;
+    let session_info = default_executor().execute(env, elf)?;
+    let mcycles = session_info
+        .segments
+        .iter()
+        .map(|segment| 1 << segment.po2)
+        .sum::<u64>()
+        .div_ceil(1_000_000);
+    tracing::info!("{} mcycles", mcycles);
+
+    let job = ProofJob {
+        elf,
+        image_id: compute_image_id(elf)?.into(),
+        input,
+        journal_digest: session_info.journal.digest().into(),
+        mcycles,
+    };
+    let fulfillment = market.fulfill(&job).await?;
+    ensure!(
+        fulfillment.journal == session_info.journal.bytes,
+        "the fulfilled journal does not match the executed one"
+    );
+    Ok((
+        receipt::decode_fulfillment(&job, fulfillment)?,
+        session_info.journal.bytes,
+    ))
+}
+
+/// A proof request for a guest, with the journal its execution produced locally.
+pub struct ProofJob<'a> {
+    pub elf: &'a [u8],
+    pub image_id: [u32; 8],
+    pub input: Vec<u8>,
+    /// The digest of the expected journal. Provers are only paid for a proof of this journal.
+    pub journal_digest: [u8; 32],
+    /// The proven cycles, rounded up to millions, used to price the request.
+    pub mcycles: u64,
+}
+
+/// The journal and seal of a fulfilled request, as returned by the market.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fulfillment {
+    pub journal: Vec<u8>,
+    pub seal: Vec<u8>,
+    /// The image ID of the set builder guest, needed to verify set inclusion seals.
+    pub set_builder_image_id: Option<[u32; 8]>,
+}
+
+/// A market that proves a job and returns its fulfillment.
+pub trait ProofMarket {
+    fn fulfill(&self, job: &ProofJob<'_>) -> impl Future<Output = anyhow::Result<Fulfillment>>;
+}
+
+/// The Boundless market, configured from the command line or the environment.
+#[derive(clap::Args, Debug, Clone)]
+pub struct BoundlessMarket {
+    /// URL of the Ethereum RPC endpoint.
+    #[clap(short, long, env)]
+    rpc_url: Url,
+    /// Private key used to pay for the requests.
+    #[clap(short, long, env)]
+    wallet_private_key: PrivateKeySigner,
+    /// Submit the request offchain via the provided order stream service url.
+    #[clap(short, long, env, requires = "order_stream_url")]
+    offchain: bool,
+    /// Offchain order stream service URL to submit offchain requests to.
+    #[clap(long, env)]
+    order_stream_url: Option<Url>,
+    /// Storage provider to use
+    #[clap(flatten)]
+    storage_config: Option<StorageProviderConfig>,
+    /// Address of the RiscZeroSetVerifier contract.
+    #[clap(short, long, env)]
+    set_verifier_address: Address,
+    /// Address of the BoundlessfMarket contract.
+    #[clap(short, long, env)]
+    boundless_market_address: Address,
+}
+
+impl BoundlessMarket {
+    /// Reads the market configuration from the environment, e.g. a `.env` file.
+    pub fn from_env() -> anyhow::Result<Self> {
+        #[derive(clap::Parser)]
+        struct EnvArgs {
+            #[clap(flatten)]
+            market: BoundlessMarket,
+        }
+
+        match dotenvy::dotenv() {
+            Ok(_) => {}
+            Err(e) if e.not_found() => {}
+            Err(e) => anyhow::bail!("failed to load .env file: {}", e),
+        }
+        Ok(<EnvArgs as clap::Parser>::try_parse_from(["boundless"])?.market)
+    }
+}
+
+impl ProofMarket for BoundlessMarket {
+    async fn fulfill(&self, job: &ProofJob<'_>) -> anyhow::Result<Fulfillment> {
+        // Create a Boundless client from the provided parameters.
+        let client = ClientBuilder::new()
+            .with_rpc_url(self.rpc_url.clone())
+            .with_boundless_market_address(self.boundless_market_address)
+            .with_set_verifier_address(self.set_verifier_address)
+            .with_order_stream_url(
+                self.offchain
+                    .then(|| self.order_stream_url.clone())
+                    .flatten(),
+            )
+            .with_private_key(self.wallet_private_key.clone())
+            .with_storage_provider_config(self.storage_config.clone())
+            .await?
+            .build()
+            .await?;
+
+        ensure!(
+            client.storage_provider.is_some(),
+            "a storage provider is required to upload the zkVM guest ELF"
+        );
+        let image_url = client.upload_image(job.elf).await?;
+        tracing::info!("Uploaded image to {}", image_url);
+
+        // If the input exceeds 2 kB, upload the input and provide its URL instead, as a rule of thumb.
+        let input_url = client.upload_input(&job.input).await?;
+        tracing::info!("Uploaded input to {}", input_url);
+
+        let request = ProofRequest::builder()
+            .with_image_url(image_url.as_str())
+            .with_input(Input::url(input_url.as_str()))
+            .with_requirements(Requirements::new(
+                job.image_id,
+                Predicate::digest_match(job.journal_digest),
+            ))
+            .with_offer(
+                Offer::default()
+                    // The market uses a reverse Dutch auction mechanism to match requests with
+                    // provers. Each request has a price range that a prover can bid on. One way to
+                    // set the price is to choose a desired (min and max) price per million cycles
+                    // and multiply it by the number of cycles. Alternatively, you can use the
+                    // `with_min_price` and `with_max_price` methods to set the price directly.
+                    .with_min_price_per_mcycle(parse_ether("0.001")?, job.mcycles)
+                    // NOTE: If your offer is not being accepted, try increasing the max price.
+                    .with_max_price_per_mcycle(parse_ether("0.002")?, job.mcycles)
+                    // The timeout is the maximum number of blocks the request can stay
+                    // unfulfilled in the market before it expires. If a prover locks in
+                    // the request and does not fulfill it before the timeout, the prover can be
+                    // slashed.
+                    .with_timeout(1000),
+            )
+            .build()?;
+        tracing::debug!("Request: {:#?}", request);
+
+        // Send the request and wait for it to be completed.
+        let start_time = Instant::now();
+        let (request_id, expires_at) = client.submit_request(&request).await?;
+        tracing::info!("Request 0x{request_id:x} submitted");
+
+        // Wait for the request to be fulfilled by the market, returning the journal and seal.
+        tracing::info!("Waiting for 0x{request_id:x} to be fulfilled");
+        let (journal, seal) = client
+            .wait_for_request_fulfillment(request_id, Duration::from_secs(10), expires_at)
+            .await?;
+        tracing::info!(
+            "Request 0x{request_id:x} fulfilled in {:?}",
+            start_time.elapsed()
+        );
+
+        // The market aggregates proofs with the set builder guest configured in the set verifier.
+        let (set_builder_image_id, _) = client.set_verifier.image_info().await?;
+
+        Ok(Fulfillment {
+            journal: journal.to_vec(),
+            seal: seal.to_vec(),
+            set_builder_image_id: Some(Digest::from(set_builder_image_id.0).into()),
+        })
+    }
+}
+
+/// A local stand-in for the market, for tests. It executes the guest itself and returns a fake
+/// seal, encoded the way the on-chain verifiers encode fake receipts.
+pub struct MockMarket;
+
+impl ProofMarket for MockMarket {
+    async fn fulfill(&self, job: &ProofJob<'_>) -> anyhow::Result<Fulfillment> {
+        let env = ExecutorEnv::builder().write_slice(&job.input).build()?;
+        let journal = default_executor().execute(env, job.elf)?.journal;
+        ensure!(
+            <[u8; 32]>::from(journal.digest()) == job.journal_digest,
+            "the journal does not match the request"
+        );
+
+        let claim = ReceiptClaim::ok(job.image_id, journal.bytes.clone());
+        let mut seal = receipt::FAKE_SELECTOR.to_vec();
+        seal.extend_from_slice(claim.digest().as_bytes());
+        Ok(Fulfillment {
+            journal: journal.bytes,
+            seal,
+            set_builder_image_id: None,
+        })
+    }
+}
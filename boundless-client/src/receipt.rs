@@ -0,0 +1,147 @@
+use anyhow::{bail, ensure, Context};
+use risc0_aggregation::{RecursionVerifierParameters, SetInclusionReceiptVerifierParameters};
+use risc0_ethereum_contracts::receipt::{decode_seal, Receipt as ContractReceipt};
+use risc0_zkvm::{
+    default_prover, sha::Digestible, ExecutorEnv, FakeReceipt, Groth16ReceiptVerifierParameters,
+    InnerReceipt, ProverOpts, Receipt, ReceiptClaim, VerifierContext,
+};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+use crate::market::{Fulfillment, ProofJob};
+
+/// The selector of seals of fake receipts, which only verify in dev mode.
+pub const FAKE_SELECTOR: [u8; 4] = [0xFF; 4];
+
+/// The selector of Groth16 seals, the first bytes of the digest of the verifier parameters.
+pub fn groth16_selector() -> [u8; 4] {
+    Groth16ReceiptVerifierParameters::default()
+        .digest()
+        .as_bytes()[..4]
+        .try_into()
+        .unwrap()
+}
+
+/// A fulfilled proof whose seal is a set inclusion proof, i.e. a Merkle path from the claim to a
+/// root that the set builder guest proved. The root is proven with Groth16, which the recursion
+/// circuit cannot verify, so the proof cannot be used with `add_assumption` and has to be resolved
+/// into a succinct receipt with `resolve`. The input is kept for that.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SetInclusionProof {
+    pub image_id: [u32; 8],
+    #[serde(with = "hex::serde")]
+    pub journal: Vec<u8>,
+    #[serde(with = "hex::serde")]
+    pub seal: Vec<u8>,
+    #[serde(with = "hex::serde")]
+    pub input: Vec<u8>,
+    /// The image ID of the set builder guest that proved the root, from the set verifier contract.
+    pub set_builder_image_id: [u32; 8],
+}
+
+impl SetInclusionProof {
+    /// Verifies the Merkle path and the proof of its root. Fails if the seal does not include the
+    /// root proof, e.g. when the root was already verified on chain by another request.
+    pub fn verify(&self) -> anyhow::Result<()> {
+        match decode_seal(
+            self.seal.clone().into(),
+            self.image_id,
+            self.journal.clone(),
+        )
+        .context("malformed seal")?
+        {
+            ContractReceipt::SetInclusion(receipt) => receipt
+                .verify_integrity_with_context(
+                    &VerifierContext::default(),
+                    SetInclusionReceiptVerifierParameters {
+                        image_id: self.set_builder_image_id.into(),
+                    },
+                    Some(RecursionVerifierParameters::default()),
+                )
+                .context("invalid set inclusion proof"),
+            ContractReceipt::Base(_) => bail!("the seal is not a set inclusion proof"),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// A proof saved by the client: a receipt in the format `host` saves, or a set inclusion proof to
+/// resolve into one.
+pub enum SavedProof {
+    Receipt(Box<Receipt>),
+    SetInclusion(SetInclusionProof),
+}
+
+impl SavedProof {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let bytes = fs::read(path).with_context(|| format!("failed to read {:?}", path))?;
+        // Set inclusion proofs are saved as JSON, receipts with borsh.
+        if bytes.first() == Some(&b'{') {
+            Ok(SavedProof::SetInclusion(serde_json::from_slice(&bytes)?))
+        } else {
+            Ok(SavedProof::Receipt(
+                borsh::from_slice(&bytes).context("neither a receipt nor a set inclusion proof")?,
+            ))
+        }
+    }
+}
+
+/// Converts a fulfillment of `job` into a receipt in the format `host` saves, or a set inclusion
+/// proof if the market aggregated it. The seal is the selector of its verifier followed by the
+/// proof. Groth16 seals are rejected: the recursion circuit cannot verify them, so they could not
+/// be used as the assumption of the next proof.
+pub fn decode_fulfillment(
+    job: &ProofJob<'_>,
+    fulfillment: Fulfillment,
+) -> anyhow::Result<SavedProof> {
+    ensure!(fulfillment.seal.len() >= 4, "the seal has no selector");
+    let (selector, proof) = fulfillment.seal.split_at(4);
+    let claim = ReceiptClaim::ok(job.image_id, fulfillment.journal.clone());
+
+    let inner = if selector == FAKE_SELECTOR {
+        ensure!(
+            proof == claim.digest().as_bytes(),
+            "the fake seal is not for the journal"
+        );
+        InnerReceipt::Fake(FakeReceipt::new(claim))
+    } else if selector == groth16_selector() {
+        bail!("the market returned a Groth16 proof, which cannot be used as an assumption");
+    } else {
+        let proof = SetInclusionProof {
+            image_id: job.image_id,
+            journal: fulfillment.journal,
+            seal: fulfillment.seal,
+            input: job.input.clone(),
+            set_builder_image_id: fulfillment
+                .set_builder_image_id
+                .context("the market did not return the set builder image ID")?,
+        };
+        proof.verify()?;
+        return Ok(SavedProof::SetInclusion(proof));
+    };
+    Ok(SavedProof::Receipt(Box::new(Receipt::new(
+        inner,
+        fulfillment.journal,
+    ))))
+}
+
+/// Resolves a set inclusion proof into a succinct receipt that can be used with `add_assumption`,
+/// by proving its input again with the default prover. The market's proof is verified first, and
+/// the new receipt must have the same journal.
+pub fn resolve(proof: &SetInclusionProof, elf: &[u8]) -> anyhow::Result<Receipt> {
+    proof.verify()?;
+    let env = ExecutorEnv::builder().write_slice(&proof.input).build()?;
+    let receipt = default_prover()
+        .prove_with_opts(env, elf, &ProverOpts::succinct())?
+        .receipt;
+    ensure!(
+        receipt.journal.bytes == proof.journal,
+        "the resolved journal does not match the market's proof"
+    );
+    receipt.verify(proof.image_id)?;
+    Ok(receipt)
+}
@@ -0,0 +1,84 @@
+//! Helpers shared by the build scripts, which name the reproducibly built ELFs of a network.
+
+use sha2::{Digest, Sha256};
+use std::env;
+
+/// The challenge of the public signet, which custom signets default to.
+pub const PUBLIC_SIGNET_CHALLENGE: &str =
+    "512103ad5e0edad18cb1f0fc0d28a3d4f1f3e445640337489abb10404f2d1e086be430210359ef5021964fe22d\
+     6f8e05b2463c9540ce96883fe3b278760f048f5189f2e6c452ae";
+
+/// The pow limit of the public signet, which custom signets default to.
+pub const PUBLIC_SIGNET_POW_LIMIT: &str = "1e0377ae";
+
+/// Reruns the build script when the network it builds for changes.
+pub fn rerun_if_network_changed() {
+    println!("cargo:rerun-if-env-changed=BITCOIN_NETWORK");
+    println!("cargo:rerun-if-env-changed=BITCOIN_SIGNET_CHALLENGE");
+    println!("cargo:rerun-if-env-changed=BITCOIN_SIGNET_POW_LIMIT");
+}
+
+/// The challenge and pow limit of a custom signet, in hex, if either is set.
+pub fn custom_signet(network: &str) -> Option<(String, String)> {
+    let challenge = env::var("BITCOIN_SIGNET_CHALLENGE").ok();
+    let pow_limit = env::var("BITCOIN_SIGNET_POW_LIMIT").ok();
+    if network != "signet" || (challenge.is_none() && pow_limit.is_none()) {
+        return None;
+    }
+    let normalize = |value: Option<String>, default: &str| {
+        value
+            .as_deref()
+            .unwrap_or(default)
+            .trim_start_matches("0x")
+            .to_lowercase()
+    };
+    Some((
+        normalize(challenge, PUBLIC_SIGNET_CHALLENGE),
+        normalize(pow_limit, PUBLIC_SIGNET_POW_LIMIT),
+    ))
+}
+
+/// The prefix of the ELF and image ID files of the network. A custom signet is named after its
+/// magic, e.g. `signet-54d26fbd`, so that it does not overwrite the public signet files.
+pub fn elf_name(network: &str) -> String {
+    match custom_signet(network) {
+        Some((challenge, _)) => format!("signet-{}", signet_magic(&challenge)),
+        None => network.to_lowercase(),
+    }
+}
+
+/// The first four bytes of the double SHA256 of the serialized challenge, like Bitcoin Core.
+pub fn signet_magic(challenge: &str) -> String {
+    let challenge: Vec<u8> = (0..challenge.len())
+        .step_by(2)
+        .map(|i| {
+            challenge
+                .get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .expect("BITCOIN_SIGNET_CHALLENGE is not hex")
+        })
+        .collect();
+    let mut serialized = if challenge.len() < 0xfd {
+        vec![challenge.len() as u8]
+    } else {
+        let mut prefix = vec![0xfd];
+        prefix.extend((challenge.len() as u16).to_le_bytes());
+        prefix
+    };
+    serialized.extend(challenge);
+    Sha256::digest(Sha256::digest(&serialized))[..4]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signet_magic() {
+        // The P2P magic of the public signet.
+        assert_eq!(signet_magic(PUBLIC_SIGNET_CHALLENGE), "0a03cf40");
+    }
+}
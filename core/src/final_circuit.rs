@@ -1,10 +1,254 @@
 use borsh::{BorshDeserialize, BorshSerialize};
+use crypto_bigint::{Encoding, U256};
+use serde::{Deserialize, Serialize};
 
-use crate::{header_chain::BlockHeaderCircuitOutput, spv::SPV};
+use crate::{
+    header_chain::{BlockHeaderCircuitOutput, NETWORK_CONSTANTS},
+    serde_utils,
+    spv::SPV,
+};
 
-#[derive(Eq, PartialEq, Clone, Debug, BorshDeserialize, BorshSerialize)]
+/// The current version of `FinalCircuitOutput`.
+pub const FINAL_CIRCUIT_OUTPUT_VERSION: u8 = 1;
 
+#[derive(Serialize, Deserialize, Eq, PartialEq, Clone, Debug, BorshDeserialize, BorshSerialize)]
 pub struct FinalCircuitInput {
     pub block_header_circuit_output: BlockHeaderCircuitOutput,
     pub spv: SPV,
+    /// The minimum total work (big-endian) of the header chain, checked by the circuit and
+    /// committed in the output. All zeros disables the check.
+    #[serde(with = "serde_utils::work")]
+    pub min_total_work: [u8; 32],
+}
+
+/// The statement proven by the final circuit. The Groth16 circuit expects a 32-byte journal, so
+/// only `journal_digest` is committed and the output itself is shared alongside the proof.
+#[derive(Serialize, Deserialize, Eq, PartialEq, Clone, Debug, BorshDeserialize, BorshSerialize)]
+pub struct FinalCircuitOutput {
+    pub version: u8,
+    /// The P2P magic of the network the header chain was verified for.
+    pub network_magic: [u8; 4],
+    #[serde(with = "serde_utils::display_hash")]
+    pub genesis_block_hash: [u8; 32],
+    /// The method ID of the verified header chain circuit.
+    pub header_chain_method_id: [u32; 8],
+    #[serde(with = "serde_utils::display_hash")]
+    pub best_block_hash: [u8; 32],
+    pub block_height: u32,
+    #[serde(with = "serde_utils::work")]
+    pub total_work: [u8; 32],
+    /// The minimum total work the circuit checked `total_work` against.
+    #[serde(with = "serde_utils::work")]
+    pub min_total_work: [u8; 32],
+    #[serde(with = "serde_utils::display_hash")]
+    pub txid: [u8; 32],
+    /// The hash and height of the block containing the transaction.
+    #[serde(with = "serde_utils::display_hash")]
+    pub tx_block_hash: [u8; 32],
+    pub tx_block_height: u32,
+}
+
+/// Errors returned by `FinalCircuitOutput::decode`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FinalCircuitOutputError {
+    Malformed(String),
+    UnsupportedVersion(u8),
+    /// The output does not hash to the journal of the final circuit.
+    JournalMismatch,
+    /// The minimum total work checked by the circuit is below the policy of the verifier.
+    InsufficientMinTotalWork {
+        committed: [u8; 32],
+        required: [u8; 32],
+    },
+}
+
+impl core::fmt::Display for FinalCircuitOutputError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            FinalCircuitOutputError::Malformed(e) => {
+                write!(f, "malformed final circuit output: {}", e)
+            }
+            FinalCircuitOutputError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported final circuit output version {} (expected {})",
+                version, FINAL_CIRCUIT_OUTPUT_VERSION
+            ),
+            FinalCircuitOutputError::JournalMismatch => {
+                write!(f, "final circuit output does not match the journal")
+            }
+            FinalCircuitOutputError::InsufficientMinTotalWork {
+                committed,
+                required,
+            } => write!(
+                f,
+                "the circuit checked a minimum total work of 0x{:x}, but 0x{:x} is required",
+                U256::from_be_bytes(*committed),
+                U256::from_be_bytes(*required)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FinalCircuitOutputError {}
+
+impl FinalCircuitOutput {
+    /// Builds the output of the final circuit for an input whose SPV proof is for the block at
+    /// `tx_block_height`.
+    pub fn new(
+        input: &FinalCircuitInput,
+        header_chain_method_id: [u32; 8],
+        tx_block_height: u32,
+    ) -> Self {
+        let chain_state = &input.block_header_circuit_output.chain_state;
+        FinalCircuitOutput {
+            version: FINAL_CIRCUIT_OUTPUT_VERSION,
+            network_magic: NETWORK_CONSTANTS.magic,
+            genesis_block_hash: NETWORK_CONSTANTS.genesis_block_hash,
+            header_chain_method_id,
+            best_block_hash: chain_state.best_block_hash,
+            block_height: chain_state.block_height,
+            total_work: chain_state.total_work,
+            min_total_work: input.min_total_work,
+            txid: input.spv.transaction.txid(),
+            tx_block_hash: input.spv.block_header.compute_block_hash(),
+            tx_block_height,
+        }
+    }
+
+    /// The Blake3 digest of the borsh encoding, which is the journal of the final circuit.
+    pub fn journal_digest(&self) -> [u8; 32] {
+        let bytes = borsh::to_vec(self).expect("Serialization to vec is infallible");
+        blake3::hash(&bytes).into()
+    }
+
+    /// Decodes a borsh encoded output and checks it against the journal of a final circuit proof.
+    pub fn decode(bytes: &[u8], journal: &[u8]) -> Result<Self, FinalCircuitOutputError> {
+        let output = FinalCircuitOutput::try_from_slice(bytes)
+            .map_err(|e| FinalCircuitOutputError::Malformed(e.to_string()))?;
+        if output.version != FINAL_CIRCUIT_OUTPUT_VERSION {
+            return Err(FinalCircuitOutputError::UnsupportedVersion(output.version));
+        }
+        if output.journal_digest().as_slice() != journal {
+            return Err(FinalCircuitOutputError::JournalMismatch);
+        }
+        Ok(output)
+    }
+
+    /// Checks that the circuit enforced at least the given minimum total work (big-endian), e.g.
+    /// the recommended `NetworkConstants::min_total_work` of the network.
+    pub fn check_min_total_work(
+        &self,
+        min_total_work: &[u8; 32],
+    ) -> Result<(), FinalCircuitOutputError> {
+        // Fixed-size big-endian byte arrays compare like the numbers they encode.
+        if self.min_total_work < *min_total_work {
+            return Err(FinalCircuitOutputError::InsufficientMinTotalWork {
+                committed: self.min_total_work,
+                required: *min_total_work,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{constants::genesis_block, hashes::Hash, Network};
+
+    use super::*;
+    use crate::header_chain::NETWORK_TYPE;
+
+    fn test_output() -> FinalCircuitOutput {
+        FinalCircuitOutput {
+            version: FINAL_CIRCUIT_OUTPUT_VERSION,
+            network_magic: NETWORK_CONSTANTS.magic,
+            genesis_block_hash: NETWORK_CONSTANTS.genesis_block_hash,
+            header_chain_method_id: [1, 2, 3, 4, 5, 6, 7, 8],
+            best_block_hash: [2; 32],
+            block_height: 100,
+            total_work: [3; 32],
+            min_total_work: [
+                0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+                0, 0, 0, 0,
+            ],
+            txid: [4; 32],
+            tx_block_hash: [5; 32],
+            tx_block_height: 42,
+        }
+    }
+
+    #[test]
+    fn test_network_constants_match_bitcoin() {
+        let network = match NETWORK_TYPE {
+            "mainnet" => Network::Bitcoin,
+            "testnet4" => Network::Testnet4,
+            "testnet3" => Network::Testnet,
+            "signet" => Network::Signet,
+            "regtest" => Network::Regtest,
+            _ => unreachable!(),
+        };
+        assert_eq!(NETWORK_CONSTANTS.magic, network.magic().to_bytes());
+        assert_eq!(
+            NETWORK_CONSTANTS.genesis_block_hash,
+            genesis_block(network).block_hash().to_byte_array()
+        );
+    }
+
+    #[test]
+    fn test_final_circuit_output_decode() {
+        let output = test_output();
+        let bytes = borsh::to_vec(&output).unwrap();
+        let journal = output.journal_digest();
+        assert_eq!(
+            FinalCircuitOutput::decode(&bytes, &journal),
+            Ok(output.clone())
+        );
+
+        let mut tampered = output.clone();
+        tampered.tx_block_height += 1;
+        assert_eq!(
+            FinalCircuitOutput::decode(&borsh::to_vec(&tampered).unwrap(), &journal),
+            Err(FinalCircuitOutputError::JournalMismatch)
+        );
+
+        let mut future = output;
+        future.version += 1;
+        assert_eq!(
+            FinalCircuitOutput::decode(&borsh::to_vec(&future).unwrap(), &future.journal_digest()),
+            Err(FinalCircuitOutputError::UnsupportedVersion(future.version))
+        );
+
+        assert!(matches!(
+            FinalCircuitOutput::decode(&bytes[..bytes.len() - 1], &journal),
+            Err(FinalCircuitOutputError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn test_check_min_total_work() {
+        let output = test_output();
+        output.check_min_total_work(&[0; 32]).unwrap();
+        output.check_min_total_work(&output.min_total_work).unwrap();
+
+        let mut required = [0; 32];
+        required[1] = 1;
+        let err = output.check_min_total_work(&required).unwrap_err();
+        assert_eq!(
+            err,
+            FinalCircuitOutputError::InsufficientMinTotalWork {
+                committed: output.min_total_work,
+                required,
+            }
+        );
+        assert!(err
+            .to_string()
+            .contains("0x0000010000000000000000000000000000000000000000000000000000000000"));
+
+        // A larger least significant byte does not make up for a smaller most significant one.
+        let mut required = [0xff; 32];
+        required[0] = 0;
+        required[1] = 0;
+        required[2] = 0;
+        output.check_min_total_work(&required).unwrap();
+    }
 }
@@ -4,16 +4,19 @@
 /// This module contains the implementation of the header chain circuit, which is basically
 /// the Bitcoin header chain verification logic.
 /// WARNING: This implementation is not a word-to-word translation of the Bitcoin Core source code.
-use crate::mmr_guest::MMRGuest;
+use crate::{mmr_guest::MMRGuest, serde_utils, utils::calculate_double_sha256};
 use bitcoin::{
     block::{Header, Version},
+    consensus,
     hashes::Hash,
+    hex::FromHex,
     BlockHash, CompactTarget, TxMerkleNode,
 };
 use borsh::{BorshDeserialize, BorshSerialize};
 use crypto_bigint::{Encoding, U256};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use std::sync::LazyLock;
 
 /// Network configuration holder for Bitcoin-specific constants
 #[derive(Debug)]
@@ -21,12 +24,304 @@ pub struct NetworkConstants {
     pub max_bits: u32,
     pub max_target: U256,
     pub max_target_bytes: [u8; 32],
+    /// The P2P message start bytes, identifying the network.
+    pub magic: [u8; 4],
+    /// The genesis block hash in internal byte order.
+    pub genesis_block_hash: [u8; 32],
+    /// The genesis block header. The first header applied to a new `ChainState` must be this one.
+    pub genesis_block_header: CircuitBlockHeader,
+    /// Blocks may use the minimum difficulty if they are 20 minutes later than the previous one.
+    pub pow_allow_min_difficulty_blocks: bool,
+    /// The difficulty is never adjusted.
+    pub pow_no_retargeting: bool,
+    /// BIP94: retargets scale the target of the epoch instead of the bits of its last block, which
+    /// may be the minimum difficulty, and the first block of an epoch may be at most 10 minutes
+    /// earlier than the previous block.
+    pub enforce_bip94: bool,
+    /// The height from which blocks must have at least version 2 (BIP34).
+    pub bip34_height: u32,
+    /// The height from which blocks must have at least version 3 (BIP66).
+    pub bip66_height: u32,
+    /// The height from which blocks must have at least version 4 (BIP65).
+    pub bip65_height: u32,
+    /// The recommended minimum total work of a header chain before its outputs are accepted,
+    /// similar to Bitcoin Core's `nMinimumChainWork`.
+    pub min_total_work: U256,
 }
 
+/// The merkle root of the genesis block of every network except testnet4.
+const GENESIS_MERKLE_ROOT: [u8; 32] = [
+    59, 163, 237, 253, 122, 123, 18, 178, 122, 199, 44, 62, 103, 118, 143, 97, 127, 200, 27, 195,
+    136, 138, 81, 50, 58, 159, 184, 170, 75, 30, 94, 74,
+];
+
+pub const MAINNET_CONSTANTS: NetworkConstants = NetworkConstants {
+    max_bits: 0x1D00FFFF,
+    max_target: U256::from_be_hex(
+        "00000000FFFF0000000000000000000000000000000000000000000000000000",
+    ),
+    max_target_bytes: [
+        0, 0, 0, 0, 255, 255, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0,
+    ],
+    magic: [249, 190, 180, 217],
+    genesis_block_hash: [
+        111, 226, 140, 10, 182, 241, 179, 114, 193, 166, 162, 70, 174, 99, 247, 79, 147, 30, 131,
+        101, 225, 90, 8, 156, 104, 214, 25, 0, 0, 0, 0, 0,
+    ],
+    genesis_block_header: CircuitBlockHeader {
+        version: 1,
+        prev_block_hash: [0; 32],
+        merkle_root: GENESIS_MERKLE_ROOT,
+        time: 1231006505,
+        bits: 0x1D00FFFF,
+        nonce: 2083236893,
+    },
+    pow_allow_min_difficulty_blocks: false,
+    pow_no_retargeting: false,
+    enforce_bip94: false,
+    bip34_height: 227931,
+    bip66_height: 363725,
+    bip65_height: 388381,
+    // Bitcoin Core 28.0's `nMinimumChainWork` (block 856760).
+    min_total_work: U256::from_be_hex(
+        "000000000000000000000000000000000000000088e186b70e0862c193ec44d6",
+    ),
+};
+
+pub const TESTNET4_CONSTANTS: NetworkConstants = NetworkConstants {
+    max_bits: 0x1D00FFFF,
+    max_target: U256::from_be_hex(
+        "00000000FFFF0000000000000000000000000000000000000000000000000000",
+    ),
+    max_target_bytes: [
+        0, 0, 0, 0, 255, 255, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0,
+    ],
+    magic: [28, 22, 63, 40],
+    genesis_block_hash: [
+        67, 240, 139, 218, 176, 80, 227, 91, 86, 124, 134, 75, 145, 244, 127, 80, 174, 114, 90,
+        226, 222, 83, 188, 251, 186, 242, 132, 218, 0, 0, 0, 0,
+    ],
+    genesis_block_header: CircuitBlockHeader {
+        version: 1,
+        prev_block_hash: [0; 32],
+        merkle_root: [
+            78, 123, 43, 145, 40, 254, 2, 145, 219, 6, 147, 175, 42, 228, 24, 183, 103, 230, 87,
+            205, 64, 126, 128, 203, 20, 52, 34, 30, 174, 167, 160, 122,
+        ],
+        time: 1714777860,
+        bits: 0x1D00FFFF,
+        nonce: 393743547,
+    },
+    pow_allow_min_difficulty_blocks: true,
+    pow_no_retargeting: false,
+    enforce_bip94: true,
+    bip34_height: 1,
+    bip66_height: 1,
+    bip65_height: 1,
+    // Test networks are cheap to rewrite, so there is no meaningful default; the verifier should
+    // pick the work of a recent block it trusts.
+    min_total_work: U256::ZERO,
+};
+
+pub const TESTNET3_CONSTANTS: NetworkConstants = NetworkConstants {
+    max_bits: 0x1D00FFFF,
+    max_target: U256::from_be_hex(
+        "00000000FFFF0000000000000000000000000000000000000000000000000000",
+    ),
+    max_target_bytes: [
+        0, 0, 0, 0, 255, 255, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0,
+    ],
+    magic: [11, 17, 9, 7],
+    genesis_block_hash: [
+        67, 73, 127, 215, 248, 38, 149, 113, 8, 244, 163, 15, 217, 206, 195, 174, 186, 121, 151,
+        32, 132, 233, 14, 173, 1, 234, 51, 9, 0, 0, 0, 0,
+    ],
+    genesis_block_header: CircuitBlockHeader {
+        version: 1,
+        prev_block_hash: [0; 32],
+        merkle_root: GENESIS_MERKLE_ROOT,
+        time: 1296688602,
+        bits: 0x1D00FFFF,
+        nonce: 414098458,
+    },
+    pow_allow_min_difficulty_blocks: true,
+    pow_no_retargeting: false,
+    // Without BIP94, an epoch whose last block uses the minimum difficulty retargets from it, which
+    // resets the difficulty and causes the block storms of testnet3.
+    enforce_bip94: false,
+    bip34_height: 21111,
+    bip66_height: 330776,
+    bip65_height: 581885,
+    min_total_work: U256::ZERO,
+};
+
+pub const SIGNET_CONSTANTS: NetworkConstants = NetworkConstants {
+    max_bits: 0x1E0377AE,
+    max_target: U256::from_be_hex(
+        "00000377AE000000000000000000000000000000000000000000000000000000",
+    ),
+    max_target_bytes: [
+        0, 0, 3, 119, 174, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0,
+    ],
+    magic: [10, 3, 207, 64],
+    genesis_block_hash: [
+        246, 30, 238, 59, 99, 163, 128, 164, 119, 160, 99, 175, 50, 178, 187, 201, 124, 159, 249,
+        240, 31, 44, 66, 37, 233, 115, 152, 129, 8, 0, 0, 0,
+    ],
+    genesis_block_header: CircuitBlockHeader {
+        version: 1,
+        prev_block_hash: [0; 32],
+        merkle_root: GENESIS_MERKLE_ROOT,
+        time: 1598918400,
+        bits: 0x1E0377AE,
+        nonce: 52613770,
+    },
+    pow_allow_min_difficulty_blocks: false,
+    pow_no_retargeting: false,
+    enforce_bip94: false,
+    bip34_height: 1,
+    bip66_height: 1,
+    bip65_height: 1,
+    min_total_work: U256::ZERO,
+};
+
+/// The challenge script of the public signet, which its blocks must be signed for.
+pub const DEFAULT_SIGNET_CHALLENGE: [u8; 71] = [
+    81, 33, 3, 173, 94, 14, 218, 209, 140, 177, 240, 252, 13, 40, 163, 212, 241, 243, 228, 69, 100,
+    3, 55, 72, 154, 187, 16, 64, 79, 45, 30, 8, 107, 228, 48, 33, 3, 89, 239, 80, 33, 150, 79, 226,
+    45, 111, 142, 5, 178, 70, 60, 149, 64, 206, 150, 136, 63, 227, 178, 120, 118, 15, 4, 143, 81,
+    137, 242, 230, 196, 82, 174,
+];
+
+/// Why the parameters of a custom signet are unusable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignetParamsError {
+    /// The challenge is not a hex script.
+    ChallengeNotHex,
+    /// The pow limit is not hex compact bits.
+    PowLimitNotHex,
+    /// The pow limit is not valid compact bits.
+    PowLimit(CompactTargetError),
+    /// The pow limit is below the target of the genesis block, which the first epoch keeps, so no
+    /// block could follow it.
+    PowLimitBelowGenesis,
+}
+
+impl core::fmt::Display for SignetParamsError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            SignetParamsError::ChallengeNotHex => f.write_str("the challenge is not hex"),
+            SignetParamsError::PowLimitNotHex => {
+                f.write_str("the pow limit is not hex compact bits")
+            }
+            SignetParamsError::PowLimit(e) => write!(f, "invalid pow limit: {}", e),
+            SignetParamsError::PowLimitBelowGenesis => {
+                f.write_str("the pow limit is below the target of the genesis block")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SignetParamsError {}
+
+/// The constants of a signet with the given challenge script and pow limit (as compact bits). As in
+/// Bitcoin Core, the magic is the first 4 bytes of the double SHA256 of the serialized challenge,
+/// and every signet shares the genesis block of the public one.
+pub fn signet_constants(
+    challenge: &[u8],
+    max_bits: u32,
+) -> Result<NetworkConstants, SignetParamsError> {
+    let max_target_bytes = checked_bits_to_target(max_bits).map_err(SignetParamsError::PowLimit)?;
+    if max_target_bytes < bits_to_target(SIGNET_CONSTANTS.genesis_block_header.bits) {
+        return Err(SignetParamsError::PowLimitBelowGenesis);
+    }
+    let challenge_hash = calculate_double_sha256(&consensus::serialize(&challenge.to_vec()));
+    Ok(NetworkConstants {
+        // The bits the retarget clamps to, which may be shorter than the given ones.
+        max_bits: target_to_bits(&max_target_bytes),
+        max_target: U256::from_be_bytes(max_target_bytes),
+        max_target_bytes,
+        magic: challenge_hash[..4].try_into().unwrap(),
+        ..SIGNET_CONSTANTS
+    })
+}
+
+/// The constants of a signet with the given challenge script and pow limit (compact bits) in
+/// hex, the way `BITCOIN_SIGNET_CHALLENGE` and `BITCOIN_SIGNET_POW_LIMIT` are given, each
+/// defaulting to the one of the public signet.
+pub fn signet_constants_from_hex(
+    challenge: Option<&str>,
+    pow_limit: Option<&str>,
+) -> Result<NetworkConstants, SignetParamsError> {
+    let challenge = challenge
+        .map(|challenge| {
+            Vec::from_hex(challenge.trim_start_matches("0x"))
+                .map_err(|_| SignetParamsError::ChallengeNotHex)
+        })
+        .transpose()?;
+    let max_bits = pow_limit
+        .map(|bits| {
+            u32::from_str_radix(bits.trim_start_matches("0x"), 16)
+                .map_err(|_| SignetParamsError::PowLimitNotHex)
+        })
+        .transpose()?;
+    signet_constants(
+        challenge.as_deref().unwrap_or(&DEFAULT_SIGNET_CHALLENGE),
+        max_bits.unwrap_or(SIGNET_CONSTANTS.max_bits),
+    )
+}
+
+/// The signet selected with `BITCOIN_SIGNET_CHALLENGE` and `BITCOIN_SIGNET_POW_LIMIT` at compile
+/// time, by default the public signet.
+fn compiled_signet_constants() -> NetworkConstants {
+    signet_constants_from_hex(
+        option_env!("BITCOIN_SIGNET_CHALLENGE"),
+        option_env!("BITCOIN_SIGNET_POW_LIMIT"),
+    )
+    .unwrap_or_else(|e| panic!("Invalid custom signet: {}", e))
+}
+
+pub const REGTEST_CONSTANTS: NetworkConstants = NetworkConstants {
+    max_bits: 0x207FFFFF,
+    max_target: U256::from_be_hex(
+        "7FFFFF0000000000000000000000000000000000000000000000000000000000",
+    ),
+    max_target_bytes: [
+        127, 255, 255, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0,
+    ],
+    magic: [250, 191, 181, 218],
+    genesis_block_hash: [
+        6, 34, 110, 70, 17, 26, 11, 89, 202, 175, 18, 96, 67, 235, 91, 191, 40, 195, 79, 58, 94,
+        51, 42, 31, 199, 178, 183, 60, 241, 136, 145, 15,
+    ],
+    genesis_block_header: CircuitBlockHeader {
+        version: 1,
+        prev_block_hash: [0; 32],
+        merkle_root: GENESIS_MERKLE_ROOT,
+        time: 1296688602,
+        bits: 0x207FFFFF,
+        nonce: 2,
+    },
+    pow_allow_min_difficulty_blocks: false,
+    pow_no_retargeting: true,
+    enforce_bip94: false,
+    // Always active on regtest, as in Bitcoin Core. The `bitcoin` crate has older values.
+    bip34_height: 1,
+    bip66_height: 1,
+    bip65_height: 1,
+    min_total_work: U256::ZERO,
+};
+
 pub const NETWORK_TYPE: &str = {
     match option_env!("BITCOIN_NETWORK") {
         Some(network) if matches!(network.as_bytes(), b"mainnet") => "mainnet",
         Some(network) if matches!(network.as_bytes(), b"testnet4") => "testnet4",
+        Some(network) if matches!(network.as_bytes(), b"testnet3") => "testnet3",
         Some(network) if matches!(network.as_bytes(), b"signet") => "signet",
         Some(network) if matches!(network.as_bytes(), b"regtest") => "regtest",
         None => "mainnet",
@@ -34,83 +329,68 @@ pub const NETWORK_TYPE: &str = {
     }
 };
 
-// Const evaluation of network type from environment
-const IS_REGTEST: bool = matches!(NETWORK_TYPE.as_bytes(), b"regtest");
-const IS_TESTNET4: bool = matches!(NETWORK_TYPE.as_bytes(), b"testnet4");
-const MINIMUM_WORK_TESTNET: U256 =
-    U256::from_be_hex("0000000000000000000000000000000000000000000000000000000100010001");
+/// The constants of the network selected with `BITCOIN_NETWORK` at compile time. On signet, the
+/// challenge and pow limit can be set at compile time too, see `compiled_signet_constants`.
+pub static NETWORK_CONSTANTS: LazyLock<NetworkConstants> = LazyLock::new(|| match NETWORK_TYPE {
+    "signet" => compiled_signet_constants(),
+    "regtest" => REGTEST_CONSTANTS,
+    "testnet4" => TESTNET4_CONSTANTS,
+    "testnet3" => TESTNET3_CONSTANTS,
+    _ => MAINNET_CONSTANTS,
+});
+
+/// The networks with constants, by name.
+pub static NETWORKS: [(&str, NetworkConstants); 5] = [
+    ("mainnet", MAINNET_CONSTANTS),
+    ("testnet4", TESTNET4_CONSTANTS),
+    ("testnet3", TESTNET3_CONSTANTS),
+    ("signet", SIGNET_CONSTANTS),
+    ("regtest", REGTEST_CONSTANTS),
+];
+
+/// The constants of the network with the given name, e.g. `"testnet4"`.
+pub fn network_constants(name: &str) -> Option<&'static NetworkConstants> {
+    // The network compiled for may be a custom signet.
+    if name == NETWORK_TYPE {
+        return Some(&*NETWORK_CONSTANTS);
+    }
+    NETWORKS
+        .iter()
+        .find(|(network, _)| *network == name)
+        .map(|(_, constants)| constants)
+}
 
-pub const NETWORK_CONSTANTS: NetworkConstants = {
-    match option_env!("BITCOIN_NETWORK") {
-        Some(n) if matches!(n.as_bytes(), b"signet") => NetworkConstants {
-            max_bits: 0x1E0377AE,
-            max_target: U256::from_be_hex(
-                "00000377AE000000000000000000000000000000000000000000000000000000",
-            ),
-            max_target_bytes: [
-                0, 0, 3, 119, 174, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0,
-            ],
-        },
-        Some(n) if matches!(n.as_bytes(), b"regtest") => NetworkConstants {
-            max_bits: 0x207FFFFF,
-            max_target: U256::from_be_hex(
-                "7FFFFF0000000000000000000000000000000000000000000000000000000000",
-            ),
-            max_target_bytes: [
-                127, 255, 255, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0,
-            ],
-        },
-        Some(n) if matches!(n.as_bytes(), b"testnet4") => NetworkConstants {
-            max_bits: 0x1D00FFFF,
-            max_target: U256::from_be_hex(
-                "00000000FFFF0000000000000000000000000000000000000000000000000000",
-            ),
-            max_target_bytes: [
-                0, 0, 0, 0, 255, 255, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0,
-            ],
-        },
-        Some(n) if matches!(n.as_bytes(), b"mainnet") => NetworkConstants {
-            max_bits: 0x1D00FFFF,
-            max_target: U256::from_be_hex(
-                "00000000FFFF0000000000000000000000000000000000000000000000000000",
-            ),
-            max_target_bytes: [
-                0, 0, 0, 0, 255, 255, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0,
-            ],
-        },
-        // Default to mainnet for None
-        None => NetworkConstants {
-            max_bits: 0x1D00FFFF,
-            max_target: U256::from_be_hex(
-                "00000000FFFF0000000000000000000000000000000000000000000000000000",
-            ),
-            max_target_bytes: [
-                0, 0, 0, 0, 255, 255, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0,
-            ],
-        },
-        _ => panic!("Unsupported network"),
+/// The name of the network with the given P2P magic.
+pub fn network_name(magic: [u8; 4]) -> Option<&'static str> {
+    if magic == NETWORK_CONSTANTS.magic {
+        return Some(NETWORK_TYPE);
     }
-};
+    NETWORKS
+        .iter()
+        .find(|(_, constants)| constants.magic == magic)
+        .map(|(network, _)| *network)
+}
 
 /// An epoch should be two weeks (represented as number of seconds)
 /// seconds/minute * minutes/hour * hours/day * 14 days
-const EXPECTED_EPOCH_TIMESPAN: u32 = 60 * 60 * 24 * 14;
+pub(crate) const EXPECTED_EPOCH_TIMESPAN: u32 = 60 * 60 * 24 * 14;
 
 /// Number of blocks per epoch
-const BLOCKS_PER_EPOCH: u32 = 2016;
+pub(crate) const BLOCKS_PER_EPOCH: u32 = 2016;
+
+/// How much earlier than the previous block the first block of an epoch may be under BIP94.
+const MAX_TIMEWARP: u32 = 600;
 
 /// Bitcoin block header.
 #[derive(Serialize, Deserialize, Eq, PartialEq, Clone, Debug, BorshDeserialize, BorshSerialize)]
 pub struct CircuitBlockHeader {
     pub version: i32,
+    #[serde(with = "serde_utils::display_hash")]
     pub prev_block_hash: [u8; 32],
+    #[serde(with = "serde_utils::display_hash")]
     pub merkle_root: [u8; 32],
     pub time: u32,
+    #[serde(with = "serde_utils::bits")]
     pub bits: u32,
     pub nonce: u32,
 }
@@ -166,8 +446,11 @@ impl Into<Header> for CircuitBlockHeader {
 #[derive(Serialize, Deserialize, Eq, PartialEq, Clone, Debug, BorshDeserialize, BorshSerialize)]
 pub struct ChainState {
     pub block_height: u32,
+    #[serde(with = "serde_utils::work")]
     pub total_work: [u8; 32],
+    #[serde(with = "serde_utils::display_hash")]
     pub best_block_hash: [u8; 32],
+    #[serde(with = "serde_utils::bits")]
     pub current_target_bits: u32,
     pub epoch_start_time: u32,
     pub prev_11_timestamps: [u32; 11],
@@ -176,11 +459,18 @@ pub struct ChainState {
 
 impl ChainState {
     pub fn new() -> Self {
+        Self::new_with_params(&NETWORK_CONSTANTS)
+    }
+
+    /// Creates the state before the genesis block of the network with the given constants.
+    pub fn new_with_params(params: &NetworkConstants) -> Self {
         ChainState {
             block_height: u32::MAX,
             total_work: [0u8; 32],
             best_block_hash: [0u8; 32],
-            current_target_bits: NETWORK_CONSTANTS.max_bits,
+            // The first epoch keeps the bits of the genesis block, which are the maximum ones
+            // except on custom signets.
+            current_target_bits: params.genesis_block_header.bits,
             epoch_start_time: 0,
             prev_11_timestamps: [0u32; 11],
             block_hashes_mmr: MMRGuest::new(),
@@ -188,49 +478,51 @@ impl ChainState {
     }
 
     pub fn apply_blocks(&mut self, block_headers: Vec<CircuitBlockHeader>) {
-        let mut current_target_bytes = if IS_REGTEST {
-            NETWORK_CONSTANTS.max_target.to_be_bytes()
-        } else {
-            bits_to_target(self.current_target_bits)
-        };
-        let mut current_work: U256 = U256::from_be_bytes(self.total_work);
+        self.apply_blocks_with_params(block_headers, &NETWORK_CONSTANTS);
+    }
 
-        let mut last_block_time = if IS_TESTNET4 {
-            if self.block_height == u32::MAX {
-                0
-            } else {
-                self.prev_11_timestamps[self.block_height as usize % 11]
+    /// Applies the block headers under the consensus rules of the network with the given
+    /// constants. Panics if any of the headers is invalid.
+    pub fn apply_blocks_with_params(
+        &mut self,
+        block_headers: Vec<CircuitBlockHeader>,
+        params: &NetworkConstants,
+    ) {
+        for block_header in block_headers {
+            if let Err(e) = self.try_apply_block(&block_header, params) {
+                panic!("{}", e);
             }
+        }
+    }
+
+    /// Applies the block header under the consensus rules of the network with the given constants.
+    /// Returns why the header is invalid, and leaves the state unchanged then.
+    pub fn try_apply_block(
+        &mut self,
+        block_header: &CircuitBlockHeader,
+        params: &NetworkConstants,
+    ) -> Result<(), BlockHeaderError> {
+        let block_height = self.block_height.wrapping_add(1);
+        let current_target_bytes = if params.pow_no_retargeting {
+            params.max_target_bytes
         } else {
-            0
+            bits_to_target(self.current_target_bits)
         };
 
-        for block_header in block_headers {
-            self.block_height = self.block_height.wrapping_add(1);
-
-            let (target_to_use, expected_bits, work_to_add) = if IS_TESTNET4 {
-                if block_header.time > last_block_time + 1200 {
-                    // If the block is an epoch block, then it still has to have the real target.
-                    if self.block_height % BLOCKS_PER_EPOCH == 0 {
-                        (
-                            current_target_bytes,
-                            self.current_target_bits,
-                            calculate_work(&current_target_bytes),
-                        )
-                    } else {
-                        (
-                            NETWORK_CONSTANTS.max_target_bytes,
-                            NETWORK_CONSTANTS.max_bits,
-                            MINIMUM_WORK_TESTNET,
-                        )
-                    }
-                } else {
-                    (
-                        current_target_bytes,
-                        self.current_target_bits,
-                        calculate_work(&current_target_bytes),
-                    )
-                }
+        let (target_to_use, expected_bits, work_to_add) =
+            if self.is_min_difficulty_block(block_header.time, params) {
+                // Like any block, it is credited with the work of its own bits.
+                (
+                    params.max_target_bytes,
+                    params.max_bits,
+                    calculate_work(&params.max_target_bytes),
+                )
+            } else if params.pow_no_retargeting {
+                (
+                    current_target_bytes,
+                    params.max_bits,
+                    calculate_work(&current_target_bytes),
+                )
             } else {
                 (
                     current_target_bytes,
@@ -239,47 +531,108 @@ impl ChainState {
                 )
             };
 
-            let new_block_hash = block_header.compute_block_hash();
+        let new_block_hash = block_header.compute_block_hash();
 
-            assert_eq!(block_header.prev_block_hash, self.best_block_hash);
+        // A new chain state has no previous block, so the chain must start at the genesis block
+        // of the network instead of any header with an all-zero previous block hash.
+        if block_height == 0 && new_block_hash != params.genesis_block_hash {
+            return Err(BlockHeaderError::InvalidGenesisBlock);
+        }
 
-            if IS_REGTEST {
-                assert_eq!(block_header.bits, NETWORK_CONSTANTS.max_bits);
-            } else {
-                assert_eq!(block_header.bits, expected_bits);
-            }
+        if block_header.prev_block_hash != self.best_block_hash {
+            return Err(BlockHeaderError::PrevBlockHashMismatch);
+        }
 
-            check_hash_valid(&new_block_hash, &target_to_use);
+        if is_version_outdated(block_header.version, block_height, params) {
+            return Err(BlockHeaderError::OutdatedVersion);
+        }
 
-            if !validate_timestamp(block_header.time, self.prev_11_timestamps) {
-                panic!("Timestamp is not valid");
-            }
+        checked_bits_to_target(block_header.bits).map_err(BlockHeaderError::InvalidBits)?;
 
-            self.block_hashes_mmr.append(new_block_hash);
-            self.best_block_hash = new_block_hash;
-            current_work = current_work.wrapping_add(&work_to_add);
+        if block_header.bits != expected_bits {
+            return Err(BlockHeaderError::UnexpectedBits {
+                expected: expected_bits,
+                actual: block_header.bits,
+            });
+        }
 
-            if !IS_REGTEST && self.block_height % BLOCKS_PER_EPOCH == 0 {
-                self.epoch_start_time = block_header.time;
-            }
+        check_hash_valid(&new_block_hash, &target_to_use)?;
 
-            self.prev_11_timestamps[self.block_height as usize % 11] = block_header.time;
+        if !validate_timestamp(block_header.time, self.prev_11_timestamps) {
+            return Err(BlockHeaderError::InvalidTimestamp);
+        }
 
-            if IS_TESTNET4 {
-                last_block_time = block_header.time;
+        // BIP94 stops the time warp attack, which moves the start of an epoch back in time.
+        if params.enforce_bip94 && block_height != 0 && block_height % BLOCKS_PER_EPOCH == 0 {
+            let prev_block_time = self.prev_11_timestamps[(block_height - 1) as usize % 11];
+            if block_header.time < prev_block_time.saturating_sub(MAX_TIMEWARP) {
+                return Err(BlockHeaderError::TimewarpTimestamp);
             }
+        }
 
-            if !IS_REGTEST && self.block_height % BLOCKS_PER_EPOCH == BLOCKS_PER_EPOCH - 1 {
-                current_target_bytes = calculate_new_difficulty(
-                    self.epoch_start_time,
-                    block_header.time,
-                    self.current_target_bits,
-                );
-                self.current_target_bits = target_to_bits(&current_target_bytes);
-            }
+        self.block_height = block_height;
+        self.block_hashes_mmr.append(new_block_hash);
+        self.best_block_hash = new_block_hash;
+        self.total_work = U256::from_be_bytes(self.total_work)
+            .wrapping_add(&work_to_add)
+            .to_be_bytes();
+
+        if !params.pow_no_retargeting && block_height % BLOCKS_PER_EPOCH == 0 {
+            self.epoch_start_time = block_header.time;
         }
 
-        self.total_work = current_work.to_be_bytes();
+        self.prev_11_timestamps[block_height as usize % 11] = block_header.time;
+
+        if !params.pow_no_retargeting && block_height % BLOCKS_PER_EPOCH == BLOCKS_PER_EPOCH - 1 {
+            // Without BIP94, the retarget scales the bits of the last block of the epoch, even if
+            // it uses the minimum difficulty. The next blocks are checked against, and credited
+            // with the work of, the target the new bits encode, which is the new target rounded
+            // down.
+            let last_bits = if params.enforce_bip94 {
+                self.current_target_bits
+            } else {
+                block_header.bits
+            };
+            self.current_target_bits = target_to_bits(&calculate_new_difficulty(
+                self.epoch_start_time,
+                block_header.time,
+                last_bits,
+                params,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Whether the next block may, and then must, use the minimum difficulty because it is more
+    /// than 20 minutes later than the best block, on a network that allows it. Epoch blocks never
+    /// do.
+    pub fn is_min_difficulty_block(&self, time: u32, params: &NetworkConstants) -> bool {
+        if !params.pow_allow_min_difficulty_blocks || self.block_height == u32::MAX {
+            return false;
+        }
+        let last_block_time = self.prev_11_timestamps[self.block_height as usize % 11];
+        (self.block_height + 1) % BLOCKS_PER_EPOCH != 0
+            && time > last_block_time.saturating_add(1200)
+    }
+
+    /// The bits the next block must have, given its timestamp.
+    pub fn expected_bits(&self, time: u32, params: &NetworkConstants) -> u32 {
+        if params.pow_no_retargeting || self.is_min_difficulty_block(time, params) {
+            params.max_bits
+        } else {
+            self.current_target_bits
+        }
+    }
+
+    /// The SHA256 digest of the borsh encoding, which range proofs commit to their initial state by.
+    pub fn digest(&self) -> [u8; 32] {
+        let bytes = borsh::to_vec(self).expect("Serialization to vec is infallible");
+        Sha256::digest(&bytes).into()
+    }
+
+    /// Returns whether the total work of the chain is at least `min_total_work` (big-endian).
+    pub fn has_min_total_work(&self, min_total_work: &[u8; 32]) -> bool {
+        U256::from_be_bytes(self.total_work) >= U256::from_be_bytes(*min_total_work)
     }
 }
 
@@ -289,74 +642,194 @@ fn median(arr: [u32; 11]) -> u32 {
     sorted_arr[5]
 }
 
+/// Whether a block at the height has a version that BIP34, BIP66 or BIP65 no longer allows there,
+/// as in Bitcoin Core's `ContextualCheckBlockHeader`.
+fn is_version_outdated(version: i32, height: u32, params: &NetworkConstants) -> bool {
+    (version < 2 && height >= params.bip34_height)
+        || (version < 3 && height >= params.bip66_height)
+        || (version < 4 && height >= params.bip65_height)
+}
+
 fn validate_timestamp(block_time: u32, prev_11_timestamps: [u32; 11]) -> bool {
     let median_time = median(prev_11_timestamps);
     block_time > median_time
 }
 
+/// Why a block header does not extend a chain state under the consensus rules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockHeaderError {
+    /// The first header of a chain is not the genesis block of the network.
+    InvalidGenesisBlock,
+    /// The header does not build on the best block of the chain.
+    PrevBlockHashMismatch,
+    /// The version was outdated by BIP34, BIP66 or BIP65 at the height of the header.
+    OutdatedVersion,
+    InvalidBits(CompactTargetError),
+    /// The bits are not the ones the chain requires for the header.
+    UnexpectedBits {
+        expected: u32,
+        actual: u32,
+    },
+    /// The hash does not meet the target.
+    InvalidHash,
+    /// The timestamp is not later than the median of the last 11 timestamps.
+    InvalidTimestamp,
+    /// BIP94: the first block of an epoch is too much earlier than the last one.
+    TimewarpTimestamp,
+}
+
+impl core::fmt::Display for BlockHeaderError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            BlockHeaderError::InvalidGenesisBlock => write!(f, "Invalid genesis block"),
+            BlockHeaderError::PrevBlockHashMismatch => {
+                write!(f, "Header does not build on the best block")
+            }
+            BlockHeaderError::OutdatedVersion => write!(f, "Outdated block version"),
+            BlockHeaderError::InvalidBits(e) => write!(f, "Invalid bits: {}", e),
+            BlockHeaderError::UnexpectedBits { expected, actual } => {
+                write!(f, "Bits are {:08x} instead of {:08x}", actual, expected)
+            }
+            BlockHeaderError::InvalidHash => write!(f, "Hash is not valid"),
+            BlockHeaderError::InvalidTimestamp => write!(f, "Timestamp is not valid"),
+            BlockHeaderError::TimewarpTimestamp => {
+                write!(f, "Timestamp is too early for the first block of an epoch")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BlockHeaderError {}
+
+/// Why compact bits do not encode a target that a block can use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompactTargetError {
+    /// The sign bit of a non-zero mantissa is set.
+    Negative,
+    /// The target does not fit in 256 bits.
+    Overflow,
+    /// The target is zero.
+    Zero,
+}
+
+impl core::fmt::Display for CompactTargetError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CompactTargetError::Negative => write!(f, "the target is negative"),
+            CompactTargetError::Overflow => write!(f, "the target overflows 256 bits"),
+            CompactTargetError::Zero => write!(f, "the target is zero"),
+        }
+    }
+}
+
+impl std::error::Error for CompactTargetError {}
+
+/// Decodes compact bits like Bitcoin Core's `SetCompact`: the sign bit is ignored and the bits
+/// shifted beyond 256 bits are dropped. See `checked_bits_to_target` for the bits of a header.
 pub fn bits_to_target(bits: u32) -> [u8; 32] {
     let size = (bits >> 24) as usize;
-    let mantissa = bits & 0x00ffffff;
+    let word = bits & 0x007fffff;
 
     let target = if size <= 3 {
-        U256::from(mantissa >> (8 * (3 - size)))
+        U256::from(word >> (8 * (3 - size)))
     } else {
-        U256::from(mantissa) << (8 * (size - 3))
+        U256::from(word).shl_vartime(8 * (size - 3))
     };
-    // println!("Output: {:?}", target.to_be_bytes());
     target.to_be_bytes()
 }
 
+/// Decodes compact bits, rejecting the negative, overflowing and zero targets that Bitcoin
+/// Core's `CheckProofOfWork` rejects.
+pub fn checked_bits_to_target(bits: u32) -> Result<[u8; 32], CompactTargetError> {
+    let size = bits >> 24;
+    let word = bits & 0x007fffff;
+    let mantissa = if size <= 3 {
+        word >> (8 * (3 - size))
+    } else {
+        word
+    };
+    if mantissa != 0 && bits & 0x00800000 != 0 {
+        return Err(CompactTargetError::Negative);
+    }
+    if mantissa != 0
+        && (size > 34 || (mantissa > 0xff && size > 33) || (mantissa > 0xffff && size > 32))
+    {
+        return Err(CompactTargetError::Overflow);
+    }
+    if mantissa == 0 {
+        return Err(CompactTargetError::Zero);
+    }
+    Ok(bits_to_target(bits))
+}
+
 fn target_to_bits(target: &[u8; 32]) -> u32 {
-    let target_u256 = U256::from_be_slice(target);
-    let target_bits = target_u256.bits();
-    let size = (263 - target_bits) / 8;
-    let mut compact_target = [0u8; 4];
-    compact_target[0] = 33 - size as u8;
-    compact_target[1] = target[size - 1 as usize];
-    compact_target[2] = target[size + 0 as usize];
-    compact_target[3] = target[size + 1 as usize];
-    u32::from_be_bytes(compact_target)
+    let target = U256::from_be_slice(target);
+    let mut size = target.bits().div_ceil(8);
+    let mut compact = if size <= 3 {
+        low_u32(&target) << (8 * (3 - size))
+    } else {
+        low_u32(&target.shr_vartime(8 * (size - 3)))
+    };
+    // The mantissa is signed, so it is shifted into the exponent if its sign bit would be set.
+    if compact & 0x00800000 != 0 {
+        compact >>= 8;
+        size += 1;
+    }
+    compact | ((size as u32) << 24)
+}
+
+fn low_u32(value: &U256) -> u32 {
+    let bytes = value.to_be_bytes();
+    u32::from_be_bytes([bytes[28], bytes[29], bytes[30], bytes[31]])
 }
 
 fn calculate_new_difficulty(
     epoch_start_time: u32,
     last_timestamp: u32,
     current_target: u32,
+    params: &NetworkConstants,
 ) -> [u8; 32] {
-    let mut actual_timespan = last_timestamp - epoch_start_time;
-    if actual_timespan < EXPECTED_EPOCH_TIMESPAN / 4 {
-        actual_timespan = EXPECTED_EPOCH_TIMESPAN / 4;
-    } else if actual_timespan > EXPECTED_EPOCH_TIMESPAN * 4 {
-        actual_timespan = EXPECTED_EPOCH_TIMESPAN * 4;
-    }
+    // Timestamps are only bounded by the median time past, so the last block of an epoch can be
+    // earlier than its first one. The timespan is signed before it is clamped.
+    let actual_timespan = (last_timestamp as i64 - epoch_start_time as i64).clamp(
+        EXPECTED_EPOCH_TIMESPAN as i64 / 4,
+        EXPECTED_EPOCH_TIMESPAN as i64 * 4,
+    ) as u32;
 
     let new_target_bytes = bits_to_target(current_target);
     let mut new_target = U256::from_be_bytes(new_target_bytes)
         .wrapping_mul(&U256::from(actual_timespan))
         .wrapping_div(&U256::from(EXPECTED_EPOCH_TIMESPAN));
 
-    if new_target > NETWORK_CONSTANTS.max_target {
-        new_target = NETWORK_CONSTANTS.max_target;
+    if new_target > params.max_target {
+        new_target = params.max_target;
     }
     new_target.to_be_bytes()
 }
 
-fn check_hash_valid(hash: &[u8; 32], target_bytes: &[u8; 32]) {
+fn check_hash_valid(hash: &[u8; 32], target_bytes: &[u8; 32]) -> Result<(), BlockHeaderError> {
     for i in 0..32 {
         if hash[31 - i] < target_bytes[i] {
-            return;
+            return Ok(());
         } else if hash[31 - i] > target_bytes[i] {
-            panic!("Hash is not valid");
+            return Err(BlockHeaderError::InvalidHash);
         }
     }
+    Ok(())
 }
 
+/// The expected number of hashes to meet the target, 2^256 / (target + 1), computed as
+/// `!target / (target + 1) + 1` like Bitcoin Core. A zero target has no work.
 fn calculate_work(target: &[u8; 32]) -> U256 {
     let target = U256::from_be_slice(target);
+    if target == U256::ZERO {
+        return U256::ZERO;
+    }
     let target_plus_one = target.saturating_add(&U256::ONE);
-    let work = U256::MAX.wrapping_div(&target_plus_one);
-    work
+    target
+        .not()
+        .wrapping_div(&target_plus_one)
+        .wrapping_add(&U256::ONE)
 }
 
 /// The output of the header chain circuit.
@@ -366,12 +839,69 @@ pub struct BlockHeaderCircuitOutput {
     pub chain_state: ChainState,
 }
 
+/// The first field of `HeaderChainRangeOutput`. A header chain that builds on a range output
+/// would carry this instead of the method ID, so range outputs can never be used in place of a
+/// `BlockHeaderCircuitOutput`.
+pub const RANGE_OUTPUT_TAG: [u32; 8] = [u32::MAX; 8];
+
+/// The output of the header chain circuit for a range of headers applied to an assumed state.
+/// It only proves that the headers extend the state with the digest `initial_state_digest`, so it
+/// has to be composed with the proofs of the preceding headers.
+#[derive(Serialize, Deserialize, Eq, PartialEq, Clone, Debug, BorshDeserialize, BorshSerialize)]
+pub struct HeaderChainRangeOutput {
+    pub tag: [u32; 8],
+    pub method_id: [u32; 8],
+    #[serde(with = "serde_utils::hex_hash")]
+    pub initial_state_digest: [u8; 32],
+    pub chain_state: ChainState,
+}
+
+impl HeaderChainRangeOutput {
+    pub fn new(method_id: [u32; 8], initial_state: &ChainState, chain_state: ChainState) -> Self {
+        HeaderChainRangeOutput {
+            tag: RANGE_OUTPUT_TAG,
+            method_id,
+            initial_state_digest: initial_state.digest(),
+            chain_state,
+        }
+    }
+
+    /// Returns the state after applying the adjacent ranges to `chain_state`. Panics if a range
+    /// does not start where the previous one ends. The ranges must be verified separately.
+    pub fn compose(
+        mut chain_state: ChainState,
+        method_id: [u32; 8],
+        ranges: Vec<HeaderChainRangeOutput>,
+    ) -> ChainState {
+        for range in ranges {
+            assert_eq!(range.tag, RANGE_OUTPUT_TAG, "Invalid range output tag");
+            assert_eq!(range.method_id, method_id, "Invalid range method ID");
+            assert_eq!(
+                range.initial_state_digest,
+                chain_state.digest(),
+                "Range is not adjacent to the previous state"
+            );
+            chain_state = range.chain_state;
+        }
+        chain_state
+    }
+}
+
 /// The input proof of the header chain circuit.
 /// The proof can be either None (implying the beginning) or a Succinct Risc0 proof.
 #[derive(Serialize, Deserialize, Eq, PartialEq, Clone, Debug, BorshDeserialize, BorshSerialize)]
 pub enum HeaderChainPrevProofType {
     GenesisBlock,
     PrevProof(BlockHeaderCircuitOutput),
+    /// Applies the headers to the given state without a proof of it. The circuit commits a
+    /// `HeaderChainRangeOutput` instead of a `BlockHeaderCircuitOutput`.
+    UnknownPrevState(ChainState),
+    /// Composes the range proofs with the previous proof (or genesis if `None`) before applying
+    /// the headers. The ranges are verified against the method ID of the circuit.
+    RangeProofs {
+        prev_proof: Option<BlockHeaderCircuitOutput>,
+        ranges: Vec<HeaderChainRangeOutput>,
+    },
 }
 
 /// The input of the header chain circuit.
@@ -385,9 +915,33 @@ pub struct HeaderChainCircuitInput {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use bitcoin::Block;
+    use crate::synthetic::{lowered_difficulty, BlockSpec, ChainBuilder};
+    use bitcoin::{
+        consensus::params::Params,
+        constants::genesis_block,
+        pow::{CompactTarget, Target},
+        Network,
+    };
     use hex_literal::hex;
 
+    const REGTEST_HEADERS: &[u8] = include_bytes!("../../data/headers/regtest-headers.bin");
+
+    fn regtest_headers() -> Vec<CircuitBlockHeader> {
+        REGTEST_HEADERS
+            .chunks(80)
+            .map(|header| CircuitBlockHeader::try_from_slice(header).unwrap())
+            .collect()
+    }
+
+    /// Increments the nonce until the header satisfies the regtest proof of work.
+    fn mine_regtest(mut header: CircuitBlockHeader) -> CircuitBlockHeader {
+        let target = REGTEST_CONSTANTS.max_target_bytes;
+        while header.compute_block_hash()[31] >= target[0] {
+            header.nonce += 1;
+        }
+        header
+    }
+
     // From block 800000 to 800015
     const BLOCK_HEADERS: [[u8; 80]; 15] = [
         hex!("00601d3455bb9fbd966b3ea2dc42d0c22722e4c0c1729fad17210100000000000000000055087fab0c8f3f89f8bcfd4df26c504d81b0a88e04907161838c0c53001af09135edbd64943805175e955e06"),
@@ -923,7 +1477,6 @@ mod tests {
     }
 
     #[test]
-    #[should_panic(expected = "Hash is not valid")]
     fn test_hash_check_fail() {
         let block_headers = BLOCK_HEADERS
             .iter()
@@ -935,12 +1488,16 @@ mod tests {
             .map(|header| header.compute_block_hash())
             .collect::<Vec<[u8; 32]>>();
 
-        // The validation is expected to panic
-        check_hash_valid(
-            &first_15_hashes[0],
-            &U256::from_be_hex("00000000FFFF0000000000000000000000000000000000000000000000000000")
+        assert_eq!(
+            check_hash_valid(
+                &first_15_hashes[0],
+                &U256::from_be_hex(
+                    "00000000FFFF0000000000000000000000000000000000000000000000000000"
+                )
                 .wrapping_div(&(U256::ONE << 157))
                 .to_be_bytes(),
+            ),
+            Err(BlockHeaderError::InvalidHash)
         );
     }
 
@@ -957,7 +1514,7 @@ mod tests {
             .collect::<Vec<[u8; 32]>>();
 
         for (i, hash) in first_15_hashes.into_iter().enumerate() {
-            check_hash_valid(&hash, &bits_to_target(block_headers[i].bits));
+            check_hash_valid(&hash, &bits_to_target(block_headers[i].bits)).unwrap();
         }
     }
 
@@ -987,7 +1544,8 @@ mod tests {
     #[test]
     fn test_difficulty_adjustments() {
         for (start_time, end_time, start_target, end_target) in DIFFICULTY_ADJUSTMENTS {
-            let new_target_bytes = calculate_new_difficulty(start_time, end_time, start_target);
+            let new_target_bytes =
+                calculate_new_difficulty(start_time, end_time, start_target, &MAINNET_CONSTANTS);
             let bits = target_to_bits(&new_target_bytes);
             assert_eq!(bits, end_target);
         }
@@ -1072,4 +1630,890 @@ mod tests {
         assert_eq!(original_header, converted_header);
         assert_eq!(original_header.block_hash(), converted_header.block_hash());
     }
+
+    #[test]
+    fn test_genesis_constants() {
+        for (constants, network) in [
+            (MAINNET_CONSTANTS, Network::Bitcoin),
+            (TESTNET4_CONSTANTS, Network::Testnet4),
+            (TESTNET3_CONSTANTS, Network::Testnet),
+            (SIGNET_CONSTANTS, Network::Signet),
+            (REGTEST_CONSTANTS, Network::Regtest),
+        ] {
+            let genesis_header: Header = constants.genesis_block_header.clone().into();
+            assert_eq!(genesis_header, genesis_block(network).header);
+            assert_eq!(
+                constants.genesis_block_header.compute_block_hash(),
+                constants.genesis_block_hash
+            );
+            assert_eq!(constants.magic, network.magic().to_bytes());
+            assert_eq!(constants.max_bits, constants.genesis_block_header.bits);
+            if network != Network::Regtest {
+                let params = Params::new(network);
+                assert_eq!(constants.bip34_height, params.bip34_height);
+                assert_eq!(constants.bip66_height, params.bip66_height);
+                assert_eq!(constants.bip65_height, params.bip65_height);
+            }
+        }
+    }
+
+    #[test]
+    fn test_version_activation_heights() {
+        let params = &MAINNET_CONSTANTS;
+        for (version, last_valid_height) in [(1, 227930), (2, 363724), (3, 388380)] {
+            assert!(!is_version_outdated(version, last_valid_height, params));
+            assert!(is_version_outdated(version, last_valid_height + 1, params));
+        }
+        assert!(!is_version_outdated(4, u32::MAX, params));
+        // Versions are signed, so setting the top bit makes a version outdated.
+        assert!(is_version_outdated(0x80000004u32 as i32, 800000, params));
+    }
+
+    #[test]
+    fn test_mainnet_headers_after_activation() {
+        // Blocks 800000 to 800014 use version bits, which are above version 4.
+        let block_headers = BLOCK_HEADERS
+            .iter()
+            .map(|header| CircuitBlockHeader::try_from_slice(header).unwrap())
+            .collect::<Vec<CircuitBlockHeader>>();
+        let mut chain_state = ChainState {
+            block_height: 799999,
+            total_work: [0; 32],
+            best_block_hash: block_headers[0].prev_block_hash,
+            current_target_bits: block_headers[0].bits,
+            epoch_start_time: 0,
+            prev_11_timestamps: [block_headers[0].time - 3600; 11],
+            block_hashes_mmr: MMRGuest::new(),
+        };
+        chain_state.apply_blocks_with_params(block_headers.clone(), &MAINNET_CONSTANTS);
+        assert_eq!(chain_state.block_height, 800014);
+        assert_eq!(
+            chain_state.best_block_hash,
+            block_headers.last().unwrap().compute_block_hash()
+        );
+    }
+
+    #[test]
+    fn test_outdated_versions_rejected() {
+        let headers = regtest_headers();
+        for version in [1, 2, 3, 4, 0x20000000, -1] {
+            let mut header = headers[1].clone();
+            header.version = version;
+            let header = mine_regtest(header);
+            let mut chain_state = ChainState::new_with_params(&REGTEST_CONSTANTS);
+            chain_state.apply_blocks_with_params(vec![headers[0].clone()], &REGTEST_CONSTANTS);
+            let unchanged = chain_state.clone();
+            match chain_state.try_apply_block(&header, &REGTEST_CONSTANTS) {
+                Ok(()) => assert!(version >= 4, "version {} accepted", version),
+                Err(e) => {
+                    assert!(version < 4, "version {} rejected", version);
+                    assert_eq!(e, BlockHeaderError::OutdatedVersion);
+                    assert_eq!(chain_state, unchanged);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_apply_regtest_headers() {
+        let headers = regtest_headers();
+        let mut chain_state = ChainState::new_with_params(&REGTEST_CONSTANTS);
+        chain_state.apply_blocks_with_params(headers[..1].to_vec(), &REGTEST_CONSTANTS);
+        assert_eq!(chain_state.block_height, 0);
+        assert_eq!(
+            chain_state.best_block_hash,
+            REGTEST_CONSTANTS.genesis_block_hash
+        );
+
+        chain_state.apply_blocks_with_params(headers[1..].to_vec(), &REGTEST_CONSTANTS);
+        assert_eq!(chain_state.block_height, headers.len() as u32 - 1);
+        assert_eq!(
+            chain_state.best_block_hash,
+            headers.last().unwrap().compute_block_hash()
+        );
+
+        // Each regtest block has a work of 2.
+        let total_work = U256::from(2 * headers.len() as u64);
+        assert_eq!(chain_state.total_work, total_work.to_be_bytes());
+        assert!(chain_state.has_min_total_work(&total_work.to_be_bytes()));
+        assert!(!chain_state.has_min_total_work(&total_work.wrapping_add(&U256::ONE).to_be_bytes()));
+        assert!(!chain_state.has_min_total_work(&MAINNET_CONSTANTS.min_total_work.to_be_bytes()));
+    }
+
+    #[test]
+    fn test_chain_state_json() {
+        let headers = regtest_headers();
+        let mut chain_state = ChainState::new_with_params(&REGTEST_CONSTANTS);
+        chain_state.apply_blocks_with_params(headers[..10].to_vec(), &REGTEST_CONSTANTS);
+
+        let json = serde_json::to_value(&chain_state).unwrap();
+        let mut best_block_hash = chain_state.best_block_hash;
+        best_block_hash.reverse();
+        assert_eq!(json["best_block_hash"], hex::encode(best_block_hash));
+        assert_eq!(json["total_work"], "0x14");
+        assert_eq!(json["current_target_bits"], "207fffff");
+        assert_eq!(
+            serde_json::from_value::<ChainState>(json.clone()).unwrap(),
+            chain_state
+        );
+
+        // Decimal work is accepted.
+        let mut decimal = json;
+        decimal["total_work"] = "20".into();
+        assert_eq!(
+            serde_json::from_value::<ChainState>(decimal).unwrap(),
+            chain_state
+        );
+
+        // The binary encoding of the guests is unchanged.
+        let words = risc0_zkvm::serde::to_vec(&chain_state).unwrap();
+        let total_work = risc0_zkvm::serde::to_vec(&chain_state.total_work).unwrap();
+        assert_eq!(words[1..1 + total_work.len()], total_work);
+        assert_eq!(
+            risc0_zkvm::serde::from_slice::<ChainState, u32>(&words).unwrap(),
+            chain_state
+        );
+    }
+
+    #[test]
+    fn test_block_header_json() {
+        let genesis = &MAINNET_CONSTANTS.genesis_block_header;
+        let json = serde_json::to_value(genesis).unwrap();
+        assert_eq!(
+            json["merkle_root"],
+            "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b"
+        );
+        assert_eq!(json["bits"], "1d00ffff");
+        assert_eq!(
+            &serde_json::from_value::<CircuitBlockHeader>(json).unwrap(),
+            genesis
+        );
+
+        for (field, value) in [
+            ("prev_block_hash", "00"),
+            ("merkle_root", "xyz"),
+            ("bits", "1d00fff"),
+        ] {
+            let mut json = serde_json::to_value(genesis).unwrap();
+            json[field] = value.into();
+            assert!(serde_json::from_value::<CircuitBlockHeader>(json).is_err());
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid genesis block")]
+    fn test_forged_genesis_regtest() {
+        let mut forged_genesis = REGTEST_CONSTANTS.genesis_block_header.clone();
+        forged_genesis.merkle_root = [1; 32];
+        let forged_genesis = mine_regtest(forged_genesis);
+        // The forged genesis block is valid except for its hash.
+        check_hash_valid(
+            &forged_genesis.compute_block_hash(),
+            &REGTEST_CONSTANTS.max_target_bytes,
+        )
+        .unwrap();
+
+        let mut chain_state = ChainState::new_with_params(&REGTEST_CONSTANTS);
+        chain_state.apply_blocks_with_params(vec![forged_genesis], &REGTEST_CONSTANTS);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid genesis block")]
+    fn test_forged_chain_regtest() {
+        // A private chain with more blocks than the real one, starting from a forged genesis.
+        let mut headers = regtest_headers();
+        let mut prev_block_hash = [0; 32];
+        for header in headers.iter_mut() {
+            header.prev_block_hash = prev_block_hash;
+            header.time += 1;
+            *header = mine_regtest(header.clone());
+            prev_block_hash = header.compute_block_hash();
+        }
+
+        let mut chain_state = ChainState::new_with_params(&REGTEST_CONSTANTS);
+        chain_state.apply_blocks_with_params(headers, &REGTEST_CONSTANTS);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid genesis block")]
+    fn test_skip_genesis_regtest() {
+        let headers = regtest_headers();
+        let mut chain_state = ChainState::new_with_params(&REGTEST_CONSTANTS);
+        chain_state.apply_blocks_with_params(headers[1..].to_vec(), &REGTEST_CONSTANTS);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid genesis block")]
+    fn test_other_network_genesis() {
+        let mut chain_state = ChainState::new_with_params(&REGTEST_CONSTANTS);
+        chain_state.apply_blocks_with_params(
+            vec![SIGNET_CONSTANTS.genesis_block_header],
+            &REGTEST_CONSTANTS,
+        );
+    }
+
+    fn regtest_range(
+        initial_state: &ChainState,
+        headers: &[CircuitBlockHeader],
+    ) -> HeaderChainRangeOutput {
+        let mut chain_state = initial_state.clone();
+        chain_state.apply_blocks_with_params(headers.to_vec(), &REGTEST_CONSTANTS);
+        HeaderChainRangeOutput::new([1; 8], initial_state, chain_state)
+    }
+
+    /// Splits the regtest headers into three ranges proven from their natively computed states.
+    fn regtest_ranges() -> (ChainState, Vec<HeaderChainRangeOutput>) {
+        let headers = regtest_headers();
+        let mut chain_state = ChainState::new_with_params(&REGTEST_CONSTANTS);
+        chain_state.apply_blocks_with_params(headers[..10].to_vec(), &REGTEST_CONSTANTS);
+        let prev_state = chain_state.clone();
+
+        let mut ranges = vec![];
+        for range in [10..40, 40..41, 41..101] {
+            let output = regtest_range(&chain_state, &headers[range]);
+            chain_state = output.chain_state.clone();
+            ranges.push(output);
+        }
+        (prev_state, ranges)
+    }
+
+    #[test]
+    fn test_compose_ranges() {
+        let (prev_state, ranges) = regtest_ranges();
+        let composed = HeaderChainRangeOutput::compose(prev_state.clone(), [1; 8], ranges);
+
+        let mut sequential = ChainState::new_with_params(&REGTEST_CONSTANTS);
+        sequential.apply_blocks_with_params(regtest_headers(), &REGTEST_CONSTANTS);
+        assert_eq!(composed, sequential);
+
+        // Composing no ranges keeps the state.
+        assert_eq!(
+            HeaderChainRangeOutput::compose(prev_state.clone(), [1; 8], vec![]),
+            prev_state
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Range is not adjacent to the previous state")]
+    fn test_compose_ranges_gap() {
+        let (prev_state, mut ranges) = regtest_ranges();
+        ranges.remove(1);
+        HeaderChainRangeOutput::compose(prev_state, [1; 8], ranges);
+    }
+
+    #[test]
+    #[should_panic(expected = "Range is not adjacent to the previous state")]
+    fn test_compose_ranges_out_of_order() {
+        let (prev_state, mut ranges) = regtest_ranges();
+        ranges.swap(1, 2);
+        HeaderChainRangeOutput::compose(prev_state, [1; 8], ranges);
+    }
+
+    #[test]
+    #[should_panic(expected = "Range is not adjacent to the previous state")]
+    fn test_compose_ranges_forged_state() {
+        // A range from an assumed state that matches the previous state except for its timing.
+        let (prev_state, ranges) = regtest_ranges();
+        let mut forged_state = prev_state.clone();
+        forged_state.epoch_start_time += 1;
+        let forged_range = regtest_range(&forged_state, &regtest_headers()[10..40]);
+        HeaderChainRangeOutput::compose(prev_state, [1; 8], vec![forged_range, ranges[1].clone()]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid range method ID")]
+    fn test_compose_ranges_method_id() {
+        let (prev_state, ranges) = regtest_ranges();
+        HeaderChainRangeOutput::compose(prev_state, [2; 8], ranges);
+    }
+
+    #[test]
+    fn test_range_output_is_not_circuit_output() {
+        let (_, ranges) = regtest_ranges();
+        for range in ranges {
+            let bytes = borsh::to_vec(&range).unwrap();
+            // Even if the bytes decode, the method ID is the tag, which the final circuit rejects.
+            if let Ok(output) = BlockHeaderCircuitOutput::try_from_slice(&bytes) {
+                assert_eq!(output.method_id, RANGE_OUTPUT_TAG);
+            }
+            assert_eq!(bytes[..32], [0xff; 32]);
+        }
+    }
+
+    /// A xorshift generator, so that the differential tests against the `bitcoin` crate, which
+    /// follows Bitcoin Core, are reproducible.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next_u32(&mut self) -> u32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 >> 32) as u32
+        }
+
+        /// Bits with a non-negative mantissa whose target fits in 256 bits and is at most `max`.
+        fn bits(&mut self, max: u32) -> u32 {
+            loop {
+                let bits = (self.next_u32() % 33) << 24 | self.next_u32() & 0x007fffff;
+                if bits_to_target(bits) <= bits_to_target(max) {
+                    return bits;
+                }
+            }
+        }
+    }
+
+    const DIFFERENTIAL_ROUNDS: usize = 10_000;
+
+    fn reference_target(bits: u32) -> Target {
+        Target::from_compact(CompactTarget::from_consensus(bits))
+    }
+
+    fn reference_next_bits(
+        bits: u32,
+        epoch_start_time: u32,
+        last_timestamp: u32,
+        params: &Params,
+    ) -> u32 {
+        // Bitcoin Core clamps the signed timespan, the `bitcoin` crate takes it clamped to zero.
+        let timespan = (last_timestamp as i64 - epoch_start_time as i64).max(0) as u64;
+        CompactTarget::from_next_work_required(
+            CompactTarget::from_consensus(bits),
+            timespan,
+            params,
+        )
+        .to_consensus()
+    }
+
+    #[test]
+    fn test_bits_to_target_matches_bitcoin() {
+        let edge_cases = [
+            0, 0x00123456, 0x01003456, 0x01123456, 0x02008000, 0x02123456, 0x03000000, 0x03123456,
+            0x04000000, 0x04123456, 0x05009234, 0x1702f128, 0x1d00ffff, 0x1e0377ae, 0x207fffff,
+            0x207fffff, 0x20000001,
+        ];
+        let mut rng = Rng(1);
+        let random = (0..DIFFERENTIAL_ROUNDS).map(|_| rng.bits(0x207fffff));
+        for bits in edge_cases.into_iter().chain(random) {
+            assert_eq!(
+                bits_to_target(bits),
+                reference_target(bits).to_be_bytes(),
+                "bits {:08x}",
+                bits
+            );
+        }
+    }
+
+    #[test]
+    fn test_compact_target_vectors() {
+        // The `SetCompact` and `GetCompact` vectors of Bitcoin Core's arith_uint256 tests.
+        for bits in [
+            0, 0x00123456, 0x01003456, 0x02000056, 0x03000000, 0x04000000, 0x00923456, 0x01803456,
+            0x02800056, 0x03800000, 0x04800000,
+        ] {
+            assert_eq!(bits_to_target(bits), [0; 32]);
+            assert_eq!(checked_bits_to_target(bits), Err(CompactTargetError::Zero));
+        }
+        for (bits, target, compact) in [
+            (0x01123456, U256::from(0x12u32), 0x01120000),
+            (0x02123456, U256::from(0x1234u32), 0x02123400),
+            (0x03123456, U256::from(0x123456u32), 0x03123456),
+            (0x04123456, U256::from(0x12345600u32), 0x04123456),
+            (0x05009234, U256::from(0x92340000u32), 0x05009234),
+            (
+                0x20123456,
+                U256::from(0x123456u32).shl_vartime(8 * 29),
+                0x20123456,
+            ),
+        ] {
+            assert_eq!(checked_bits_to_target(bits), Ok(target.to_be_bytes()));
+            assert_eq!(target_to_bits(&target.to_be_bytes()), compact);
+        }
+        for (bits, target) in [(0x01fedcba, 0x7eu32), (0x04923456, 0x12345600)] {
+            assert_eq!(bits_to_target(bits), U256::from(target).to_be_bytes());
+            assert_eq!(
+                checked_bits_to_target(bits),
+                Err(CompactTargetError::Negative)
+            );
+        }
+        assert_eq!(
+            checked_bits_to_target(0xff123456),
+            Err(CompactTargetError::Overflow)
+        );
+    }
+
+    #[test]
+    fn test_compact_target_all_exponents() {
+        let mut rng = Rng(5);
+        for size in 0..=255u32 {
+            let mut mantissas = vec![
+                0, 1, 0x7f, 0x80, 0xff, 0x100, 0xffff, 0x10000, 0x7fffff, 0x800000, 0x800001,
+                0xffffff,
+            ];
+            mantissas.extend((0..8).map(|_| rng.next_u32() & 0xffffff));
+            for mantissa in mantissas {
+                let bits = size << 24 | mantissa;
+                let reference = reference_target(bits).to_be_bytes();
+                match checked_bits_to_target(bits) {
+                    Ok(target) => {
+                        assert_eq!(target, reference, "bits {:08x}", bits);
+                        assert_eq!(target, bits_to_target(bits));
+                        // A valid target survives the round trip through its compact encoding.
+                        let compact = target_to_bits(&target);
+                        assert_eq!(
+                            compact,
+                            Target::from_be_bytes(target)
+                                .to_compact_lossy()
+                                .to_consensus()
+                        );
+                        assert_eq!(checked_bits_to_target(compact), Ok(target));
+                    }
+                    Err(CompactTargetError::Negative) => {
+                        assert_ne!(bits & 0x00800000, 0, "bits {:08x}", bits);
+                    }
+                    Err(CompactTargetError::Overflow) => {
+                        assert!(size > 32, "bits {:08x}", bits);
+                        // The value does not fit, so shifting it back loses bits.
+                        let word = U256::from(mantissa & 0x7fffff);
+                        assert_ne!(
+                            U256::from_be_bytes(bits_to_target(bits))
+                                .shr_vartime(8 * (size as usize - 3)),
+                            word,
+                            "bits {:08x}",
+                            bits
+                        );
+                    }
+                    Err(CompactTargetError::Zero) => {
+                        assert_eq!(bits_to_target(bits), [0; 32], "bits {:08x}", bits);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid bits: the target is negative")]
+    fn test_negative_bits_rejected() {
+        let mut headers = regtest_headers();
+        headers[1].bits = 0x20ffffff;
+        let mut chain_state = ChainState::new_with_params(&REGTEST_CONSTANTS);
+        chain_state.apply_blocks_with_params(headers[..2].to_vec(), &REGTEST_CONSTANTS);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid bits: the target overflows 256 bits")]
+    fn test_overflowing_bits_rejected() {
+        let mut headers = regtest_headers();
+        headers[1].bits = 0x22010000;
+        let mut chain_state = ChainState::new_with_params(&REGTEST_CONSTANTS);
+        chain_state.apply_blocks_with_params(headers[..2].to_vec(), &REGTEST_CONSTANTS);
+    }
+
+    #[test]
+    fn test_target_to_bits_matches_bitcoin() {
+        let mut edge_cases = vec![
+            [0; 32],
+            U256::ONE.to_be_bytes(),
+            U256::from(0x7fu32).to_be_bytes(),
+            U256::from(0x80u32).to_be_bytes(),
+            U256::from(0xffffu32).to_be_bytes(),
+            U256::from(0x800000u32).to_be_bytes(),
+            U256::MAX.to_be_bytes(),
+        ];
+        for (_, params) in NETWORKS.iter() {
+            edge_cases.push(params.max_target_bytes);
+        }
+        let mut rng = Rng(2);
+        let random = (0..DIFFERENTIAL_ROUNDS).map(|_| {
+            let mut bytes = [0u8; 32];
+            for byte in bytes.iter_mut() {
+                *byte = rng.next_u32() as u8;
+            }
+            let shift = rng.next_u32() as usize % 257;
+            U256::from_be_bytes(bytes).shr_vartime(shift).to_be_bytes()
+        });
+        for target in edge_cases.into_iter().chain(random) {
+            let bits = target_to_bits(&target);
+            assert_eq!(
+                bits,
+                Target::from_be_bytes(target)
+                    .to_compact_lossy()
+                    .to_consensus(),
+                "target {:?}",
+                target
+            );
+            // The compact encoding rounds down.
+            assert!(bits_to_target(bits) <= target);
+        }
+    }
+
+    #[test]
+    fn test_calculate_new_difficulty_matches_bitcoin() {
+        let expected = EXPECTED_EPOCH_TIMESPAN;
+        // Timestamps are only bounded by the median time past, so an epoch can end before it
+        // starts, and the timespan can exceed the range of the difference of two `u32`s.
+        let edge_cases = [
+            (0, 0),
+            (1000, 0),
+            (1, 0),
+            (u32::MAX, 0),
+            (0, u32::MAX),
+            (u32::MAX, u32::MAX),
+            (1_700_000_000, 1_700_000_000 - expected),
+            (0, expected / 4 - 1),
+            (0, expected / 4 + 1),
+            (0, expected * 4 - 1),
+            (0, expected * 4 + 1),
+        ];
+        let mut rng = Rng(3);
+        for (epoch_start_time, last_timestamp) in edge_cases {
+            for bits in [0x1d00ffff, 0x1702f128, 0x04123456, rng.bits(0x1d00ffff)] {
+                assert_eq!(
+                    target_to_bits(&calculate_new_difficulty(
+                        epoch_start_time,
+                        last_timestamp,
+                        bits,
+                        &MAINNET_CONSTANTS
+                    )),
+                    reference_next_bits(bits, epoch_start_time, last_timestamp, &Params::MAINNET),
+                    "bits {:08x}, timestamps {} to {}",
+                    bits,
+                    epoch_start_time,
+                    last_timestamp
+                );
+            }
+        }
+        for _ in 0..DIFFERENTIAL_ROUNDS {
+            let bits = rng.bits(0x1d00ffff);
+            let epoch_start_time = rng.next_u32();
+            let offset = rng.next_u32() as i64 % (expected as i64 * 6) - expected as i64;
+            let last_timestamp =
+                (epoch_start_time as i64 + offset).clamp(0, u32::MAX as i64) as u32;
+            assert_eq!(
+                target_to_bits(&calculate_new_difficulty(
+                    epoch_start_time,
+                    last_timestamp,
+                    bits,
+                    &MAINNET_CONSTANTS
+                )),
+                reference_next_bits(bits, epoch_start_time, last_timestamp, &Params::MAINNET),
+                "bits {:08x}, timestamps {} to {}",
+                bits,
+                epoch_start_time,
+                last_timestamp
+            );
+        }
+    }
+
+    #[test]
+    fn test_calculate_work_matches_bitcoin() {
+        let mut rng = Rng(4);
+        let random = (0..DIFFERENTIAL_ROUNDS).map(|_| rng.bits(0x207fffff));
+        // A target of 0x7fffff is one less than a power of two.
+        let edge_cases = [0x037fffff, 0x03000002, 0x1d00ffff, 0x207fffff, 0x2100ffff];
+        for bits in edge_cases.into_iter().chain(random) {
+            // The `bitcoin` crate defines the work of the zero and one targets differently.
+            if bits_to_target(bits) <= U256::ONE.to_be_bytes() {
+                continue;
+            }
+            assert_eq!(
+                calculate_work(&bits_to_target(bits)).to_be_bytes(),
+                reference_target(bits).to_work().to_be_bytes(),
+                "bits {:08x}",
+                bits
+            );
+        }
+        // 2^256 / (target + 1), as in Bitcoin Core.
+        assert_eq!(calculate_work(&[0; 32]), U256::ZERO);
+        assert_eq!(
+            calculate_work(&U256::ONE.to_be_bytes()),
+            U256::ONE.shl_vartime(255)
+        );
+        assert_eq!(
+            calculate_work(&bits_to_target(0x037fffff)),
+            U256::ONE.shl_vartime(233)
+        );
+        assert_eq!(
+            calculate_work(&TESTNET4_CONSTANTS.max_target_bytes),
+            U256::from_be_hex("0000000000000000000000000000000000000000000000000000000100010001")
+        );
+    }
+
+    /// The total work of the headers and the bits after them, as computed by the `bitcoin` crate.
+    fn reference_work(headers: &[CircuitBlockHeader]) -> [u8; 32] {
+        headers
+            .iter()
+            .map(|header| Into::<Header>::into(header.clone()).work())
+            .reduce(|total, work| total + work)
+            .unwrap()
+            .to_be_bytes()
+    }
+
+    #[test]
+    fn test_apply_blocks_matches_bitcoin() {
+        const TESTNET4_HEADERS: &[u8] = include_bytes!("../../data/headers/testnet4-headers.bin");
+        let testnet4_headers: Vec<CircuitBlockHeader> = TESTNET4_HEADERS
+            .chunks(80)
+            .map(|header| CircuitBlockHeader::try_from_slice(header).unwrap())
+            .collect();
+        for (headers, params) in [
+            (regtest_headers(), &REGTEST_CONSTANTS),
+            (testnet4_headers, &TESTNET4_CONSTANTS),
+        ] {
+            let mut chain_state = ChainState::new_with_params(params);
+            chain_state.apply_blocks_with_params(headers.clone(), params);
+            assert_eq!(chain_state.total_work, reference_work(&headers));
+        }
+    }
+
+    #[test]
+    fn test_retarget_matches_bitcoin() {
+        // The last block of the epoch is earlier than its first one, which the median time past
+        // allows. The headers are mined on regtest with retargeting.
+        let params = NetworkConstants {
+            pow_no_retargeting: false,
+            ..REGTEST_CONSTANTS
+        };
+        let epoch_start_time = 1_700_000_000;
+        let initial_state = ChainState {
+            block_height: 2014,
+            total_work: [0; 32],
+            best_block_hash: [0x11; 32],
+            current_target_bits: 0x1e1fffff,
+            epoch_start_time,
+            prev_11_timestamps: [epoch_start_time - 20_000; 11],
+            block_hashes_mmr: MMRGuest::new(),
+        };
+        let last_header = CircuitBlockHeader {
+            version: 4,
+            prev_block_hash: [0x11; 32],
+            merkle_root: [0x22; 32],
+            time: epoch_start_time - 5000,
+            bits: 0x1e1fffff,
+            nonce: 206113,
+        };
+        let mut reference_params = Params::REGTEST;
+        reference_params.no_pow_retargeting = false;
+        let next_bits = reference_next_bits(
+            0x1e1fffff,
+            epoch_start_time,
+            last_header.time,
+            &reference_params,
+        );
+        assert_eq!(next_bits, 0x1e07ffff);
+        let next_header = CircuitBlockHeader {
+            version: 4,
+            prev_block_hash: last_header.compute_block_hash(),
+            merkle_root: [0x33; 32],
+            time: epoch_start_time - 4400,
+            bits: next_bits,
+            nonce: 1531377,
+        };
+        let headers = vec![last_header, next_header];
+
+        let mut chain_state = initial_state.clone();
+        chain_state.apply_blocks_with_params(headers.clone(), &params);
+        assert_eq!(chain_state.current_target_bits, next_bits);
+        // The block after the retarget is credited with the work of its bits, not of the new
+        // target before rounding, so the total work does not depend on how blocks are batched.
+        assert_eq!(chain_state.total_work, reference_work(&headers));
+        let mut one_by_one = initial_state;
+        for header in headers {
+            one_by_one.apply_blocks_with_params(vec![header], &params);
+        }
+        assert_eq!(one_by_one, chain_state);
+    }
+
+    /// Testnet3 with a lower minimum difficulty, so that its blocks are cheap to mine, but high
+    /// enough for its retargets not to overflow.
+    static LOWERED_TESTNET3: LazyLock<NetworkConstants> =
+        LazyLock::new(|| lowered_difficulty(&TESTNET3_CONSTANTS, 0x1e1fffff));
+
+    /// The state before the last block of an epoch, whose previous blocks are 6000 seconds earlier
+    /// than its start.
+    fn state_before_retarget(current_target_bits: u32, epoch_start_time: u32) -> ChainState {
+        ChainState {
+            block_height: 2014,
+            total_work: [0; 32],
+            best_block_hash: [0x11; 32],
+            current_target_bits,
+            epoch_start_time,
+            prev_11_timestamps: [epoch_start_time - 6000; 11],
+            block_hashes_mmr: MMRGuest::new(),
+        }
+    }
+
+    #[test]
+    fn test_testnet3_block_storm() {
+        // The last block of the epoch is more than 20 minutes late, so it uses the minimum
+        // difficulty. Testnet3 retargets from its bits, which resets the difficulty of the next
+        // epoch to near the minimum, while BIP94 retargets from the target of the epoch.
+        let params = &*LOWERED_TESTNET3;
+        let bip94_params = NetworkConstants {
+            enforce_bip94: true,
+            genesis_block_header: params.genesis_block_header.clone(),
+            ..*params
+        };
+        let epoch_start_time = 1_700_000_000;
+        let initial_state = state_before_retarget(0x1c0fffff, epoch_start_time);
+        let late = |builder: &ChainBuilder| BlockSpec {
+            time: Some(
+                builder.chain_state().prev_11_timestamps
+                    [builder.chain_state().block_height as usize % 11]
+                    + 1201,
+            ),
+            ..Default::default()
+        };
+
+        let mut builder = ChainBuilder::from_state(initial_state.clone(), params);
+        builder.push(&late(&builder));
+        let last_header = builder.headers()[0].clone();
+        assert_eq!(last_header.bits, 0x1e1fffff);
+        assert_eq!(builder.chain_state().current_target_bits, 0x1e07ffff);
+
+        let mut bip94_builder = ChainBuilder::from_state(initial_state, &bip94_params);
+        bip94_builder.push_header(last_header);
+        assert_eq!(bip94_builder.chain_state().current_target_bits, 0x1c03ffff);
+
+        // The first block of an epoch never uses the minimum difficulty, however late it is.
+        let late_epoch_block = builder.mine_next(&BlockSpec {
+            bits: Some(0x1e1fffff),
+            ..late(&builder)
+        });
+        assert_eq!(
+            builder
+                .chain_state()
+                .clone()
+                .try_apply_block(&late_epoch_block, params),
+            Err(BlockHeaderError::UnexpectedBits {
+                expected: 0x1e07ffff,
+                actual: 0x1e1fffff
+            })
+        );
+
+        // Blocks in time use the target of the epoch again, and late ones, such as those of a
+        // block storm, the minimum difficulty.
+        builder.extend(2);
+        for _ in 0..3 {
+            builder.push(&late(&builder));
+        }
+        builder.extend(1);
+        let bits: Vec<u32> = builder.headers().iter().map(|header| header.bits).collect();
+        assert_eq!(
+            bits,
+            [0x1e1fffff, 0x1e07ffff, 0x1e07ffff, 0x1e1fffff, 0x1e1fffff, 0x1e1fffff, 0x1e07ffff]
+        );
+        assert_eq!(builder.chain_state().block_height, 2021);
+        assert_eq!(
+            builder.chain_state().epoch_start_time,
+            builder.headers()[1].time
+        );
+        assert_eq!(builder.chain_state().current_target_bits, 0x1e07ffff);
+    }
+
+    #[test]
+    fn test_bip94_timewarp() {
+        // Under BIP94, the first block of an epoch may be at most 10 minutes earlier than the
+        // last block of the previous one. Testnet3 does not enforce it.
+        let params = &*LOWERED_TESTNET3;
+        let bip94_params = NetworkConstants {
+            enforce_bip94: true,
+            genesis_block_header: params.genesis_block_header.clone(),
+            ..*params
+        };
+        let epoch_start_time = 1_700_000_000;
+        let initial_state = state_before_retarget(0x1e1fffff, epoch_start_time);
+        let last = BlockSpec {
+            time: Some(epoch_start_time - 5000),
+            ..Default::default()
+        };
+
+        let mut bip94_builder = ChainBuilder::from_state(initial_state.clone(), &bip94_params);
+        bip94_builder.push(&last);
+        let earliest = bip94_builder.mine_next(&BlockSpec {
+            time: Some(epoch_start_time - 5600),
+            ..Default::default()
+        });
+        let too_early = bip94_builder.mine_next(&BlockSpec {
+            time: Some(epoch_start_time - 5601),
+            ..Default::default()
+        });
+        let mut builder = ChainBuilder::from_state(initial_state, params);
+        builder.push(&last);
+        assert_eq!(builder.headers(), bip94_builder.headers());
+
+        for (builder, params, header, is_valid) in [
+            (&bip94_builder, &bip94_params, &earliest, true),
+            (&bip94_builder, &bip94_params, &too_early, false),
+            (&builder, params, &too_early, true),
+        ] {
+            assert_eq!(
+                builder
+                    .chain_state()
+                    .clone()
+                    .try_apply_block(header, params),
+                if is_valid {
+                    Ok(())
+                } else {
+                    Err(BlockHeaderError::TimewarpTimestamp)
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn test_signet_constants() {
+        let public =
+            signet_constants(&DEFAULT_SIGNET_CHALLENGE, SIGNET_CONSTANTS.max_bits).unwrap();
+        assert_eq!(public.magic, SIGNET_CONSTANTS.magic);
+        assert_eq!(public.max_bits, SIGNET_CONSTANTS.max_bits);
+        assert_eq!(public.max_target, SIGNET_CONSTANTS.max_target);
+        assert_eq!(public.max_target_bytes, SIGNET_CONSTANTS.max_target_bytes);
+
+        // A signet whose blocks need no signature (OP_TRUE), with the pow limit of regtest.
+        let private = signet_constants(&[0x51], 0x207fffff).unwrap();
+        assert_eq!(private.magic, [84, 210, 111, 189]);
+        assert_eq!(private.max_target_bytes, REGTEST_CONSTANTS.max_target_bytes);
+        assert_eq!(
+            private.genesis_block_hash,
+            SIGNET_CONSTANTS.genesis_block_hash
+        );
+
+        // The first epoch keeps the bits of the genesis block, whatever the pow limit.
+        const SIGNET_HEADERS: &[u8] = include_bytes!("../../data/headers/signet-headers.bin");
+        let headers: Vec<CircuitBlockHeader> = SIGNET_HEADERS
+            .chunks(80)
+            .map(|header| CircuitBlockHeader::try_from_slice(header).unwrap())
+            .collect();
+        let mut chain_state = ChainState::new_with_params(&private);
+        chain_state.apply_blocks_with_params(headers.clone(), &private);
+        let mut public_state = ChainState::new_with_params(&SIGNET_CONSTANTS);
+        public_state.apply_blocks_with_params(headers, &SIGNET_CONSTANTS);
+        assert_eq!(chain_state, public_state);
+
+        assert_eq!(
+            signet_constants(&[0x51], 0x1d00ffff).unwrap_err(),
+            SignetParamsError::PowLimitBelowGenesis
+        );
+        assert_eq!(
+            signet_constants(&[0x51], 0x04923456).unwrap_err(),
+            SignetParamsError::PowLimit(CompactTargetError::Negative)
+        );
+
+        assert_eq!(
+            signet_constants_from_hex(None, None).unwrap().magic,
+            public.magic
+        );
+        let from_hex = signet_constants_from_hex(Some("51"), Some("0x207fffff")).unwrap();
+        assert_eq!(from_hex.magic, private.magic);
+        assert_eq!(from_hex.max_bits, private.max_bits);
+        assert_eq!(
+            signet_constants_from_hex(Some("5"), None).unwrap_err(),
+            SignetParamsError::ChallengeNotHex
+        );
+        assert_eq!(
+            signet_constants_from_hex(None, Some("bits")).unwrap_err(),
+            SignetParamsError::PowLimitNotHex
+        );
+    }
 }
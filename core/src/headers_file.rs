@@ -0,0 +1,440 @@
+//! A versioned file format for datasets of consecutive block headers.
+//!
+//! Legacy files are bare concatenations of 80-byte headers from genesis. Versioned files start
+//! with a 50-byte preamble (integers little-endian):
+//!
+//! | bytes | field                                  |
+//! |-------|----------------------------------------|
+//! | 4     | `HEADERS_FILE_MAGIC`                   |
+//! | 2     | version, `HEADERS_FILE_VERSION`        |
+//! | 4     | P2P magic of the network               |
+//! | 4     | height of the first header             |
+//! | 4     | number of headers                      |
+//! | 32    | rolling checksum, see `checksum`       |
+//!
+//! followed by the 80-byte headers.
+
+use borsh::BorshDeserialize;
+use sha2::{Digest, Sha256};
+
+use crate::header_chain::{network_constants, network_name, CircuitBlockHeader};
+
+pub const HEADERS_FILE_MAGIC: [u8; 4] = *b"BHDR";
+
+/// The current version of the headers file format.
+pub const HEADERS_FILE_VERSION: u16 = 1;
+
+const PREAMBLE_LEN: usize = 50;
+
+const HEADER_LEN: usize = 80;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeadersFileError {
+    Malformed(String),
+    UnsupportedVersion(u16),
+    WrongNetwork {
+        expected: [u8; 4],
+        actual: [u8; 4],
+    },
+    ChecksumMismatch,
+    /// The header at `height` does not build on the previous one.
+    BrokenLink {
+        height: u32,
+    },
+    /// The header at `height` differs from the one already in the file.
+    Conflict {
+        height: u32,
+    },
+    /// Appended headers start after the end of the file.
+    Gap {
+        end_height: u32,
+        start_height: u32,
+    },
+}
+
+impl core::fmt::Display for HeadersFileError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            HeadersFileError::Malformed(e) => write!(f, "malformed headers file: {}", e),
+            HeadersFileError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported headers file version {} (expected {})",
+                version, HEADERS_FILE_VERSION
+            ),
+            HeadersFileError::WrongNetwork { expected, actual } => write!(
+                f,
+                "the headers are for {}, not {}",
+                network_name(*actual).unwrap_or("an unknown network"),
+                network_name(*expected).unwrap_or("an unknown network")
+            ),
+            HeadersFileError::ChecksumMismatch => write!(f, "the headers checksum does not match"),
+            HeadersFileError::BrokenLink { height } => write!(
+                f,
+                "the header at height {} does not build on the previous one",
+                height
+            ),
+            HeadersFileError::Conflict { height } => write!(
+                f,
+                "the header at height {} differs from the one in the file",
+                height
+            ),
+            HeadersFileError::Gap {
+                end_height,
+                start_height,
+            } => write!(
+                f,
+                "the headers start at height {}, but the file ends before height {}",
+                start_height, end_height
+            ),
+        }
+    }
+}
+
+impl std::error::Error for HeadersFileError {}
+
+/// Consecutive block headers of a network, starting at `start_height`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeadersFile {
+    pub network_magic: [u8; 4],
+    pub start_height: u32,
+    pub headers: Vec<CircuitBlockHeader>,
+}
+
+impl HeadersFile {
+    pub fn new(
+        network_magic: [u8; 4],
+        start_height: u32,
+        headers: Vec<CircuitBlockHeader>,
+    ) -> Self {
+        HeadersFile {
+            network_magic,
+            start_height,
+            headers,
+        }
+    }
+
+    /// The height after the last header.
+    pub fn end_height(&self) -> u32 {
+        self.start_height + self.headers.len() as u32
+    }
+
+    /// The rolling checksum of the headers: starting from the SHA256 of the network magic and the
+    /// start height, each header is hashed with the checksum so far. Appending headers only needs
+    /// the previous checksum.
+    pub fn checksum(&self) -> [u8; 32] {
+        let mut checksum: [u8; 32] = Sha256::new()
+            .chain_update(self.network_magic)
+            .chain_update(self.start_height.to_le_bytes())
+            .finalize()
+            .into();
+        for header in &self.headers {
+            checksum = Sha256::new()
+                .chain_update(checksum)
+                .chain_update(borsh::to_vec(header).expect("Serialization to vec is infallible"))
+                .finalize()
+                .into();
+        }
+        checksum
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(PREAMBLE_LEN + self.headers.len() * HEADER_LEN);
+        bytes.extend_from_slice(&HEADERS_FILE_MAGIC);
+        bytes.extend_from_slice(&HEADERS_FILE_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&self.network_magic);
+        bytes.extend_from_slice(&self.start_height.to_le_bytes());
+        bytes.extend_from_slice(&(self.headers.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.checksum());
+        for header in &self.headers {
+            borsh::to_writer(&mut bytes, header).expect("Serialization to vec is infallible");
+        }
+        bytes
+    }
+
+    /// Whether the bytes are a versioned headers file rather than a legacy one.
+    pub fn is_versioned(bytes: &[u8]) -> bool {
+        bytes.starts_with(&HEADERS_FILE_MAGIC)
+    }
+
+    /// Decodes a versioned headers file and checks its checksum.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, HeadersFileError> {
+        if bytes.len() < PREAMBLE_LEN {
+            return Err(HeadersFileError::Malformed(format!(
+                "{} bytes is shorter than the preamble",
+                bytes.len()
+            )));
+        }
+        if !Self::is_versioned(bytes) {
+            return Err(HeadersFileError::Malformed("missing magic".to_string()));
+        }
+        let version = u16::from_le_bytes(bytes[4..6].try_into().unwrap());
+        if version != HEADERS_FILE_VERSION {
+            return Err(HeadersFileError::UnsupportedVersion(version));
+        }
+        let network_magic = bytes[6..10].try_into().unwrap();
+        let start_height = u32::from_le_bytes(bytes[10..14].try_into().unwrap());
+        let count = u32::from_le_bytes(bytes[14..18].try_into().unwrap());
+        let checksum: [u8; 32] = bytes[18..50].try_into().unwrap();
+        let body = &bytes[PREAMBLE_LEN..];
+        if body.len() != count as usize * HEADER_LEN {
+            return Err(HeadersFileError::Malformed(format!(
+                "{} bytes of headers for a count of {}",
+                body.len(),
+                count
+            )));
+        }
+        if start_height.checked_add(count).is_none() {
+            return Err(HeadersFileError::Malformed(
+                "the headers overflow the height".to_string(),
+            ));
+        }
+
+        let file = HeadersFile::new(network_magic, start_height, decode_headers(body)?);
+        if file.checksum() != checksum {
+            return Err(HeadersFileError::ChecksumMismatch);
+        }
+        Ok(file)
+    }
+
+    /// Decodes a legacy file of bare 80-byte headers starting at `start_height`.
+    pub fn from_legacy(
+        bytes: &[u8],
+        network_magic: [u8; 4],
+        start_height: u32,
+    ) -> Result<Self, HeadersFileError> {
+        Ok(HeadersFile::new(
+            network_magic,
+            start_height,
+            decode_headers(bytes)?,
+        ))
+    }
+
+    /// Decodes a versioned headers file of the network with the given magic, or a legacy file,
+    /// which starts at genesis.
+    pub fn decode(bytes: &[u8], network_magic: [u8; 4]) -> Result<Self, HeadersFileError> {
+        let file = if Self::is_versioned(bytes) {
+            Self::from_bytes(bytes)?
+        } else {
+            Self::from_legacy(bytes, network_magic, 0)?
+        };
+        if file.network_magic != network_magic {
+            return Err(HeadersFileError::WrongNetwork {
+                expected: network_magic,
+                actual: file.network_magic,
+            });
+        }
+        Ok(file)
+    }
+
+    /// Checks that each header builds on the previous one, and that a file from height 0 starts
+    /// with the genesis block of a known network. The proof of work is not checked.
+    pub fn validate_linkage(&self) -> Result<(), HeadersFileError> {
+        if self.start_height == 0 {
+            let genesis = network_name(self.network_magic)
+                .and_then(network_constants)
+                .map(|constants| constants.genesis_block_hash);
+            if let (Some(genesis), Some(first)) = (genesis, self.headers.first()) {
+                if first.compute_block_hash() != genesis {
+                    return Err(HeadersFileError::BrokenLink { height: 0 });
+                }
+            }
+        }
+        for (i, pair) in self.headers.windows(2).enumerate() {
+            if pair[1].prev_block_hash != pair[0].compute_block_hash() {
+                return Err(HeadersFileError::BrokenLink {
+                    height: self.start_height + i as u32 + 1,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Appends the headers that start at `start_height` and are not in the file yet. Headers that
+    /// overlap the file must match it, and new ones must build on the last header. Returns the
+    /// number of headers appended.
+    pub fn append(
+        &mut self,
+        start_height: u32,
+        headers: &[CircuitBlockHeader],
+    ) -> Result<usize, HeadersFileError> {
+        let end_height = self.end_height();
+        if start_height > end_height {
+            return Err(HeadersFileError::Gap {
+                end_height,
+                start_height,
+            });
+        }
+        if start_height < self.start_height {
+            return Err(HeadersFileError::Malformed(format!(
+                "the headers start at height {}, before the file at height {}",
+                start_height, self.start_height
+            )));
+        }
+        let overlap = ((end_height - start_height) as usize).min(headers.len());
+        let offset = (start_height - self.start_height) as usize;
+        for (i, header) in headers[..overlap].iter().enumerate() {
+            if *header != self.headers[offset + i] {
+                return Err(HeadersFileError::Conflict {
+                    height: start_height + i as u32,
+                });
+            }
+        }
+
+        let mut prev_block_hash = self
+            .headers
+            .last()
+            .map(|header| header.compute_block_hash());
+        for (i, header) in headers[overlap..].iter().enumerate() {
+            if prev_block_hash.is_some_and(|hash| hash != header.prev_block_hash) {
+                self.headers
+                    .truncate((end_height - self.start_height) as usize);
+                return Err(HeadersFileError::BrokenLink {
+                    height: end_height + i as u32,
+                });
+            }
+            prev_block_hash = Some(header.compute_block_hash());
+            self.headers.push(header.clone());
+        }
+        Ok(headers.len() - overlap)
+    }
+}
+
+fn decode_headers(bytes: &[u8]) -> Result<Vec<CircuitBlockHeader>, HeadersFileError> {
+    if bytes.len() % HEADER_LEN != 0 {
+        return Err(HeadersFileError::Malformed(format!(
+            "{} bytes is not a multiple of {}",
+            bytes.len(),
+            HEADER_LEN
+        )));
+    }
+    Ok(bytes
+        .chunks(HEADER_LEN)
+        .map(|header| CircuitBlockHeader::try_from_slice(header).expect("Headers are 80 bytes"))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header_chain::{MAINNET_CONSTANTS, REGTEST_CONSTANTS};
+
+    const REGTEST_HEADERS: &[u8] = include_bytes!("../../data/headers/regtest-headers.bin");
+
+    fn regtest_file() -> HeadersFile {
+        HeadersFile::from_legacy(REGTEST_HEADERS, REGTEST_CONSTANTS.magic, 0).unwrap()
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let file = regtest_file();
+        file.validate_linkage().unwrap();
+        let bytes = file.to_bytes();
+        assert_eq!(bytes.len(), 50 + REGTEST_HEADERS.len());
+        assert_eq!(bytes[50..], *REGTEST_HEADERS);
+        assert_eq!(HeadersFile::from_bytes(&bytes).unwrap(), file);
+        assert_eq!(
+            HeadersFile::decode(&bytes, REGTEST_CONSTANTS.magic).unwrap(),
+            file
+        );
+        // Legacy files are decoded from genesis.
+        assert_eq!(
+            HeadersFile::decode(REGTEST_HEADERS, REGTEST_CONSTANTS.magic).unwrap(),
+            file
+        );
+    }
+
+    #[test]
+    fn test_corrupted_file() {
+        let bytes = regtest_file().to_bytes();
+
+        let mut corrupted = bytes.clone();
+        corrupted[100] ^= 1;
+        assert_eq!(
+            HeadersFile::from_bytes(&corrupted),
+            Err(HeadersFileError::ChecksumMismatch)
+        );
+        // The start height is covered by the checksum.
+        let mut corrupted = bytes.clone();
+        corrupted[10] = 1;
+        assert_eq!(
+            HeadersFile::from_bytes(&corrupted),
+            Err(HeadersFileError::ChecksumMismatch)
+        );
+        let mut corrupted = bytes.clone();
+        corrupted[4] = 2;
+        assert_eq!(
+            HeadersFile::from_bytes(&corrupted),
+            Err(HeadersFileError::UnsupportedVersion(2))
+        );
+        assert!(matches!(
+            HeadersFile::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(HeadersFileError::Malformed(_))
+        ));
+        assert!(matches!(
+            HeadersFile::decode(&REGTEST_HEADERS[..79], REGTEST_CONSTANTS.magic),
+            Err(HeadersFileError::Malformed(_))
+        ));
+        assert!(matches!(
+            HeadersFile::decode(&bytes, MAINNET_CONSTANTS.magic),
+            Err(HeadersFileError::WrongNetwork { .. })
+        ));
+    }
+
+    #[test]
+    fn test_validate_linkage() {
+        let mut file = regtest_file();
+        file.headers.remove(5);
+        assert_eq!(
+            file.validate_linkage(),
+            Err(HeadersFileError::BrokenLink { height: 5 })
+        );
+
+        // A file from genesis must start with the genesis block of its network.
+        let file = HeadersFile::new(MAINNET_CONSTANTS.magic, 0, regtest_file().headers);
+        assert_eq!(
+            file.validate_linkage(),
+            Err(HeadersFileError::BrokenLink { height: 0 })
+        );
+        let file = HeadersFile::new(
+            REGTEST_CONSTANTS.magic,
+            10,
+            regtest_file().headers[10..].to_vec(),
+        );
+        file.validate_linkage().unwrap();
+    }
+
+    #[test]
+    fn test_append() {
+        let headers = regtest_file().headers;
+        let mut file = HeadersFile::new(REGTEST_CONSTANTS.magic, 0, headers[..10].to_vec());
+
+        // Overlapping headers are skipped.
+        assert_eq!(file.append(5, &headers[5..20]), Ok(10));
+        assert_eq!(file.append(0, &headers[..20]), Ok(0));
+        assert_eq!(file.headers, headers[..20]);
+        // The checksum of the appended file is that of the file with all the headers.
+        assert_eq!(
+            file.checksum(),
+            HeadersFile::new(REGTEST_CONSTANTS.magic, 0, headers[..20].to_vec()).checksum()
+        );
+
+        assert_eq!(
+            file.append(21, &headers[21..30]),
+            Err(HeadersFileError::Gap {
+                end_height: 20,
+                start_height: 21
+            })
+        );
+        assert_eq!(
+            file.append(19, &headers[20..30]),
+            Err(HeadersFileError::Conflict { height: 19 })
+        );
+        let mut unlinked = headers[20..30].to_vec();
+        unlinked.remove(3);
+        assert_eq!(
+            file.append(20, &unlinked),
+            Err(HeadersFileError::BrokenLink { height: 23 })
+        );
+        assert_eq!(file.headers, headers[..20]);
+    }
+}
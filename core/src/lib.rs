@@ -1,9 +1,13 @@
 pub mod final_circuit;
 pub mod header_chain;
+pub mod headers_file;
 pub mod merkle_tree;
 pub mod mmr_guest;
 pub mod mmr_native;
+pub mod serde_utils;
 pub mod spv;
+#[cfg(any(test, feature = "synthetic"))]
+pub mod synthetic;
 pub mod transaction;
 pub mod utils;
 pub mod zkvm;
@@ -101,6 +101,7 @@ impl BitcoinMerkleTree {
 #[derive(Serialize, Deserialize, Eq, PartialEq, Clone, Debug, BorshDeserialize, BorshSerialize)]
 pub struct BlockInclusionProof {
     idx: u32,
+    #[serde(with = "crate::serde_utils::display_hashes")]
     merkle_proof: Vec<[u8; 32]>,
 }
 
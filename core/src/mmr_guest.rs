@@ -7,6 +7,7 @@ use crate::{mmr_native::MMRInclusionProof, utils::hash_pair};
 #[derive(Serialize, Deserialize, Eq, PartialEq, Clone, Debug, BorshDeserialize, BorshSerialize)]
 
 pub struct MMRGuest {
+    #[serde(with = "crate::serde_utils::hex_hashes")]
     pub subroots: Vec<[u8; 32]>,
     pub size: u32,
 }
@@ -55,6 +56,29 @@ impl MMRGuest {
     //     calculate_sha256(&preimage)
     // }
 
+    /// Returns the index of the leaf an inclusion proof is for, or `None` if the proof does not
+    /// have the shape of a proof for a leaf of the current MMR.
+    pub fn leaf_index(&self, mmr_proof: &MMRInclusionProof) -> Option<u32> {
+        let mut offset = 0u32;
+        let mut subroot_idx = 0;
+        for height in (0..32).rev() {
+            if self.size & (1 << height) == 0 {
+                continue;
+            }
+            if subroot_idx == mmr_proof.subroot_idx {
+                if mmr_proof.inclusion_proof.len() != height as usize
+                    || mmr_proof.internal_idx >= 1 << height
+                {
+                    return None;
+                }
+                return Some(offset + mmr_proof.internal_idx);
+            }
+            offset += 1 << height;
+            subroot_idx += 1;
+        }
+        None
+    }
+
     /// Verifies an inclusion proof against the current MMR root
     pub fn verify_proof(&self, leaf: [u8; 32], mmr_proof: &MMRInclusionProof) -> bool {
         println!("GUEST: mmr_proof: {:?}", mmr_proof);
@@ -152,6 +152,7 @@ impl MMRNative {
 pub struct MMRInclusionProof {
     pub subroot_idx: usize,
     pub internal_idx: u32,
+    #[serde(with = "crate::serde_utils::hex_hashes")]
     pub inclusion_proof: Vec<[u8; 32]>,
 }
 
@@ -267,7 +268,34 @@ mod tests {
                     "Failed to verify proof for leaf {} in guest MMR",
                     j
                 );
+                assert_eq!(mmr_guest.leaf_index(&mmr_proof), Some(j));
             }
         }
     }
+
+    #[test]
+    fn test_mmr_leaf_index_malformed_proof() {
+        let mut mmr_native = MMRNative::new();
+        let mut mmr_guest = MMRGuest::new();
+        for i in 0..13 {
+            mmr_native.append([i as u8; 32]);
+            mmr_guest.append([i as u8; 32]);
+        }
+
+        // 13 = 8 + 4 + 1, leaf 9 is in the second subtree.
+        let (_, mmr_proof) = mmr_native.generate_proof(9);
+        assert_eq!(mmr_guest.leaf_index(&mmr_proof), Some(9));
+
+        let mut short_proof = mmr_proof.clone();
+        short_proof.inclusion_proof.pop();
+        assert_eq!(mmr_guest.leaf_index(&short_proof), None);
+
+        let mut wrong_internal_idx = mmr_proof.clone();
+        wrong_internal_idx.internal_idx = 4;
+        assert_eq!(mmr_guest.leaf_index(&wrong_internal_idx), None);
+
+        let mut wrong_subroot = mmr_proof;
+        wrong_subroot.subroot_idx = 3;
+        assert_eq!(mmr_guest.leaf_index(&wrong_subroot), None);
+    }
 }
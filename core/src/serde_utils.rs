@@ -0,0 +1,226 @@
+//! Field (de)serializers for a human-readable serde mode, e.g. JSON.
+//!
+//! Human-readable formats get hashes in hex, block hashes, merkle roots and txids in the reversed
+//! (display) byte order of block explorers, total work as a hex string and target bits as hex.
+//! Binary formats, including the risc0 serde of the guests, keep the raw encoding, so they stay
+//! byte for byte the same as before.
+
+use bitcoin::hex::{DisplayHex, FromHex};
+use crypto_bigint::{CheckedAdd, CheckedMul, Encoding, U256};
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+fn parse_hash<E: Error>(s: &str) -> Result<[u8; 32], E> {
+    <[u8; 32]>::from_hex(s).map_err(|e| E::custom(format!("invalid hash {:?}: {}", s, e)))
+}
+
+/// A hash in internal byte order, serialized in display (reversed) byte order.
+pub mod display_hash {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(hash: &[u8; 32], serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            let mut display = *hash;
+            display.reverse();
+            serializer.serialize_str(&display.to_lower_hex_string())
+        } else {
+            hash.serialize(serializer)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; 32], D::Error> {
+        if deserializer.is_human_readable() {
+            let mut hash = parse_hash(&String::deserialize(deserializer)?)?;
+            hash.reverse();
+            Ok(hash)
+        } else {
+            <[u8; 32]>::deserialize(deserializer)
+        }
+    }
+}
+
+/// Hashes in internal byte order, serialized in display (reversed) byte order.
+pub mod display_hashes {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(hashes: &[[u8; 32]], serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_seq(hashes.iter().map(|hash| {
+                let mut display = *hash;
+                display.reverse();
+                display.to_lower_hex_string()
+            }))
+        } else {
+            hashes.serialize(serializer)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<[u8; 32]>, D::Error> {
+        if deserializer.is_human_readable() {
+            Vec::<String>::deserialize(deserializer)?
+                .iter()
+                .map(|s| {
+                    let mut hash = parse_hash(s)?;
+                    hash.reverse();
+                    Ok(hash)
+                })
+                .collect()
+        } else {
+            Vec::<[u8; 32]>::deserialize(deserializer)
+        }
+    }
+}
+
+/// A hash that has no display byte order, e.g. an MMR node, serialized as plain hex.
+pub mod hex_hash {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(hash: &[u8; 32], serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&hash.to_lower_hex_string())
+        } else {
+            hash.serialize(serializer)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; 32], D::Error> {
+        if deserializer.is_human_readable() {
+            parse_hash(&String::deserialize(deserializer)?)
+        } else {
+            <[u8; 32]>::deserialize(deserializer)
+        }
+    }
+}
+
+/// Hashes that have no display byte order, serialized as plain hex.
+pub mod hex_hashes {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(hashes: &[[u8; 32]], serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_seq(hashes.iter().map(|hash| hash.to_lower_hex_string()))
+        } else {
+            hashes.serialize(serializer)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<[u8; 32]>, D::Error> {
+        if deserializer.is_human_readable() {
+            Vec::<String>::deserialize(deserializer)?
+                .iter()
+                .map(|s| parse_hash(s))
+                .collect()
+        } else {
+            Vec::<[u8; 32]>::deserialize(deserializer)
+        }
+    }
+}
+
+/// Compact target bits, serialized as 8 hex digits, e.g. `"1d00ffff"`.
+pub mod bits {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(bits: &u32, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&format!("{:08x}", bits))
+        } else {
+            bits.serialize(serializer)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+        if deserializer.is_human_readable() {
+            let s = String::deserialize(deserializer)?;
+            let digits = s.strip_prefix("0x").unwrap_or(&s);
+            if digits.len() != 8 {
+                return Err(D::Error::custom(format!("invalid bits {:?}", s)));
+            }
+            u32::from_str_radix(digits, 16)
+                .map_err(|e| D::Error::custom(format!("invalid bits {:?}: {}", s, e)))
+        } else {
+            u32::deserialize(deserializer)
+        }
+    }
+}
+
+/// Parses an amount of work given in hex with a `0x` prefix, or in decimal.
+pub fn parse_work(s: &str) -> Result<[u8; 32], String> {
+    let work = if let Some(digits) = s.strip_prefix("0x") {
+        if digits.is_empty() || digits.len() > 64 {
+            return Err(format!("invalid work {:?}", s));
+        }
+        let mut padded = [b'0'; 64];
+        padded[64 - digits.len()..].copy_from_slice(digits.as_bytes());
+        let padded = core::str::from_utf8(&padded).map_err(|e| e.to_string())?;
+        <[u8; 32]>::from_hex(padded).map_err(|e| format!("invalid work {:?}: {}", s, e))?
+    } else {
+        if s.is_empty() {
+            return Err("empty work".to_string());
+        }
+        let mut work = U256::ZERO;
+        for c in s.chars() {
+            let digit = c
+                .to_digit(10)
+                .ok_or_else(|| format!("invalid work {:?}", s))?;
+            work = Option::<U256>::from(work.checked_mul(&U256::from_u8(10)))
+                .and_then(|work| Option::from(work.checked_add(&U256::from_u32(digit))))
+                .ok_or_else(|| format!("work {:?} does not fit in 256 bits", s))?;
+        }
+        work.to_be_bytes()
+    };
+    Ok(work)
+}
+
+/// Big-endian total work, serialized in hex with a `0x` prefix, e.g. `"0xb000b000b"`. Decimal
+/// strings are accepted too.
+pub mod work {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(work: &[u8; 32], serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            let hex = work.to_lower_hex_string();
+            let digits = hex.trim_start_matches('0');
+            serializer.serialize_str(&format!(
+                "0x{}",
+                if digits.is_empty() { "0" } else { digits }
+            ))
+        } else {
+            work.serialize(serializer)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; 32], D::Error> {
+        if deserializer.is_human_readable() {
+            parse_work(&String::deserialize(deserializer)?).map_err(D::Error::custom)
+        } else {
+            <[u8; 32]>::deserialize(deserializer)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_work() {
+        let mut expected = [0u8; 32];
+        expected[27..].copy_from_slice(&[0x0b, 0x00, 0x0b, 0x00, 0x0b]);
+        assert_eq!(parse_work("0xb000b000b"), Ok(expected));
+        assert_eq!(parse_work("47245361163"), Ok(expected));
+        assert_eq!(parse_work("0x0"), Ok([0; 32]));
+        assert_eq!(parse_work(&format!("0x{}", "f".repeat(64))), Ok([0xff; 32]));
+
+        for invalid in ["", "0x", "0xg", "12a", &format!("0x{}", "f".repeat(65))] {
+            assert!(parse_work(invalid).is_err(), "{:?}", invalid);
+        }
+        // 2^256 overflows.
+        assert!(parse_work(
+            "115792089237316195423570985008687907853269984665640564039457584007913129639936"
+        )
+        .is_err());
+    }
+}
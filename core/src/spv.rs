@@ -3,8 +3,9 @@ use crate::{
     mmr_native::MMRInclusionProof, transaction::CircuitTransaction,
 };
 use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
 
-#[derive(Eq, PartialEq, Clone, Debug, BorshDeserialize, BorshSerialize)]
+#[derive(Serialize, Deserialize, Eq, PartialEq, Clone, Debug, BorshDeserialize, BorshSerialize)]
 pub struct SPV {
     pub transaction: CircuitTransaction,
     pub block_inclusion_proof: BlockInclusionProof,
@@ -138,4 +139,38 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_spv_json_roundtrip() {
+        let mut mmr_native = MMRNative::new();
+        let block_headers = MAINNET_BLOCK_HEADERS
+            .iter()
+            .map(|header| CircuitBlockHeader::try_from_slice(header).unwrap())
+            .collect::<Vec<CircuitBlockHeader>>();
+        for header in &block_headers {
+            mmr_native.append(header.compute_block_hash());
+        }
+        let tx = CircuitTransaction(
+            bitcoin::consensus::deserialize(MAINNET_BLOCK_TRANSACTIONS[5]).unwrap(),
+        );
+        let block_inclusion_proof = BitcoinMerkleTree::new(vec![tx.txid()]).generate_proof(0);
+        let (_, mmr_proof) = mmr_native.generate_proof(5);
+        let spv = SPV::new(
+            tx,
+            block_inclusion_proof,
+            block_headers[5].clone(),
+            mmr_proof,
+        );
+
+        let json = serde_json::to_value(&spv).unwrap();
+        assert_eq!(
+            json["transaction"],
+            hex::encode(MAINNET_BLOCK_TRANSACTIONS[5])
+        );
+        assert_eq!(
+            json["block_header"]["prev_block_hash"],
+            "000000004ebadb55ee9096c9a2f8880e09da59c0d68b1c228da88e48844a1485"
+        );
+        assert_eq!(serde_json::from_value::<SPV>(json).unwrap(), spv);
+    }
 }
@@ -0,0 +1,424 @@
+//! Synthetic header chains for tests and fixtures, behind the `synthetic` feature.
+//!
+//! `ChainBuilder` mines valid headers under any `NetworkConstants`, with scripted timestamps,
+//! versions and bits, and keeps the `ChainState` after every header, checked against a reference
+//! built on the `bitcoin` crate. Mining is cheap at the regtest target, and `lowered_difficulty`
+//! derives such parameters from any network. Note that, like in Bitcoin Core, retargeting scales
+//! the target before dividing, so it overflows once the target times the timespan exceeds 2^256,
+//! e.g. from the regtest target. The generator mines whatever the rules produce then, but the
+//! reference does not model the overflow, so those retargets are not checked.
+
+use crate::header_chain::{
+    bits_to_target, ChainState, CircuitBlockHeader, NetworkConstants, BLOCKS_PER_EPOCH,
+    EXPECTED_EPOCH_TIMESPAN,
+};
+use bitcoin::{
+    block::Header,
+    hashes::Hash,
+    params::Params,
+    pow::{CompactTarget, Target, Work},
+    Network,
+};
+use crypto_bigint::{Encoding, U256};
+
+/// The fields of the next header to script. The others get valid defaults: 10 minutes after the
+/// best block, version 4, the bits the chain expects and a merkle root unique to the block.
+#[derive(Debug, Clone, Default)]
+pub struct BlockSpec {
+    pub time: Option<u32>,
+    pub version: Option<i32>,
+    pub bits: Option<u32>,
+    pub merkle_root: Option<[u8; 32]>,
+}
+
+/// Increments the nonce until the hash of the header meets the target of its bits. The hashes are
+/// computed by the `bitcoin` crate, whose hashing is much faster than the circuit's in tests.
+pub fn mine(header: &mut CircuitBlockHeader) {
+    let target = bits_to_target(header.bits);
+    assert_ne!(target, [0; 32], "No hash meets a zero target");
+    let target = Target::from_be_bytes(target);
+    let mut bitcoin_header: Header = header.clone().into();
+    while !target.is_met_by(bitcoin_header.block_hash()) {
+        bitcoin_header.nonce = bitcoin_header
+            .nonce
+            .checked_add(1)
+            .expect("No nonce meets the target");
+    }
+    header.nonce = bitcoin_header.nonce;
+}
+
+/// The constants of the network with an easier maximum target, and a genesis block mined for it.
+pub fn lowered_difficulty(base: &NetworkConstants, max_bits: u32) -> NetworkConstants {
+    let max_target_bytes = bits_to_target(max_bits);
+    let mut genesis_block_header = CircuitBlockHeader {
+        bits: max_bits,
+        nonce: 0,
+        ..base.genesis_block_header.clone()
+    };
+    mine(&mut genesis_block_header);
+    NetworkConstants {
+        max_bits,
+        max_target: U256::from_be_bytes(max_target_bytes),
+        max_target_bytes,
+        genesis_block_hash: genesis_block_header.compute_block_hash(),
+        genesis_block_header,
+        ..*base
+    }
+}
+
+/// Mines a header chain and records the `ChainState` after each header, which is what applying
+/// the headers must produce.
+pub struct ChainBuilder<'a> {
+    params: &'a NetworkConstants,
+    initial_state: ChainState,
+    headers: Vec<CircuitBlockHeader>,
+    states: Vec<ChainState>,
+    /// Distinguishes the merkle roots of forks.
+    fork_id: u32,
+}
+
+impl<'a> ChainBuilder<'a> {
+    /// Starts a chain with the genesis block of the network.
+    pub fn new(params: &'a NetworkConstants) -> Self {
+        let mut builder = Self::from_state(ChainState::new_with_params(params), params);
+        builder.push_header(params.genesis_block_header.clone());
+        builder
+    }
+
+    /// Continues a chain state, e.g. one crafted just before the end of an epoch.
+    pub fn from_state(chain_state: ChainState, params: &'a NetworkConstants) -> Self {
+        ChainBuilder {
+            params,
+            initial_state: chain_state,
+            headers: vec![],
+            states: vec![],
+            fork_id: 0,
+        }
+    }
+
+    /// The height of the first header of the builder.
+    pub fn start_height(&self) -> u32 {
+        self.initial_state.block_height.wrapping_add(1)
+    }
+
+    pub fn headers(&self) -> &[CircuitBlockHeader] {
+        &self.headers
+    }
+
+    /// The state after the last header.
+    pub fn chain_state(&self) -> &ChainState {
+        self.states.last().unwrap_or(&self.initial_state)
+    }
+
+    /// The state after the header at the height, or the initial state before the first header.
+    pub fn state_at(&self, height: u32) -> &ChainState {
+        if height == self.initial_state.block_height {
+            return &self.initial_state;
+        }
+        &self.states[height.wrapping_sub(self.start_height()) as usize]
+    }
+
+    /// Mines the next header without adding it, e.g. to check that an invalid one is rejected.
+    pub fn mine_next(&self, spec: &BlockSpec) -> CircuitBlockHeader {
+        let chain_state = self.chain_state();
+        let height = chain_state.block_height.wrapping_add(1);
+        let time = spec.time.unwrap_or_else(|| {
+            if chain_state.block_height == u32::MAX {
+                self.params.genesis_block_header.time
+            } else {
+                chain_state.prev_11_timestamps[chain_state.block_height as usize % 11] + 600
+            }
+        });
+        let merkle_root = spec.merkle_root.unwrap_or_else(|| {
+            let mut merkle_root = [0; 32];
+            merkle_root[..4].copy_from_slice(&height.to_le_bytes());
+            merkle_root[4..8].copy_from_slice(&self.fork_id.to_le_bytes());
+            merkle_root
+        });
+        let mut header = CircuitBlockHeader {
+            version: spec.version.unwrap_or(4),
+            prev_block_hash: chain_state.best_block_hash,
+            merkle_root,
+            time,
+            bits: spec
+                .bits
+                .unwrap_or_else(|| chain_state.expected_bits(time, self.params)),
+            nonce: 0,
+        };
+        mine(&mut header);
+        header
+    }
+
+    /// Mines and adds the next header. Panics like the circuit if the spec makes it invalid.
+    pub fn push(&mut self, spec: &BlockSpec) -> &CircuitBlockHeader {
+        let header = self.mine_next(spec);
+        self.push_header(header)
+    }
+
+    /// Mines and adds headers with the default fields.
+    pub fn extend(&mut self, count: usize) -> &mut Self {
+        for _ in 0..count {
+            self.push(&BlockSpec::default());
+        }
+        self
+    }
+
+    /// Adds a header as is. Panics like the circuit if it is invalid, or if the state the circuit
+    /// produces differs from the reference one.
+    pub fn push_header(&mut self, header: CircuitBlockHeader) -> &CircuitBlockHeader {
+        let prev_state = self.chain_state();
+        let mut chain_state = prev_state.clone();
+        chain_state.apply_blocks_with_params(vec![header.clone()], self.params);
+        if let Some(expected) = reference_transition(prev_state, &header, self.params) {
+            assert_eq!(
+                chain_state, expected,
+                "The state after block {} differs from the reference",
+                chain_state.block_height
+            );
+        }
+        self.states.push(chain_state);
+        self.headers.push(header);
+        self.headers.last().unwrap()
+    }
+
+    /// A builder for a competing chain that shares the headers up to the height, for reorgs.
+    pub fn fork(&self, height: u32) -> Self {
+        let len = height.wrapping_sub(self.initial_state.block_height) as usize;
+        ChainBuilder {
+            params: self.params,
+            initial_state: self.initial_state.clone(),
+            headers: self.headers[..len].to_vec(),
+            states: self.states[..len].to_vec(),
+            fork_id: self.fork_id + 1,
+        }
+    }
+}
+
+/// The state after a valid header, computed like Bitcoin Core with the hashes, work and retargets
+/// of the `bitcoin` crate rather than the circuit. Returns `None` for a retarget that overflows.
+fn reference_transition(
+    chain_state: &ChainState,
+    header: &CircuitBlockHeader,
+    params: &NetworkConstants,
+) -> Option<ChainState> {
+    let bitcoin_header: Header = header.clone().into();
+    let block_height = chain_state.block_height.wrapping_add(1);
+    let block_hash = bitcoin_header.block_hash().to_byte_array();
+
+    let mut next = chain_state.clone();
+    next.block_height = block_height;
+    next.best_block_hash = block_hash;
+    next.block_hashes_mmr.append(block_hash);
+    // Core credits every block with the work of its own bits, including minimum difficulty ones.
+    next.total_work =
+        (Work::from_be_bytes(chain_state.total_work) + bitcoin_header.work()).to_be_bytes();
+    next.prev_11_timestamps[block_height as usize % 11] = header.time;
+    if params.pow_no_retargeting {
+        return Some(next);
+    }
+    if block_height % BLOCKS_PER_EPOCH == 0 {
+        next.epoch_start_time = header.time;
+    }
+    if block_height % BLOCKS_PER_EPOCH == BLOCKS_PER_EPOCH - 1 {
+        // BIP94 retargets the bits of the epoch, Core before it those of the last block.
+        let last_bits = if params.enforce_bip94 {
+            chain_state.current_target_bits
+        } else {
+            header.bits
+        };
+        let timespan = (header.time as i64 - next.epoch_start_time as i64).clamp(
+            EXPECTED_EPOCH_TIMESPAN as i64 / 4,
+            EXPECTED_EPOCH_TIMESPAN as i64 * 4,
+        ) as u64;
+        let last_target = U256::from_be_bytes(bits_to_target(last_bits));
+        if last_target > U256::MAX.wrapping_div(&U256::from(timespan)) {
+            return None;
+        }
+        let mut bitcoin_params = Params::new(Network::Regtest);
+        bitcoin_params.max_attainable_target = Target::from_be_bytes(params.max_target_bytes);
+        bitcoin_params.pow_target_timespan = EXPECTED_EPOCH_TIMESPAN as u64;
+        bitcoin_params.no_pow_retargeting = false;
+        next.current_target_bits = CompactTarget::from_next_work_required(
+            CompactTarget::from_consensus(last_bits),
+            timespan,
+            &bitcoin_params,
+        )
+        .to_consensus();
+    }
+    Some(next)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        header_chain::{REGTEST_CONSTANTS, TESTNET4_CONSTANTS},
+        mmr_guest::MMRGuest,
+    };
+
+    fn apply(headers: &[CircuitBlockHeader], params: &NetworkConstants) -> ChainState {
+        let mut chain_state = ChainState::new_with_params(params);
+        chain_state.apply_blocks_with_params(headers.to_vec(), params);
+        chain_state
+    }
+
+    fn is_rejected(builder: &ChainBuilder, header: CircuitBlockHeader) -> bool {
+        let mut chain_state = builder.chain_state().clone();
+        chain_state
+            .try_apply_block(&header, builder.params)
+            .is_err()
+    }
+
+    #[test]
+    fn test_regtest_chain() {
+        let mut builder = ChainBuilder::new(&REGTEST_CONSTANTS);
+        builder.extend(50);
+        let time = builder.chain_state().prev_11_timestamps[50 % 11] + 7;
+        builder.push(&BlockSpec {
+            time: Some(time),
+            version: Some(0x20000000),
+            ..Default::default()
+        });
+        builder.extend(10);
+
+        assert_eq!(builder.headers().len(), 62);
+        assert_eq!(builder.headers()[51].time, time);
+        assert_eq!(builder.headers()[51].version, 0x20000000);
+        assert_eq!(
+            &apply(builder.headers(), &REGTEST_CONSTANTS),
+            builder.chain_state()
+        );
+        assert_eq!(
+            &apply(&builder.headers()[..21], &REGTEST_CONSTANTS),
+            builder.state_at(20)
+        );
+    }
+
+    #[test]
+    fn test_invalid_specs_rejected() {
+        let mut builder = ChainBuilder::new(&REGTEST_CONSTANTS);
+        builder.extend(20);
+        // The median of the last 11 timestamps, which the next block must be later than.
+        let median_time = builder.headers()[15].time;
+        for spec in [
+            BlockSpec {
+                time: Some(median_time),
+                ..Default::default()
+            },
+            BlockSpec {
+                version: Some(1),
+                ..Default::default()
+            },
+            BlockSpec {
+                bits: Some(0x2000ffff),
+                ..Default::default()
+            },
+        ] {
+            assert!(is_rejected(&builder, builder.mine_next(&spec)));
+        }
+        assert!(!is_rejected(
+            &builder,
+            builder.mine_next(&BlockSpec {
+                time: Some(median_time + 1),
+                ..Default::default()
+            })
+        ));
+    }
+
+    #[test]
+    fn test_min_difficulty_blocks() {
+        let params = lowered_difficulty(&TESTNET4_CONSTANTS, 0x207fffff);
+        assert_eq!(
+            &apply(std::slice::from_ref(&params.genesis_block_header), &params).best_block_hash,
+            &params.genesis_block_hash
+        );
+
+        // A state in the middle of an epoch whose target is harder than the maximum one.
+        let time = params.genesis_block_header.time;
+        let chain_state = ChainState {
+            block_height: 100,
+            total_work: [0; 32],
+            best_block_hash: [1; 32],
+            current_target_bits: 0x2000ffff,
+            epoch_start_time: time,
+            prev_11_timestamps: [time; 11],
+            block_hashes_mmr: MMRGuest::new(),
+        };
+        let mut builder = ChainBuilder::from_state(chain_state, &params);
+        builder.extend(1);
+        let late = BlockSpec {
+            time: Some(time + 600 + 1201),
+            ..Default::default()
+        };
+        assert!(is_rejected(
+            &builder,
+            builder.mine_next(&BlockSpec {
+                bits: Some(0x2000ffff),
+                ..late.clone()
+            })
+        ));
+        builder.push(&late);
+        builder.extend(1);
+
+        let bits: Vec<u32> = builder.headers().iter().map(|header| header.bits).collect();
+        assert_eq!(bits, [0x2000ffff, 0x207fffff, 0x2000ffff]);
+        assert_eq!(builder.chain_state().current_target_bits, 0x2000ffff);
+        assert_eq!(builder.start_height(), 101);
+    }
+
+    #[test]
+    fn test_retarget_matches_reference() {
+        // The last block of the epoch is earlier than its first one, and the target is low enough
+        // for the retarget not to overflow.
+        let params = NetworkConstants {
+            pow_no_retargeting: false,
+            ..REGTEST_CONSTANTS
+        };
+        let time = params.genesis_block_header.time;
+        let chain_state = ChainState {
+            block_height: 2014,
+            total_work: [0; 32],
+            best_block_hash: [1; 32],
+            current_target_bits: 0x1e1fffff,
+            epoch_start_time: time,
+            prev_11_timestamps: [time - 20_000; 11],
+            block_hashes_mmr: MMRGuest::new(),
+        };
+        let mut builder = ChainBuilder::from_state(chain_state.clone(), &params);
+        builder.push(&BlockSpec {
+            time: Some(time - 5000),
+            ..Default::default()
+        });
+        assert_eq!(
+            reference_transition(&chain_state, &builder.headers()[0], &params).as_ref(),
+            Some(builder.chain_state())
+        );
+        assert_ne!(builder.chain_state().current_target_bits, 0x1e1fffff);
+
+        // The retarget from the regtest target overflows, which the reference does not model.
+        let chain_state = ChainState {
+            current_target_bits: 0x207fffff,
+            ..chain_state
+        };
+        let header =
+            ChainBuilder::from_state(chain_state.clone(), &params).mine_next(&BlockSpec::default());
+        assert_eq!(reference_transition(&chain_state, &header, &params), None);
+    }
+
+    #[test]
+    fn test_fork() {
+        let mut builder = ChainBuilder::new(&REGTEST_CONSTANTS);
+        builder.extend(30);
+        let mut fork = builder.fork(20);
+        fork.extend(15);
+
+        assert_eq!(fork.headers()[..21], builder.headers()[..21]);
+        assert_ne!(fork.headers()[21], builder.headers()[21]);
+        assert_eq!(fork.state_at(20), builder.state_at(20));
+        assert_eq!(fork.chain_state().block_height, 35);
+        assert_eq!(
+            &apply(fork.headers(), &REGTEST_CONSTANTS),
+            fork.chain_state()
+        );
+    }
+}
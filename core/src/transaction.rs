@@ -4,11 +4,12 @@
 use core::ops::{Deref, DerefMut};
 
 use bitcoin::absolute::LockTime;
-use bitcoin::consensus::Encodable;
+use bitcoin::consensus::{encode, Encodable};
 use bitcoin::hashes::Hash;
 use bitcoin::transaction::Version;
 use bitcoin::{Amount, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Witness};
 use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
 
 use crate::utils::calculate_double_sha256;
 
@@ -48,6 +49,30 @@ impl CircuitTransaction {
     }
 }
 
+/// Serialized as the consensus encoding, in hex for human-readable formats.
+impl Serialize for CircuitTransaction {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&encode::serialize_hex(&self.0))
+        } else {
+            Serialize::serialize(&encode::serialize(&self.0), serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for CircuitTransaction {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let transaction = if deserializer.is_human_readable() {
+            encode::deserialize_hex(&<String as Deserialize>::deserialize(deserializer)?)
+                .map_err(|e| D::Error::custom(format!("invalid transaction: {}", e)))?
+        } else {
+            encode::deserialize(&<Vec<u8> as Deserialize>::deserialize(deserializer)?)
+                .map_err(|e| D::Error::custom(format!("invalid transaction: {}", e)))?
+        };
+        Ok(Self(transaction))
+    }
+}
+
 impl BorshSerialize for CircuitTransaction {
     #[inline]
     fn serialize<W: borsh::io::Write>(&self, writer: &mut W) -> borsh::io::Result<()> {
@@ -7,6 +7,8 @@ pub trait ZkvmGuest {
     fn read_from_host<T: borsh::BorshDeserialize>(&self) -> T;
     fn commit<T: borsh::BorshSerialize>(&self, item: &T);
     fn verify<T: borsh::BorshSerialize>(&self, method_id: [u32; 8], journal: &T);
+    /// Marks the end of a phase of the guest, see `CycleMarker`.
+    fn mark_cycles(&self, phase: &str);
 }
 
 #[derive(Debug, Clone)]
@@ -51,4 +53,84 @@ impl ZkvmGuest for Risc0Guest {
     fn verify<T: borsh::BorshSerialize>(&self, method_id: [u32; 8], output: &T) {
         env::verify(method_id, &borsh::to_vec(output).unwrap()).unwrap();
     }
+
+    fn mark_cycles(&self, phase: &str) {
+        let marker = CycleMarker {
+            phase: phase.to_string(),
+            cycle_count: env::cycle_count(),
+        };
+        env::stderr()
+            .write_all(marker.to_line().as_bytes())
+            .unwrap();
+    }
+}
+
+const CYCLE_MARKER_PREFIX: &str = "cycle-marker";
+
+/// The cycle count of a guest at the end of one of its phases. The guests write these to stderr,
+/// one per line, so the host can report a per-phase breakdown when executing them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CycleMarker {
+    /// The phase name, without whitespace.
+    pub phase: String,
+    /// The number of cycles since the guest began.
+    pub cycle_count: u64,
+}
+
+impl CycleMarker {
+    /// Encodes the marker as `cycle-marker <phase> <cycle_count>\n`.
+    pub fn to_line(&self) -> String {
+        format!(
+            "{} {} {}\n",
+            CYCLE_MARKER_PREFIX, self.phase, self.cycle_count
+        )
+    }
+
+    /// Parses a line written by `to_line`.
+    pub fn parse_line(line: &str) -> Option<Self> {
+        let mut parts = line.split_whitespace();
+        if parts.next()? != CYCLE_MARKER_PREFIX {
+            return None;
+        }
+        let phase = parts.next()?.to_string();
+        let cycle_count = parts.next()?.parse().ok()?;
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(CycleMarker { phase, cycle_count })
+    }
+
+    /// Returns the markers in the given output, ignoring any other lines.
+    pub fn parse_all(output: &str) -> Vec<Self> {
+        output.lines().filter_map(Self::parse_line).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cycle_marker_roundtrip() {
+        let markers = vec![
+            CycleMarker {
+                phase: "read_input".to_string(),
+                cycle_count: 1234,
+            },
+            CycleMarker {
+                phase: "apply_blocks".to_string(),
+                cycle_count: u64::MAX,
+            },
+        ];
+        let mut output = String::from("Some guest output\n");
+        for marker in &markers {
+            output.push_str(&marker.to_line());
+            output.push_str("cycle-marker without_count\n");
+        }
+        assert_eq!(CycleMarker::parse_all(&output), markers);
+
+        assert_eq!(CycleMarker::parse_line("cycle-marker phase 12 13"), None);
+        assert_eq!(CycleMarker::parse_line("cycle-marker phase -1"), None);
+        assert_eq!(CycleMarker::parse_line("cycle-markers phase 1"), None);
+    }
 }
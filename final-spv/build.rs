@@ -1,3 +1,4 @@
+use build_utils::{custom_signet, elf_name};
 use risc0_binfmt::compute_image_id;
 use risc0_build::{embed_methods_with_options, DockerOptionsBuilder, GuestOptionsBuilder};
 use std::{collections::HashMap, env, fs, path::Path};
@@ -9,7 +10,7 @@ fn main() {
     println!("cargo:rerun-if-env-changed=OUT_DIR");
 
     // Compile time constant environment variables
-    println!("cargo:rerun-if-env-changed=BITCOIN_NETWORK");
+    build_utils::rerun_if_network_changed();
     println!("cargo:rerun-if-env-changed=TEST_SKIP_GUEST_BUILD");
 
     if std::env::var("CLIPPY_ARGS").is_ok() {
@@ -117,9 +118,17 @@ fn get_guest_options(network: String) -> HashMap<&'static str, risc0_build::Gues
             root_dir
         );
 
+        let mut docker_env = vec![("BITCOIN_NETWORK".to_string(), network.clone())];
+        // The challenge and pow limit of a custom signet.
+        for var in ["BITCOIN_SIGNET_CHALLENGE", "BITCOIN_SIGNET_POW_LIMIT"] {
+            if let Ok(value) = env::var(var) {
+                docker_env.push((var.to_string(), value));
+            }
+        }
+
         let docker_opts = DockerOptionsBuilder::default()
             .root_dir(root_dir)
-            .env(vec![("BITCOIN_NETWORK".to_string(), network.clone())])
+            .env(docker_env)
             .build()
             .unwrap();
 
@@ -163,7 +172,8 @@ fn copy_binary_to_elfs_folder(network: String) {
     }
 
     // Build destination path with network prefix
-    let dest_filename = format!("{}-final-spv-guest.bin", network.to_lowercase());
+    let elf_name = elf_name(&network);
+    let dest_filename = format!("{}-final-spv-guest.bin", elf_name);
     let dest_path = elfs_dir.join(&dest_filename);
 
     // Copy the file
@@ -177,17 +187,16 @@ fn copy_binary_to_elfs_folder(network: String) {
 
     // Calculate and print method ID
     let elf_path = match network.as_str() {
-        "mainnet" => "../elfs/mainnet-final-spv-guest.bin",
-        "testnet4" => "../elfs/testnet4-final-spv-guest.bin",
-        "signet" => "../elfs/signet-final-spv-guest.bin",
-        "regtest" => "../elfs/regtest-final-spv-guest.bin",
+        "mainnet" | "testnet4" | "testnet3" | "signet" | "regtest" => {
+            format!("../elfs/{}-final-spv-guest.bin", elf_name)
+        }
         _ => {
             println!("cargo:warning=Invalid network specified, defaulting to mainnet");
-            "../elfs/mainnet-final-spv-guest.bin"
+            "../elfs/mainnet-final-spv-guest.bin".to_string()
         }
     };
 
-    let elf_bytes: Vec<u8> = match fs::read(Path::new(elf_path)) {
+    let elf_bytes: Vec<u8> = match fs::read(Path::new(&elf_path)) {
         Ok(bytes) => bytes,
         Err(e) => {
             println!("cargo:warning=Failed to read ELF file: {}", e);
@@ -208,4 +217,42 @@ fn copy_binary_to_elfs_folder(network: String) {
         "cargo:warning=Computed method ID words: {:?}",
         method_id.as_words()
     );
+
+    // Record the header chain method ID the final circuit was built against,
+    // `host image-id check` compares it with the header chain ELF.
+    let header_chain_elf_path = format!("../elfs/{}-header-chain-guest.bin", elf_name);
+    let header_chain_method_id = match fs::read(Path::new(&header_chain_elf_path))
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| compute_image_id(&bytes).map_err(|e| e.to_string()))
+    {
+        Ok(id) => id,
+        Err(e) => {
+            println!(
+                "cargo:warning=Failed to compute header chain method ID: {}",
+                e
+            );
+            return;
+        }
+    };
+    let mut manifest = format!(
+        "{{\n  \"header_chain_guest_id\": {:?},\n  \"final_spv_guest_id\": {:?}",
+        header_chain_method_id.as_words(),
+        method_id.as_words()
+    );
+    // A custom signet also records the parameters it was built with.
+    if let Some((challenge, pow_limit)) = custom_signet(&network) {
+        manifest.push_str(&format!(
+            ",\n  \"signet_challenge\": \"{}\",\n  \"signet_pow_limit\": \"{}\"",
+            challenge, pow_limit
+        ));
+    }
+    manifest.push_str("\n}\n");
+    let manifest_path = elfs_dir.join(format!("{}-image-ids.json", elf_name));
+    match fs::write(&manifest_path, manifest) {
+        Ok(_) => println!(
+            "cargo:warning=Successfully wrote image IDs to {:?}",
+            manifest_path
+        ),
+        Err(e) => println!("cargo:warning=Failed to write image IDs: {}", e),
+    }
 }
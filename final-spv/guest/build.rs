@@ -0,0 +1,36 @@
+use build_utils::elf_name;
+use risc0_binfmt::compute_image_id;
+use std::{env, fs, path::Path};
+
+/// Generates `HEADER_CHAIN_GUEST_ID` from the reproducibly built header chain ELF of the network.
+fn main() {
+    build_utils::rerun_if_network_changed();
+
+    let network = env::var("BITCOIN_NETWORK").unwrap_or_else(|_| "mainnet".to_string());
+    if !matches!(
+        network.as_str(),
+        "mainnet" | "testnet4" | "testnet3" | "signet" | "regtest"
+    ) {
+        panic!("Invalid network type: {}", network);
+    }
+
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR").expect("Failed to get manifest dir");
+    let elf_path = Path::new(&manifest_dir)
+        .join("../../elfs")
+        .join(format!("{}-header-chain-guest.bin", elf_name(&network)));
+    println!("cargo:rerun-if-changed={}", elf_path.display());
+
+    let elf_bytes = fs::read(&elf_path)
+        .unwrap_or_else(|e| panic!("Failed to read header chain ELF {:?}: {}", elf_path, e));
+    let method_id = compute_image_id(&elf_bytes).expect("Failed to compute header chain method ID");
+
+    let out_dir = env::var("OUT_DIR").expect("OUT_DIR not set");
+    fs::write(
+        Path::new(&out_dir).join("header_chain_guest_id.rs"),
+        format!(
+            "const HEADER_CHAIN_GUEST_ID: [u32; 8] = {:?};\n",
+            method_id.as_words()
+        ),
+    )
+    .expect("Failed to write header chain guest ID");
+}
@@ -1,3 +1,4 @@
+use build_utils::elf_name;
 use risc0_binfmt::compute_image_id;
 use risc0_build::{embed_methods_with_options, DockerOptionsBuilder, GuestOptionsBuilder};
 use std::{collections::HashMap, env, fs, path::Path};
@@ -9,7 +10,7 @@ fn main() {
     println!("cargo:rerun-if-env-changed=OUT_DIR");
 
     // Compile time constant environment variables
-    println!("cargo:rerun-if-env-changed=BITCOIN_NETWORK");
+    build_utils::rerun_if_network_changed();
     println!("cargo:rerun-if-env-changed=TEST_SKIP_GUEST_BUILD");
 
     if std::env::var("CLIPPY_ARGS").is_ok() {
@@ -118,9 +119,17 @@ fn get_guest_options(network: String) -> HashMap<&'static str, risc0_build::Gues
             root_dir
         );
 
+        let mut docker_env = vec![("BITCOIN_NETWORK".to_string(), network.clone())];
+        // The challenge and pow limit of a custom signet.
+        for var in ["BITCOIN_SIGNET_CHALLENGE", "BITCOIN_SIGNET_POW_LIMIT"] {
+            if let Ok(value) = env::var(var) {
+                docker_env.push((var.to_string(), value));
+            }
+        }
+
         let docker_opts = DockerOptionsBuilder::default()
             .root_dir(root_dir)
-            .env(vec![("BITCOIN_NETWORK".to_string(), network.clone())])
+            .env(docker_env)
             .build()
             .unwrap();
 
@@ -164,7 +173,8 @@ fn copy_binary_to_elfs_folder(network: String) {
     }
 
     // Build destination path with network prefix
-    let dest_filename = format!("{}-header-chain-guest.bin", network.to_lowercase());
+    let elf_name = elf_name(&network);
+    let dest_filename = format!("{}-header-chain-guest.bin", elf_name);
     let dest_path = elfs_dir.join(&dest_filename);
 
     // Copy the file
@@ -178,17 +188,16 @@ fn copy_binary_to_elfs_folder(network: String) {
 
     // Calculate and print method ID
     let elf_path = match network.as_str() {
-        "mainnet" => "../elfs/mainnet-header-chain-guest.bin",
-        "testnet4" => "../elfs/testnet4-header-chain-guest.bin",
-        "signet" => "../elfs/signet-header-chain-guest.bin",
-        "regtest" => "../elfs/regtest-header-chain-guest.bin",
+        "mainnet" | "testnet4" | "testnet3" | "signet" | "regtest" => {
+            format!("../elfs/{}-header-chain-guest.bin", elf_name)
+        }
         _ => {
             println!("cargo:warning=Invalid network specified, defaulting to mainnet");
-            "../elfs/mainnet-header-chain-guest.bin"
+            "../elfs/mainnet-header-chain-guest.bin".to_string()
         }
     };
 
-    let elf_bytes: Vec<u8> = match fs::read(Path::new(elf_path)) {
+    let elf_bytes: Vec<u8> = match fs::read(Path::new(&elf_path)) {
         Ok(bytes) => bytes,
         Err(e) => {
             println!("cargo:warning=Failed to read ELF file: {}", e);
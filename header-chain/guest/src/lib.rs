@@ -1,10 +1,9 @@
-use risc0_to_bitvm2_core::{header_chain::{BlockHeaderCircuitOutput, ChainState, HeaderChainCircuitInput, HeaderChainPrevProofType}, zkvm::ZkvmGuest};
+use risc0_to_bitvm2_core::{header_chain::{BlockHeaderCircuitOutput, ChainState, HeaderChainCircuitInput, HeaderChainPrevProofType, HeaderChainRangeOutput}, zkvm::ZkvmGuest};
 
 /// The main entry point of the header chain circuit.
 pub fn header_chain_circuit(guest: &impl ZkvmGuest) {
-    let start = risc0_zkvm::guest::env::cycle_count();
-
     let input: HeaderChainCircuitInput = guest.read_from_host();
+    guest.mark_cycles("read_input");
     // println!("Detected network: {:?}", NETWORK_TYPE);
     // println!("NETWORK_CONSTANTS: {:?}", NETWORK_CONSTANTS);
     let mut chain_state = match input.prev_proof {
@@ -14,14 +13,43 @@ pub fn header_chain_circuit(guest: &impl ZkvmGuest) {
             guest.verify(input.method_id, &prev_proof);
             prev_proof.chain_state
         }
+        HeaderChainPrevProofType::UnknownPrevState(initial_state) => {
+            guest.mark_cycles("verify_prev_proof");
+            let mut chain_state = initial_state.clone();
+            chain_state.apply_blocks(input.block_headers);
+            guest.mark_cycles("apply_blocks");
+
+            guest.commit(&HeaderChainRangeOutput::new(
+                input.method_id,
+                &initial_state,
+                chain_state,
+            ));
+            guest.mark_cycles("commit");
+            return;
+        }
+        HeaderChainPrevProofType::RangeProofs { prev_proof, ranges } => {
+            let chain_state = match prev_proof {
+                Some(prev_proof) => {
+                    assert_eq!(prev_proof.method_id, input.method_id);
+                    guest.verify(input.method_id, &prev_proof);
+                    prev_proof.chain_state
+                }
+                None => ChainState::new(),
+            };
+            for range in &ranges {
+                guest.verify(input.method_id, range);
+            }
+            HeaderChainRangeOutput::compose(chain_state, input.method_id, ranges)
+        }
     };
+    guest.mark_cycles("verify_prev_proof");
 
     chain_state.apply_blocks(input.block_headers);
+    guest.mark_cycles("apply_blocks");
 
     guest.commit(&BlockHeaderCircuitOutput {
         method_id: input.method_id,
         chain_state,
     });
-    let end = risc0_zkvm::guest::env::cycle_count();
-    println!("Header chain circuit took {:?} cycles", end - start);
+    guest.mark_cycles("commit");
 }
@@ -0,0 +1,15 @@
+use build_utils::{custom_signet, elf_name};
+use std::env;
+
+fn main() {
+    build_utils::rerun_if_network_changed();
+
+    // The signet ELFs and headers to embed, which are those of the custom signet if one is set,
+    // named like `header-chain/build.rs` names them.
+    let network = env::var("BITCOIN_NETWORK").unwrap_or_default();
+    let signet_name = match custom_signet(&network) {
+        Some(_) => elf_name(&network),
+        None => "signet".to_string(),
+    };
+    println!("cargo:rustc-env=SIGNET_ELF_NAME={}", signet_name);
+}
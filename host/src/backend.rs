@@ -0,0 +1,386 @@
+use bonsai_sdk::blocking::Client as BonsaiClient;
+use risc0_zkvm::{
+    compute_image_id, default_executor, default_prover, AssumptionReceipt, ExecutorEnv,
+    FakeReceipt, InnerReceipt, LocalProver, Prover, ProverOpts, Receipt, ReceiptClaim,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+/// Proves guests into succinct receipts, which can be used as assumptions of later proofs.
+pub trait ProvingBackend {
+    /// Proves the guest on the input, i.e. the bytes it reads, verifying the assumptions.
+    fn prove(
+        &self,
+        elf: &[u8],
+        input: &[u8],
+        assumptions: Vec<Receipt>,
+    ) -> Result<Receipt, Box<dyn Error>>;
+}
+
+/// The proving backend to use, read from a JSON file such as
+/// `{"backend": "bonsai", "api_url": "https://api.bonsai.xyz", "api_key": "..."}`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(tag = "backend", rename_all = "lowercase")]
+pub enum BackendConfig {
+    /// The prover risc0 selects from the environment, e.g. `RISC0_PROVER` or `BONSAI_API_URL`.
+    #[default]
+    Default,
+    /// The local CPU (or GPU, if built with it) prover.
+    Local,
+    Bonsai {
+        api_url: String,
+        api_key: String,
+    },
+    /// The Boundless market, configured from the environment like `boundless-client`. It cannot
+    /// prove guests that need assumptions.
+    Boundless {
+        /// Where to save a set inclusion proof returned by the market, to be resolved with
+        /// `boundless-client resolve`. It is discarded if not set.
+        #[serde(default)]
+        set_inclusion_proof_path: Option<PathBuf>,
+    },
+    /// Executes the guest and returns a fake receipt, which only verifies in dev mode.
+    Mock,
+}
+
+impl BackendConfig {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    pub fn backend(&self) -> Result<Box<dyn ProvingBackend>, Box<dyn Error>> {
+        Ok(match self {
+            BackendConfig::Default => Box::new(DefaultBackend),
+            BackendConfig::Local => Box::new(LocalBackend),
+            BackendConfig::Bonsai { api_url, api_key } => Box::new(BonsaiBackend {
+                client: BonsaiClient::from_parts(
+                    api_url.clone(),
+                    api_key.clone(),
+                    risc0_zkvm::VERSION,
+                )?,
+            }),
+            #[cfg(feature = "boundless")]
+            BackendConfig::Boundless {
+                set_inclusion_proof_path,
+            } => Box::new(BoundlessBackend {
+                market: boundless_client::market::BoundlessMarket::from_env()?,
+                set_inclusion_proof_path: set_inclusion_proof_path.clone(),
+            }),
+            #[cfg(not(feature = "boundless"))]
+            BackendConfig::Boundless { .. } => {
+                return Err("host was built without the `boundless` feature".into())
+            }
+            BackendConfig::Mock => Box::new(MockBackend),
+        })
+    }
+}
+
+fn executor_env(
+    input: &[u8],
+    assumptions: Vec<Receipt>,
+) -> Result<ExecutorEnv<'static>, Box<dyn Error>> {
+    let mut builder = ExecutorEnv::builder();
+    builder.write_slice(input);
+    for receipt in assumptions {
+        builder.add_assumption(receipt);
+    }
+    Ok(builder.build()?)
+}
+
+fn prove_succinct(
+    prover: &dyn Prover,
+    elf: &[u8],
+    input: &[u8],
+    assumptions: Vec<Receipt>,
+) -> Result<Receipt, Box<dyn Error>> {
+    let prove_info = prover.prove_with_opts(
+        executor_env(input, assumptions)?,
+        elf,
+        &ProverOpts::succinct(),
+    )?;
+    println!("New Receipt: {:?}", prove_info.stats);
+    Ok(prove_info.receipt)
+}
+
+pub struct DefaultBackend;
+
+impl ProvingBackend for DefaultBackend {
+    fn prove(
+        &self,
+        elf: &[u8],
+        input: &[u8],
+        assumptions: Vec<Receipt>,
+    ) -> Result<Receipt, Box<dyn Error>> {
+        prove_succinct(default_prover().as_ref(), elf, input, assumptions)
+    }
+}
+
+pub struct LocalBackend;
+
+impl ProvingBackend for LocalBackend {
+    fn prove(
+        &self,
+        elf: &[u8],
+        input: &[u8],
+        assumptions: Vec<Receipt>,
+    ) -> Result<Receipt, Box<dyn Error>> {
+        prove_succinct(&LocalProver::new("local"), elf, input, assumptions)
+    }
+}
+
+pub struct BonsaiBackend {
+    client: BonsaiClient,
+}
+
+impl ProvingBackend for BonsaiBackend {
+    fn prove(
+        &self,
+        elf: &[u8],
+        input: &[u8],
+        assumptions: Vec<Receipt>,
+    ) -> Result<Receipt, Box<dyn Error>> {
+        let image_id = compute_image_id(elf)?;
+        self.client
+            .upload_img(&image_id.to_string(), elf.to_vec())?;
+        let input_id = self.client.upload_input(input.to_vec())?;
+        let mut receipt_ids = vec![];
+        for receipt in assumptions {
+            let AssumptionReceipt::Proven(receipt) = AssumptionReceipt::from(receipt) else {
+                unreachable!("Receipts are proven assumptions");
+            };
+            receipt_ids.push(self.client.upload_receipt(bincode::serialize(&receipt)?)?);
+        }
+
+        let session =
+            self.client
+                .create_session(image_id.to_string(), input_id, receipt_ids, false)?;
+        println!("Bonsai session {}", session.uuid);
+        let receipt = loop {
+            let status = session.status(&self.client)?;
+            match status.status.as_str() {
+                "RUNNING" => thread::sleep(Duration::from_secs(15)),
+                "SUCCEEDED" => {
+                    let receipt_url = status
+                        .receipt_url
+                        .ok_or("the Bonsai session has no receipt")?;
+                    break bincode::deserialize::<Receipt>(&self.client.download(&receipt_url)?)?;
+                }
+                _ => {
+                    return Err(format!(
+                        "the Bonsai session {}: {}",
+                        status.status,
+                        status.error_msg.unwrap_or_default()
+                    )
+                    .into())
+                }
+            }
+        };
+        receipt.verify(image_id)?;
+        Ok(receipt)
+    }
+}
+
+/// Proves guests on the Boundless market. The guest is executed locally first, so it cannot need
+/// assumptions, and the market must return a receipt rather than a set inclusion proof.
+#[cfg(feature = "boundless")]
+pub struct BoundlessBackend {
+    market: boundless_client::market::BoundlessMarket,
+    set_inclusion_proof_path: Option<PathBuf>,
+}
+
+#[cfg(feature = "boundless")]
+impl ProvingBackend for BoundlessBackend {
+    fn prove(
+        &self,
+        elf: &[u8],
+        input: &[u8],
+        assumptions: Vec<Receipt>,
+    ) -> Result<Receipt, Box<dyn Error>> {
+        use boundless_client::{market, receipt::SavedProof};
+
+        if !assumptions.is_empty() {
+            return Err("Boundless provers cannot be given assumptions".into());
+        }
+        let runtime = tokio::runtime::Runtime::new()?;
+        let (proof, _) = runtime.block_on(market::prove(&self.market, elf, input.to_vec()))?;
+        match proof {
+            SavedProof::Receipt(receipt) => Ok(*receipt),
+            SavedProof::SetInclusion(proof) => match &self.set_inclusion_proof_path {
+                Some(path) => {
+                    proof.save(path)?;
+                    Err(format!(
+                        "the market returned a set inclusion proof, saved to {:?}. Resolve it \
+                         with `boundless-client resolve`",
+                        path
+                    )
+                    .into())
+                }
+                None => Err("the market returned a set inclusion proof, set \
+                             `set_inclusion_proof_path` in the backend config to keep it"
+                    .into()),
+            },
+        }
+    }
+}
+
+pub struct MockBackend;
+
+impl ProvingBackend for MockBackend {
+    fn prove(
+        &self,
+        elf: &[u8],
+        input: &[u8],
+        assumptions: Vec<Receipt>,
+    ) -> Result<Receipt, Box<dyn Error>> {
+        let session_info = default_executor().execute(executor_env(input, assumptions)?, elf)?;
+        let claim = ReceiptClaim::ok(compute_image_id(elf)?, session_info.journal.bytes.clone());
+        Ok(Receipt::new(
+            InnerReceipt::Fake(FakeReceipt::new(claim)),
+            session_info.journal.bytes,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use borsh::BorshDeserialize;
+    use risc0_to_bitvm2_core::header_chain::{
+        BlockHeaderCircuitOutput, CircuitBlockHeader, HeaderChainCircuitInput,
+        HeaderChainPrevProofType,
+    };
+    use risc0_zkvm::sha::Digestible;
+
+    const REGTEST_ELF: &[u8] = include_bytes!("../../elfs/regtest-header-chain-guest.bin");
+    const REGTEST_HEADERS: &[u8] = include_bytes!("../../data/headers/regtest-headers.bin");
+
+    #[test]
+    fn test_backend_config() {
+        assert_eq!(
+            serde_json::from_str::<BackendConfig>(
+                r#"{"backend": "bonsai", "api_url": "https://api.bonsai.xyz", "api_key": "key"}"#
+            )
+            .unwrap(),
+            BackendConfig::Bonsai {
+                api_url: "https://api.bonsai.xyz".to_string(),
+                api_key: "key".to_string(),
+            }
+        );
+        assert_eq!(
+            serde_json::from_str::<BackendConfig>(r#"{"backend": "mock"}"#).unwrap(),
+            BackendConfig::Mock
+        );
+        assert_eq!(
+            serde_json::from_str::<BackendConfig>(r#"{"backend": "boundless"}"#).unwrap(),
+            BackendConfig::Boundless {
+                set_inclusion_proof_path: None
+            }
+        );
+        assert_eq!(
+            serde_json::from_str::<BackendConfig>(
+                r#"{"backend": "boundless", "set_inclusion_proof_path": "proofs/set.json"}"#
+            )
+            .unwrap(),
+            BackendConfig::Boundless {
+                set_inclusion_proof_path: Some(PathBuf::from("proofs/set.json"))
+            }
+        );
+        assert!(serde_json::from_str::<BackendConfig>(r#"{"backend": "gpu"}"#).is_err());
+    }
+
+    #[test]
+    fn test_mock_backend() {
+        let method_id: [u32; 8] = compute_image_id(REGTEST_ELF).unwrap().into();
+        let prove = |prev_proof, headers: &[u8], assumptions| {
+            let input = HeaderChainCircuitInput {
+                method_id,
+                prev_proof,
+                block_headers: headers
+                    .chunks(80)
+                    .map(|header| CircuitBlockHeader::try_from_slice(header).unwrap())
+                    .collect(),
+            };
+            let backend = BackendConfig::Mock.backend().unwrap();
+            backend
+                .prove(REGTEST_ELF, &borsh::to_vec(&input).unwrap(), assumptions)
+                .unwrap()
+        };
+
+        let first = prove(
+            HeaderChainPrevProofType::GenesisBlock,
+            &REGTEST_HEADERS[..10 * 80],
+            vec![],
+        );
+        let output = BlockHeaderCircuitOutput::try_from_slice(&first.journal.bytes).unwrap();
+        assert_eq!(output.chain_state.block_height, 9);
+        assert_eq!(
+            first.claim().unwrap().digest(),
+            ReceiptClaim::ok(method_id, first.journal.bytes.clone()).digest()
+        );
+
+        // Fake receipts can be used as assumptions of the next proof.
+        let second = prove(
+            HeaderChainPrevProofType::PrevProof(output),
+            &REGTEST_HEADERS[10 * 80..20 * 80],
+            vec![first],
+        );
+        let output = BlockHeaderCircuitOutput::try_from_slice(&second.journal.bytes).unwrap();
+        assert_eq!(output.chain_state.block_height, 19);
+    }
+
+    #[cfg(feature = "boundless")]
+    #[test]
+    fn test_market_receipt_as_assumption() {
+        use boundless_client::{
+            market::{self, MockMarket},
+            receipt::SavedProof,
+        };
+
+        let method_id: [u32; 8] = compute_image_id(REGTEST_ELF).unwrap().into();
+        let input = |prev_proof, headers: &[u8]| HeaderChainCircuitInput {
+            method_id,
+            prev_proof,
+            block_headers: headers
+                .chunks(80)
+                .map(|header| CircuitBlockHeader::try_from_slice(header).unwrap())
+                .collect(),
+        };
+        let first = input(
+            HeaderChainPrevProofType::GenesisBlock,
+            &REGTEST_HEADERS[..10 * 80],
+        );
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let (proof, journal) = runtime
+            .block_on(market::prove(
+                &MockMarket,
+                REGTEST_ELF,
+                borsh::to_vec(&first).unwrap(),
+            ))
+            .unwrap();
+        let SavedProof::Receipt(receipt) = proof else {
+            panic!("The mock market returns fake receipts");
+        };
+
+        // The decoded fulfillment is an assumption of the next proof, like a local receipt.
+        let output = BlockHeaderCircuitOutput::try_from_slice(&journal).unwrap();
+        let second = input(
+            HeaderChainPrevProofType::PrevProof(output),
+            &REGTEST_HEADERS[10 * 80..20 * 80],
+        );
+        let receipt = MockBackend
+            .prove(
+                REGTEST_ELF,
+                &borsh::to_vec(&second).unwrap(),
+                vec![*receipt],
+            )
+            .unwrap();
+        let output = BlockHeaderCircuitOutput::try_from_slice(&receipt.journal.bytes).unwrap();
+        assert_eq!(output.chain_state.block_height, 19);
+    }
+}
@@ -0,0 +1,507 @@
+use ark_bn254::{Bn254, Fq, Fq2, G1Affine, G2Affine};
+use ark_ff::PrimeField;
+use ark_groth16::{Proof, VerifyingKey};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use borsh::{BorshDeserialize, BorshSerialize};
+use risc0_zkvm::{sha::Digestible, Receipt, SuccinctReceiptVerifierParameters};
+use serde::{Deserialize, Serialize};
+use std::{fmt, fs, path::Path, str::FromStr};
+
+use crate::{docker::Groth16Output, succinct_output_prefix};
+use risc0_circuit_recursion::control_id::BN254_IDENTITY_CONTROL_ID;
+
+/// The current version of the `BitVmProofBundle` format.
+pub const BITVM_PROOF_BUNDLE_VERSION: u32 = 1;
+
+/// Everything the BitVM script generator needs to check a Groth16 proof of the final circuit.
+///
+/// Byte arrays are encoded as hex strings in JSON.
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct BitVmProofBundle {
+    pub version: u32,
+    /// The proof as `a || b || c` with big-endian uncompressed coordinates, in the same order as
+    /// the risc0 `Seal` (`G2` coordinates are `c1 || c0`).
+    #[serde(with = "hex::serde")]
+    pub proof_uncompressed: [u8; 256],
+    /// The proof as `a || b || c` in the arkworks compressed (little-endian) encoding.
+    #[serde(with = "hex::serde")]
+    pub proof_compressed: [u8; 128],
+    /// The single public input of the Groth16 proof as a big-endian BN254 scalar.
+    #[serde(with = "hex::serde")]
+    pub public_input: [u8; 32],
+    /// The method ID of the final circuit.
+    pub method_id: [u32; 8],
+    #[serde(with = "hex::serde")]
+    pub journal: Vec<u8>,
+    #[serde(with = "hex::serde")]
+    pub pre_state_digest: [u8; 32],
+    #[serde(with = "hex::serde")]
+    pub post_state_digest: [u8; 32],
+    /// The control root of the succinct verifier parameters.
+    #[serde(with = "hex::serde")]
+    pub control_root: [u8; 32],
+    /// The control ID of the `identity_p254` recursion program.
+    #[serde(with = "hex::serde")]
+    pub bn254_control_id: [u8; 32],
+}
+
+/// Errors returned when a `BitVmProofBundle` cannot be built or does not validate.
+#[derive(Debug)]
+pub enum BundleError {
+    UnsupportedVersion(u32),
+    /// The receipt is not a succinct receipt of a successful execution.
+    InvalidReceipt(String),
+    /// The proof points are not valid BN254 points or the two encodings differ.
+    MalformedProof(String),
+    /// The pre state digest is not the method ID.
+    MethodIdMismatch,
+    /// The public input does not commit to the constants and the journal.
+    PublicInputMismatch {
+        expected: [u8; 32],
+        actual: [u8; 32],
+    },
+    /// The Groth16 proof does not verify against the verifying key.
+    InvalidProof,
+}
+
+impl fmt::Display for BundleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BundleError::UnsupportedVersion(version) => {
+                write!(f, "unsupported bundle version {}", version)
+            }
+            BundleError::InvalidReceipt(e) => write!(f, "invalid receipt: {}", e),
+            BundleError::MalformedProof(e) => write!(f, "malformed proof: {}", e),
+            BundleError::MethodIdMismatch => {
+                write!(f, "pre state digest does not match the method ID")
+            }
+            BundleError::PublicInputMismatch { expected, actual } => write!(
+                f,
+                "public input mismatch: expected {}, got {}",
+                hex::encode(expected),
+                hex::encode(actual)
+            ),
+            BundleError::InvalidProof => write!(f, "groth16 proof verification failed"),
+        }
+    }
+}
+
+impl std::error::Error for BundleError {}
+
+impl BitVmProofBundle {
+    /// Builds the bundle from a succinct final circuit receipt and its Groth16 proof.
+    pub fn new(receipt: &Receipt, groth16_output: &Groth16Output) -> Result<Self, BundleError> {
+        let claim = receipt
+            .claim()
+            .map_err(|e| BundleError::InvalidReceipt(e.to_string()))?
+            .value()
+            .map_err(|e| BundleError::InvalidReceipt(e.to_string()))?;
+        let pre_state_digest: [u8; 32] = claim.pre.digest().into();
+        let post_state_digest: [u8; 32] = claim.post.digest().into();
+        let method_id: [u32; 8] = claim.pre.digest().into();
+
+        let proof_uncompressed: [u8; 256] = groth16_output
+            .seal
+            .to_vec()
+            .try_into()
+            .map_err(|_| BundleError::MalformedProof("seal is not 256 bytes".to_string()))?;
+        let proof = proof_from_uncompressed(&proof_uncompressed)?;
+        let mut proof_compressed = [0u8; 128];
+        proof
+            .serialize_compressed(&mut proof_compressed[..])
+            .expect("Compressed proof is 128 bytes");
+
+        let mut public_input = [0u8; 32];
+        public_input[1..].copy_from_slice(&groth16_output.public_output);
+
+        let bundle = BitVmProofBundle {
+            version: BITVM_PROOF_BUNDLE_VERSION,
+            proof_uncompressed,
+            proof_compressed,
+            public_input,
+            method_id,
+            journal: receipt.journal.bytes.clone(),
+            pre_state_digest,
+            post_state_digest,
+            control_root: SuccinctReceiptVerifierParameters::default()
+                .control_root
+                .into(),
+            bn254_control_id: BN254_IDENTITY_CONTROL_ID.into(),
+        };
+        bundle.validate()?;
+        Ok(bundle)
+    }
+
+    /// Checks that the bundle is self-consistent and that the proof verifies.
+    pub fn validate(&self) -> Result<(), BundleError> {
+        if self.version != BITVM_PROOF_BUNDLE_VERSION {
+            return Err(BundleError::UnsupportedVersion(self.version));
+        }
+
+        let proof = proof_from_uncompressed(&self.proof_uncompressed)?;
+        let compressed_proof = Proof::<Bn254>::deserialize_compressed(&self.proof_compressed[..])
+            .map_err(|e| BundleError::MalformedProof(e.to_string()))?;
+        if proof != compressed_proof {
+            return Err(BundleError::MalformedProof(
+                "compressed and uncompressed proofs differ".to_string(),
+            ));
+        }
+
+        let method_id_digest: [u8; 32] = risc0_zkvm::sha::Digest::from(self.method_id).into();
+        if method_id_digest != self.pre_state_digest {
+            return Err(BundleError::MethodIdMismatch);
+        }
+
+        let expected = self.expected_public_input();
+        if expected != self.public_input {
+            return Err(BundleError::PublicInputMismatch {
+                expected,
+                actual: self.public_input,
+            });
+        }
+
+        let public_input_scalar = ark_bn254::Fr::from_be_bytes_mod_order(&self.public_input);
+        let pvk = ark_groth16::prepare_verifying_key(&get_ark_verifying_key());
+        match ark_groth16::Groth16::<Bn254>::verify_proof(&pvk, &proof, &[public_input_scalar]) {
+            Ok(true) => Ok(()),
+            _ => Err(BundleError::InvalidProof),
+        }
+    }
+
+    /// Blake3(constants_digest, journal), trimmed to 31 bytes to fit the BN254 scalar field.
+    pub fn expected_public_input(&self) -> [u8; 32] {
+        let constants_digest = succinct_output_prefix(
+            self.control_root,
+            &self.pre_state_digest,
+            self.post_state_digest,
+            self.bn254_control_id,
+        );
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&constants_digest);
+        hasher.update(&self.journal);
+        let mut public_input = [0u8; 32];
+        public_input[1..].copy_from_slice(&hasher.finalize().as_bytes()[..31]);
+        public_input
+    }
+
+    /// Writes the bundle as `bundle.borsh` and `bundle.json` into `dir`.
+    pub fn save(&self, dir: &Path) -> std::io::Result<()> {
+        fs::create_dir_all(dir)?;
+        fs::write(
+            dir.join("bundle.borsh"),
+            borsh::to_vec(self).expect("Serialization to vec is infallible"),
+        )?;
+        fs::write(
+            dir.join("bundle.json"),
+            serde_json::to_string_pretty(self).expect("Bundle is always serializable"),
+        )?;
+        Ok(())
+    }
+
+    /// Reads a bundle, as JSON if the file has a `.json` extension and as borsh otherwise.
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        let bytes = fs::read(path)?;
+        if path.extension().is_some_and(|ext| ext == "json") {
+            Ok(serde_json::from_slice(&bytes)?)
+        } else {
+            BitVmProofBundle::try_from_slice(&bytes)
+        }
+    }
+}
+
+/// Decodes a big-endian field element, rejecting non-canonical encodings.
+fn fq_from_be_bytes(bytes: &[u8]) -> Result<Fq, BundleError> {
+    let mut le_bytes = bytes.to_vec();
+    le_bytes.reverse();
+    Fq::deserialize_uncompressed(&le_bytes[..])
+        .map_err(|e| BundleError::MalformedProof(e.to_string()))
+}
+
+fn g1_from_be_bytes(bytes: &[u8]) -> Result<G1Affine, BundleError> {
+    let point = G1Affine::new_unchecked(
+        fq_from_be_bytes(&bytes[0..32])?,
+        fq_from_be_bytes(&bytes[32..64])?,
+    );
+    if !point.is_on_curve() || !point.is_in_correct_subgroup_assuming_on_curve() {
+        return Err(BundleError::MalformedProof(
+            "G1 point is not on the curve".to_string(),
+        ));
+    }
+    Ok(point)
+}
+
+fn g2_from_be_bytes(bytes: &[u8]) -> Result<G2Affine, BundleError> {
+    let point = G2Affine::new_unchecked(
+        Fq2::new(
+            fq_from_be_bytes(&bytes[32..64])?,
+            fq_from_be_bytes(&bytes[0..32])?,
+        ),
+        Fq2::new(
+            fq_from_be_bytes(&bytes[96..128])?,
+            fq_from_be_bytes(&bytes[64..96])?,
+        ),
+    );
+    if !point.is_on_curve() || !point.is_in_correct_subgroup_assuming_on_curve() {
+        return Err(BundleError::MalformedProof(
+            "G2 point is not on the curve".to_string(),
+        ));
+    }
+    Ok(point)
+}
+
+/// Decodes a proof in the risc0 `Seal` byte layout.
+pub fn proof_from_uncompressed(seal_bytes: &[u8; 256]) -> Result<Proof<Bn254>, BundleError> {
+    Ok(Proof {
+        a: g1_from_be_bytes(&seal_bytes[0..64])?,
+        b: g2_from_be_bytes(&seal_bytes[64..192])?,
+        c: g1_from_be_bytes(&seal_bytes[192..256])?,
+    })
+}
+
+/// The verifying key of the Groth16 circuit in `groth16_proof/circuits`.
+pub fn get_ark_verifying_key() -> VerifyingKey<Bn254> {
+    let alpha_g1 = G1Affine::new(
+        Fq::from_str(
+            "20491192805390485299153009773594534940189261866228447918068658471970481763042",
+        )
+        .unwrap(),
+        Fq::from_str(
+            "9383485363053290200918347156157836566562967994039712273449902621266178545958",
+        )
+        .unwrap(),
+    );
+
+    let beta_g2 = G2Affine::new(
+        Fq2::new(
+            Fq::from_str(
+                "6375614351688725206403948262868962793625744043794305715222011528459656738731",
+            )
+            .unwrap(),
+            Fq::from_str(
+                "4252822878758300859123897981450591353533073413197771768651442665752259397132",
+            )
+            .unwrap(),
+        ),
+        Fq2::new(
+            Fq::from_str(
+                "10505242626370262277552901082094356697409835680220590971873171140371331206856",
+            )
+            .unwrap(),
+            Fq::from_str(
+                "21847035105528745403288232691147584728191162732299865338377159692350059136679",
+            )
+            .unwrap(),
+        ),
+    );
+
+    let gamma_g2 = G2Affine::new(
+        Fq2::new(
+            Fq::from_str(
+                "10857046999023057135944570762232829481370756359578518086990519993285655852781",
+            )
+            .unwrap(),
+            Fq::from_str(
+                "11559732032986387107991004021392285783925812861821192530917403151452391805634",
+            )
+            .unwrap(),
+        ),
+        Fq2::new(
+            Fq::from_str(
+                "8495653923123431417604973247489272438418190587263600148770280649306958101930",
+            )
+            .unwrap(),
+            Fq::from_str(
+                "4082367875863433681332203403145435568316851327593401208105741076214120093531",
+            )
+            .unwrap(),
+        ),
+    );
+
+    let delta_g2 = G2Affine::new(
+        Fq2::new(
+            Fq::from_str(
+                "19928663713463533589216209779412278386769407450988172849262535478593422929698",
+            )
+            .unwrap(),
+            Fq::from_str(
+                "19916519943909223643323234301580053157586699704876134064841182937085943926141",
+            )
+            .unwrap(),
+        ),
+        Fq2::new(
+            Fq::from_str(
+                "4584600978911428195337731119171761277167808711062125916470525050324985708782",
+            )
+            .unwrap(),
+            Fq::from_str(
+                "903010326261527050999816348900764705196723158942686053018929539519969664840",
+            )
+            .unwrap(),
+        ),
+    );
+
+    let gamma_abc_g1 = vec![
+        G1Affine::new(
+            Fq::from_str(
+                "6698887085900109660417671413804888867145870700073340970189635830129386206569",
+            )
+            .unwrap(),
+            Fq::from_str(
+                "10431087902009508261375793061696708147989126018612269070732549055898651692604",
+            )
+            .unwrap(),
+        ),
+        G1Affine::new(
+            Fq::from_str(
+                "20225609417084538563062516991929114218412992453664808591983416996515711931386",
+            )
+            .unwrap(),
+            Fq::from_str(
+                "3236310410959095762960658876334609343091075204896196791007975095263664214628",
+            )
+            .unwrap(),
+        ),
+    ];
+
+    VerifyingKey::<Bn254> {
+        alpha_g1,
+        beta_g2,
+        gamma_g2,
+        delta_g2,
+        gamma_abc_g1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ark_ec::{AffineRepr, CurveGroup};
+
+    fn fq_to_be_bytes(fq: &Fq) -> Vec<u8> {
+        let mut bytes = vec![];
+        fq.serialize_uncompressed(&mut bytes).unwrap();
+        bytes.reverse();
+        bytes
+    }
+
+    /// The risc0 `Seal` layout of the proof, the inverse of `proof_from_uncompressed`.
+    fn proof_to_uncompressed(proof: &Proof<Bn254>) -> [u8; 256] {
+        let mut bytes = vec![];
+        bytes.extend(fq_to_be_bytes(&proof.a.x));
+        bytes.extend(fq_to_be_bytes(&proof.a.y));
+        for fq2 in [proof.b.x, proof.b.y] {
+            bytes.extend(fq_to_be_bytes(&fq2.c1));
+            bytes.extend(fq_to_be_bytes(&fq2.c0));
+        }
+        bytes.extend(fq_to_be_bytes(&proof.c.x));
+        bytes.extend(fq_to_be_bytes(&proof.c.y));
+        bytes.try_into().unwrap()
+    }
+
+    fn proof_to_compressed(proof: &Proof<Bn254>) -> [u8; 128] {
+        let mut bytes = [0u8; 128];
+        proof.serialize_compressed(&mut bytes[..]).unwrap();
+        bytes
+    }
+
+    /// A bundle that passes every check but the pairing, the proof is made of curve generators.
+    fn fixture_bundle() -> BitVmProofBundle {
+        let proof = Proof::<Bn254> {
+            a: G1Affine::generator(),
+            b: G2Affine::generator(),
+            c: (G1Affine::generator() + G1Affine::generator()).into_affine(),
+        };
+        let method_id = [1, 2, 3, 4, 5, 6, 7, 8];
+        let mut bundle = BitVmProofBundle {
+            version: BITVM_PROOF_BUNDLE_VERSION,
+            proof_uncompressed: proof_to_uncompressed(&proof),
+            proof_compressed: proof_to_compressed(&proof),
+            public_input: [0; 32],
+            method_id,
+            journal: vec![0xab; 32],
+            pre_state_digest: risc0_zkvm::sha::Digest::from(method_id).into(),
+            post_state_digest: [0x22; 32],
+            control_root: [0x33; 32],
+            bn254_control_id: [0x44; 32],
+        };
+        bundle.public_input = bundle.expected_public_input();
+        bundle
+    }
+
+    #[test]
+    fn test_fixture_bundle_reaches_the_pairing_check() {
+        assert!(matches!(
+            fixture_bundle().validate(),
+            Err(BundleError::InvalidProof)
+        ));
+    }
+
+    #[test]
+    fn test_bundle_round_trip() {
+        let bundle = fixture_bundle();
+        let dir = tempfile::tempdir().unwrap();
+        bundle.save(dir.path()).unwrap();
+        for file_name in ["bundle.borsh", "bundle.json"] {
+            let loaded = BitVmProofBundle::load(&dir.path().join(file_name)).unwrap();
+            assert_eq!(loaded, bundle);
+        }
+
+        let json: serde_json::Value =
+            serde_json::from_slice(&fs::read(dir.path().join("bundle.json")).unwrap()).unwrap();
+        assert_eq!(json["journal"], hex::encode(&bundle.journal));
+        assert_eq!(json["public_input"], hex::encode(bundle.public_input));
+    }
+
+    #[test]
+    fn test_bundle_version() {
+        let mut bundle = fixture_bundle();
+        bundle.version = BITVM_PROOF_BUNDLE_VERSION + 1;
+        assert!(matches!(
+            bundle.validate(),
+            Err(BundleError::UnsupportedVersion(version)) if version == BITVM_PROOF_BUNDLE_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn test_bundle_proof_encodings_mismatch() {
+        let mut bundle = fixture_bundle();
+        let mut other = proof_from_uncompressed(&bundle.proof_uncompressed).unwrap();
+        std::mem::swap(&mut other.a, &mut other.c);
+        bundle.proof_compressed = proof_to_compressed(&other);
+        assert!(matches!(
+            bundle.validate(),
+            Err(BundleError::MalformedProof(_))
+        ));
+
+        let mut bundle = fixture_bundle();
+        bundle.proof_uncompressed[0] ^= 1;
+        assert!(matches!(
+            bundle.validate(),
+            Err(BundleError::MalformedProof(_))
+        ));
+    }
+
+    #[test]
+    fn test_bundle_tampered_public_input() {
+        let mut bundle = fixture_bundle();
+        bundle.public_input[31] ^= 1;
+        assert!(matches!(
+            bundle.validate(),
+            Err(BundleError::PublicInputMismatch { .. })
+        ));
+
+        let mut bundle = fixture_bundle();
+        bundle.journal[0] ^= 1;
+        assert!(matches!(
+            bundle.validate(),
+            Err(BundleError::PublicInputMismatch { .. })
+        ));
+
+        let mut bundle = fixture_bundle();
+        bundle.method_id[0] ^= 1;
+        assert!(matches!(
+            bundle.validate(),
+            Err(BundleError::MethodIdMismatch)
+        ));
+    }
+}
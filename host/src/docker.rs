@@ -9,60 +9,261 @@ use risc0_zkvm::{
 use serde_json::Value;
 use std::{
     env::consts::ARCH,
-    fs,
-    path::Path,
-    process::{Command, Stdio},
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 use tempfile::tempdir;
 
+use crate::calculate_succinct_output_prefix;
+
+/// The default Groth16 prover image, see `groth16_proof/docker/prover.Dockerfile`.
+pub const DEFAULT_GROTH16_PROVER_IMAGE: &str = "ozancw/risc0-to-bitvm2-groth16-prover:latest";
+
+/// How to run the Groth16 prover container.
+#[derive(clap::Args, Debug, Clone, PartialEq, Eq)]
+pub struct Groth16ProverConfig {
+    /// Container runtime binary (e.g. `docker`, `podman`).
+    #[clap(long = "groth16-runtime", default_value = "docker")]
+    pub runtime: String,
+    /// Prover image reference, preferably pinned by digest.
+    #[clap(long = "groth16-image", default_value = DEFAULT_GROTH16_PROVER_IMAGE)]
+    pub image: String,
+    /// Platform passed with `--platform`, empty to omit the flag.
+    #[clap(long = "groth16-platform", default_value = "linux/amd64")]
+    pub platform: String,
+    /// Directory shared with the container as `/mnt`. A temporary directory is used if not set.
+    #[clap(long = "groth16-work-dir", env = "RISC0_WORK_DIR")]
+    pub work_dir: Option<PathBuf>,
+    /// Additional volume mounts in the runtime's `host:container[:options]` syntax.
+    #[clap(long = "groth16-mount")]
+    pub mounts: Vec<String>,
+    /// Memory limit passed with `--memory` (e.g. `16g`).
+    #[clap(long = "groth16-memory")]
+    pub memory: Option<String>,
+    /// CPU limit passed with `--cpus` (e.g. `8`).
+    #[clap(long = "groth16-cpus")]
+    pub cpus: Option<String>,
+    /// Kill the container after this many seconds.
+    #[clap(long = "groth16-timeout-secs")]
+    pub timeout_secs: Option<u64>,
+    /// Keep the temporary work directory after the run for debugging.
+    #[clap(long = "groth16-keep-work-dir")]
+    pub keep_work_dir: bool,
+}
+
+impl Default for Groth16ProverConfig {
+    fn default() -> Self {
+        Groth16ProverConfig {
+            runtime: "docker".to_string(),
+            image: DEFAULT_GROTH16_PROVER_IMAGE.to_string(),
+            platform: "linux/amd64".to_string(),
+            work_dir: std::env::var_os("RISC0_WORK_DIR").map(PathBuf::from),
+            mounts: vec![],
+            memory: None,
+            cpus: None,
+            timeout_secs: None,
+            keep_work_dir: false,
+        }
+    }
+}
+
+impl Groth16ProverConfig {
+    /// Arguments passed to the runtime binary to run the prover over `work_dir` in a container
+    /// named `name`.
+    fn run_args(&self, work_dir: &Path, name: &str) -> Vec<String> {
+        let mut args = vec![
+            "run".to_string(),
+            "--rm".to_string(),
+            format!("--name={}", name),
+        ];
+        if !self.platform.is_empty() {
+            args.push(format!("--platform={}", self.platform));
+        }
+        if let Some(memory) = &self.memory {
+            args.push(format!("--memory={}", memory));
+        }
+        if let Some(cpus) = &self.cpus {
+            args.push(format!("--cpus={}", cpus));
+        }
+        args.push("-v".to_string());
+        args.push(format!("{}:/mnt", work_dir.to_string_lossy()));
+        for mount in &self.mounts {
+            args.push("-v".to_string());
+            args.push(mount.clone());
+        }
+        args.push(self.image.clone());
+        args
+    }
+}
+
+/// The result of a successful Groth16 proving run.
+#[derive(Debug, Clone)]
+pub struct Groth16Output {
+    pub seal: Seal,
+    /// The single public output of the Groth16 circuit (the first 31 bytes of the Blake3 digest).
+    pub public_output: [u8; 31],
+}
+
+/// Errors that can occur while converting a succinct STARK receipt into a Groth16 proof.
+#[derive(Debug)]
+pub enum Groth16Error {
+    /// `identity_p254` failed to convert the succinct receipt to the Poseidon254 hash function.
+    IdentityP254(String),
+    /// The claim of the succinct receipt is pruned, so its pre and post states are unknown.
+    PrunedClaim(String),
+    /// The identity_p254 seal could not be converted into the prover's `input.json`.
+    MalformedSeal(String),
+    /// The Groth16 prover image is only available for x86 hosts.
+    UnsupportedArchitecture(&'static str),
+    /// The container runtime is not installed or not reachable.
+    RuntimeNotInstalled(String),
+    /// The container runtime could not start or wait for the prover container (e.g. the daemon
+    /// is unavailable or the image could not be pulled).
+    RuntimeFailed(String),
+    /// The prover exited with a failure status.
+    ContainerFailed {
+        exit_code: Option<i32>,
+        stderr: String,
+    },
+    /// The prover container did not finish within the configured timeout (in seconds).
+    Timeout(u64),
+    /// Reading or writing the work directory failed.
+    Io(io::Error),
+    /// `proof.json` is missing or could not be converted into a `Seal`.
+    MalformedProof(String),
+    /// `public.json` is missing or does not hold a single 31-byte field element.
+    MalformedPublicOutput(String),
+    /// The public output of the Groth16 proof does not commit to the given receipt.
+    PublicOutputMismatch {
+        expected: [u8; 31],
+        actual: [u8; 31],
+    },
+}
+
+impl Groth16Error {
+    /// Whether retrying the same request may succeed (e.g. the daemon was briefly unavailable).
+    /// A prover that ran and failed would fail again on the same input, so it is terminal.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            Groth16Error::RuntimeFailed(_) | Groth16Error::Timeout(_)
+        )
+    }
+}
+
+impl fmt::Display for Groth16Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Groth16Error::IdentityP254(e) => write!(f, "identity_p254 failed: {}", e),
+            Groth16Error::PrunedClaim(e) => write!(f, "receipt claim is pruned: {}", e),
+            Groth16Error::MalformedSeal(e) => write!(f, "malformed input.json: {}", e),
+            Groth16Error::UnsupportedArchitecture(arch) => write!(
+                f,
+                "stark_to_succinct is only supported on x86 architecture, found {}",
+                arch
+            ),
+            Groth16Error::RuntimeNotInstalled(runtime) => {
+                write!(f, "container runtime `{}` is not installed", runtime)
+            }
+            Groth16Error::RuntimeFailed(e) => {
+                write!(f, "container runtime failed to run the prover: {}", e)
+            }
+            Groth16Error::ContainerFailed { exit_code, stderr } => write!(
+                f,
+                "groth16 prover container failed with exit code {:?}: {}",
+                exit_code, stderr
+            ),
+            Groth16Error::Timeout(secs) => {
+                write!(f, "groth16 prover container timed out after {}s", secs)
+            }
+            Groth16Error::Io(e) => write!(f, "work directory i/o error: {}", e),
+            Groth16Error::MalformedProof(e) => write!(f, "malformed proof.json: {}", e),
+            Groth16Error::MalformedPublicOutput(e) => write!(f, "malformed public.json: {}", e),
+            Groth16Error::PublicOutputMismatch { expected, actual } => write!(
+                f,
+                "public output mismatch: expected {}, got {}",
+                hex::encode(expected),
+                hex::encode(actual)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Groth16Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Groth16Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Groth16Error {
+    fn from(e: io::Error) -> Self {
+        Groth16Error::Io(e)
+    }
+}
+
 pub fn stark_to_succinct(
     succinct_receipt: SuccinctReceipt<ReceiptClaim>,
     journal: &[u8],
-) -> (Seal, [u8; 31]) {
-    let ident_receipt = risc0_zkvm::recursion::identity_p254(&succinct_receipt).unwrap();
+    config: &Groth16ProverConfig,
+) -> Result<Groth16Output, Groth16Error> {
+    let ident_receipt = risc0_zkvm::recursion::identity_p254(&succinct_receipt)
+        .map_err(|e| Groth16Error::IdentityP254(e.to_string()))?;
     let identity_p254_seal_bytes = ident_receipt.get_seal_bytes();
-    let receipt_claim = succinct_receipt.claim.value().unwrap();
+    let receipt_claim = succinct_receipt
+        .claim
+        .value()
+        .map_err(|e| Groth16Error::PrunedClaim(e.to_string()))?;
 
     // This part is from risc0-groth16
     if !is_x86_architecture() {
-        panic!("stark_to_snark is only supported on x86 architecture.")
+        return Err(Groth16Error::UnsupportedArchitecture(ARCH));
     }
-    if !is_docker_installed() {
-        panic!("Please install docker first.")
+    if !is_runtime_installed(&config.runtime) {
+        return Err(Groth16Error::RuntimeNotInstalled(config.runtime.clone()));
     }
 
-    let tmp_dir = tempdir().unwrap();
-    let work_dir = std::env::var("RISC0_WORK_DIR");
-    let work_dir = work_dir.as_ref().map(Path::new).unwrap_or(tmp_dir.path());
-    println!("work_dir: {:?}", work_dir);
-    std::fs::write(work_dir.join("seal.r0"), identity_p254_seal_bytes.clone()).unwrap();
+    let tmp_dir = tempdir()?;
+    let work_dir = match &config.work_dir {
+        Some(work_dir) => work_dir.clone(),
+        // Kept before anything can fail, failed runs are the ones worth debugging.
+        None if config.keep_work_dir => {
+            let work_dir = tmp_dir.into_path();
+            println!("Keeping work dir: {:?}", work_dir);
+            work_dir
+        }
+        None => tmp_dir.path().to_path_buf(),
+    };
+    std::fs::write(work_dir.join("seal.r0"), identity_p254_seal_bytes.clone())?;
     let seal_path = work_dir.join("input.json");
     let proof_path = work_dir.join("proof.json");
     let output_path = work_dir.join("public.json");
     let mut seal_json = Vec::new();
-    to_json(&*identity_p254_seal_bytes, &mut seal_json).unwrap();
-    std::fs::write(seal_path.clone(), seal_json).unwrap();
+    to_json(&*identity_p254_seal_bytes, &mut seal_json)
+        .map_err(|e| Groth16Error::MalformedSeal(e.to_string()))?;
+    std::fs::write(seal_path.clone(), seal_json)?;
 
     let pre_state: risc0_zkvm::MaybePruned<SystemState> = receipt_claim.clone().pre;
-    println!("pre_state: {:?}", pre_state);
     let pre_state_digest: Digest = pre_state.clone().digest();
-    println!("pre_state_digest: {:?}", pre_state_digest);
     let pre_state_digest_bits: Vec<String> = pre_state_digest
         .as_bytes()
         .iter()
         .flat_map(|&byte| (0..8).rev().map(move |i| ((byte >> i) & 1).to_string()))
         .collect();
-    println!("pre_state_digest_bits: {:?}", pre_state_digest_bits);
     let post_state: risc0_zkvm::MaybePruned<SystemState> = receipt_claim.clone().post;
-    println!("post_state: {:?}", post_state);
     let post_state_digest: Digest = post_state.clone().digest();
     let post_state_digest_bits: Vec<String> = post_state_digest
         .as_bytes()
         .iter()
         .flat_map(|&byte| (0..8).rev().map(move |i| ((byte >> i) & 1).to_string()))
         .collect();
-    println!("post_state_digest_bits: {:?}", post_state_digest_bits);
 
     let mut journal_bits = Vec::new();
     for byte in journal {
@@ -70,44 +271,33 @@ pub fn stark_to_succinct(
             journal_bits.push((byte >> (7 - i)) & 1);
         }
     }
-    println!("journal_bits len: {:?}", journal_bits.len());
 
     let succinct_verifier_params = SuccinctReceiptVerifierParameters::default();
-    println!("Succinct verifier params: {:?}", succinct_verifier_params);
     let succinct_control_root = succinct_verifier_params.control_root;
-    println!("Succinct control root: {:?}", succinct_control_root);
-    let mut succinct_control_root_bytes: [u8; 32] =
-        succinct_control_root.as_bytes().try_into().unwrap();
+    let mut succinct_control_root_bytes: [u8; 32] = succinct_control_root
+        .as_bytes()
+        .try_into()
+        .expect("Digest is 32 bytes");
     succinct_control_root_bytes.reverse();
     let succinct_control_root_bytes: String = succinct_control_root_bytes.encode_hex();
     let a1_str = succinct_control_root_bytes[0..32].to_string();
     let a0_str = succinct_control_root_bytes[32..64].to_string();
-    println!("Succinct control root a0: {:?}", a0_str);
-    println!("Succinct control root a1: {:?}", a1_str);
-    let a0_dec = to_decimal(&a0_str).unwrap();
-    let a1_dec = to_decimal(&a1_str).unwrap();
-    println!("Succinct control root a0 dec: {:?}", a0_dec);
-    println!("Succinct control root a1 dec: {:?}", a1_dec);
-    println!("CONTROL_ID: {:?}", ident_receipt.control_id);
+    let a0_dec = to_decimal(&a0_str).expect("Hex encoding is always a valid number");
+    let a1_dec = to_decimal(&a1_str).expect("Hex encoding is always a valid number");
     let mut id_bn254_fr_bits: Vec<String> = ident_receipt
         .control_id
         .as_bytes()
         .iter()
         .flat_map(|&byte| (0..8).rev().map(move |i| ((byte >> i) & 1).to_string()))
         .collect();
-    println!("id_bn254_fr_bits: {:?}", id_bn254_fr_bits);
     // remove 248th and 249th bits
     id_bn254_fr_bits.remove(248);
     id_bn254_fr_bits.remove(248);
 
-    println!(
-        "id_bn254_fr_bits after removing 2 extra bits: {:?}",
-        id_bn254_fr_bits
-    );
-
     let mut seal_json: Value = {
-        let file_content = fs::read_to_string(&seal_path).unwrap();
-        serde_json::from_str(&file_content).unwrap()
+        let file_content = fs::read_to_string(&seal_path)?;
+        serde_json::from_str(&file_content)
+            .map_err(|e| Groth16Error::MalformedSeal(e.to_string()))?
     };
 
     seal_json["journal_digest_bits"] = journal_bits.into();
@@ -115,59 +305,168 @@ pub fn stark_to_succinct(
     seal_json["post_state_digest_bits"] = post_state_digest_bits.into();
     seal_json["id_bn254_fr_bits"] = id_bn254_fr_bits.into();
     seal_json["control_root"] = vec![a0_dec, a1_dec].into();
-    std::fs::write(seal_path, serde_json::to_string_pretty(&seal_json).unwrap()).unwrap();
-
-    let output = Command::new("docker")
-        .arg("run")
-        .arg("--rm")
-        .arg("--platform=linux/amd64") // Force linux/amd64 platform
-        .arg("-v")
-        .arg(format!("{}:/mnt", work_dir.to_string_lossy()))
-        .arg("ozancw/risc0-to-bitvm2-groth16-prover:latest")
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
-        .unwrap();
+    std::fs::write(
+        seal_path,
+        serde_json::to_string_pretty(&seal_json).expect("JSON value is always serializable"),
+    )?;
 
-    println!("Output: {:?}", output);
+    run_prover(config, &work_dir)?;
+    let proof_content = std::fs::read_to_string(proof_path)
+        .map_err(|e| Groth16Error::MalformedProof(e.to_string()))?;
+    let output_content_dec = std::fs::read_to_string(output_path)
+        .map_err(|e| Groth16Error::MalformedPublicOutput(e.to_string()))?;
+    let seal = parse_proof(&proof_content)?;
+    let public_output = parse_public_output(&output_content_dec)?;
 
-    if !output.status.success() {
-        eprintln!(
-            "docker returned failure exit code: {:?}",
-            output.status.code()
-        );
+    // The Groth16 circuit binds the constants digest and the journal with Blake3.
+    let constants_digest = calculate_succinct_output_prefix(pre_state_digest.as_bytes());
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&constants_digest);
+    hasher.update(journal);
+    let expected: [u8; 31] = hasher.finalize().as_bytes()[..31]
+        .try_into()
+        .expect("Blake3 digest is 32 bytes");
+    if expected != public_output {
+        return Err(Groth16Error::PublicOutputMismatch {
+            expected,
+            actual: public_output,
+        });
     }
-    println!("proof_path: {:?}", proof_path);
-    let proof_content = std::fs::read_to_string(proof_path).unwrap();
-    let output_content_dec = std::fs::read_to_string(output_path).unwrap();
-    println!("output content: {:?}", output_content_dec);
-    let proof_json: ProofJson = serde_json::from_str(&proof_content).unwrap();
-    // let output_json: Value = serde_json::from_str(&output_content).unwrap();
-    // Convert output_content_dec from decimal to hex
-    let parsed_json: Value = serde_json::from_str(&output_content_dec).unwrap();
-    let output_str = parsed_json[0].as_str().unwrap(); // Extracts the string from the JSON array
-
-    // Step 2: Convert the decimal string to BigUint and then to hexadecimal
-    let output_content_hex = BigUint::from_str_radix(output_str, 10)
-        .unwrap()
-        .to_str_radix(16);
-
-    // If the length of the hexadecimal string is odd, add a leading zero
-    let output_content_hex = if output_content_hex.len() % 2 == 0 {
-        output_content_hex
-    } else {
-        format!("0{}", output_content_hex)
+
+    Ok(Groth16Output {
+        seal,
+        public_output,
+    })
+}
+
+/// Exit code `docker run` and `podman run` use when the runtime itself failed, as opposed to
+/// the command inside the container.
+const RUNTIME_ERROR_EXIT_CODE: i32 = 125;
+
+/// Runs the prover container over `work_dir`, which must contain `input.json`.
+/// The container output is written to `prover.stdout.log` and `prover.stderr.log` in `work_dir`.
+pub fn run_prover(config: &Groth16ProverConfig, work_dir: &Path) -> Result<(), Groth16Error> {
+    let stdout_path = work_dir.join("prover.stdout.log");
+    let stderr_path = work_dir.join("prover.stderr.log");
+    let name = container_name();
+    let mut child = Command::new(&config.runtime)
+        .args(config.run_args(work_dir, &name))
+        .stdout(Stdio::from(fs::File::create(&stdout_path)?))
+        .stderr(Stdio::from(fs::File::create(&stderr_path)?))
+        .spawn()
+        .map_err(|e| Groth16Error::RuntimeFailed(e.to_string()))?;
+
+    let status = match config.timeout_secs {
+        Some(timeout_secs) => {
+            match wait_with_timeout(&mut child, Duration::from_secs(timeout_secs))
+                .map_err(|e| Groth16Error::RuntimeFailed(e.to_string()))?
+            {
+                Some(status) => status,
+                None => {
+                    remove_container(config, &name);
+                    return Err(Groth16Error::Timeout(timeout_secs));
+                }
+            }
+        }
+        None => child
+            .wait()
+            .map_err(|e| Groth16Error::RuntimeFailed(e.to_string()))?,
     };
 
-    // Step 3: Decode the hexadecimal string to a byte vector
-    let output_byte_vec = hex::decode(&output_content_hex).unwrap();
-    // let output_byte_vec = hex::decode(output_hex).unwrap();
-    let output_bytes: [u8; 31] = output_byte_vec.as_slice().try_into().unwrap();
-    (proof_json.try_into().unwrap(), output_bytes)
+    let stderr = || fs::read_to_string(&stderr_path).unwrap_or_default();
+    if status.code() == Some(RUNTIME_ERROR_EXIT_CODE) {
+        return Err(Groth16Error::RuntimeFailed(stderr()));
+    }
+    if !status.success() {
+        return Err(Groth16Error::ContainerFailed {
+            exit_code: status.code(),
+            stderr: stderr(),
+        });
+    }
+    Ok(())
+}
+
+/// A container name unique to this run, so a timed out container can be removed.
+fn container_name() -> String {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.subsec_nanos())
+        .unwrap_or_default();
+    format!(
+        "risc0-groth16-prover-{}-{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed),
+        nanos
+    )
+}
+
+/// Force removes the container. Killing the `run` client leaves the container running.
+fn remove_container(config: &Groth16ProverConfig, name: &str) {
+    let removed = Command::new(&config.runtime)
+        .args(["rm", "-f", name])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false);
+    if !removed {
+        println!("Failed to remove the prover container {}", name);
+    }
+}
+
+/// Waits for the child to exit, killing it if it is still running after `timeout`.
+fn wait_with_timeout(
+    child: &mut std::process::Child,
+    timeout: Duration,
+) -> io::Result<Option<ExitStatus>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if Instant::now() >= deadline {
+            child.kill()?;
+            child.wait()?;
+            return Ok(None);
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+fn parse_proof(proof_content: &str) -> Result<Seal, Groth16Error> {
+    let proof_json: ProofJson = serde_json::from_str(proof_content)
+        .map_err(|e| Groth16Error::MalformedProof(e.to_string()))?;
+    Seal::try_from(proof_json).map_err(|e| Groth16Error::MalformedProof(e.to_string()))
 }
 
-fn is_docker_installed() -> bool {
-    Command::new("docker")
+/// Parses `public.json`, a JSON array holding the public output as a decimal string.
+fn parse_public_output(output_content_dec: &str) -> Result<[u8; 31], Groth16Error> {
+    let parsed_json: Value = serde_json::from_str(output_content_dec)
+        .map_err(|e| Groth16Error::MalformedPublicOutput(e.to_string()))?;
+    let output_str = parsed_json[0].as_str().ok_or_else(|| {
+        Groth16Error::MalformedPublicOutput("expected an array with a string element".to_string())
+    })?;
+
+    // Convert the decimal string to BigUint and then to big-endian bytes
+    let output_content = BigUint::from_str_radix(output_str, 10)
+        .map_err(|e| Groth16Error::MalformedPublicOutput(e.to_string()))?
+        .to_bytes_be();
+    if output_content.len() > 31 {
+        return Err(Groth16Error::MalformedPublicOutput(format!(
+            "output is {} bytes, expected at most 31",
+            output_content.len()
+        )));
+    }
+
+    // Left-pad with zeros, leading zero bytes are dropped by the decimal encoding
+    let mut output_bytes = [0u8; 31];
+    output_bytes[31 - output_content.len()..].copy_from_slice(&output_content);
+    Ok(output_bytes)
+}
+
+fn is_runtime_installed(runtime: &str) -> bool {
+    Command::new(runtime)
         .arg("--version")
         .output()
         .map(|output| output.status.success())
@@ -182,3 +481,180 @@ pub fn to_decimal(s: &str) -> Option<String> {
     let int = BigUint::from_str_radix(s, 16).ok();
     int.map(|n| n.to_str_radix(10))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_public_output() {
+        let bytes: [u8; 31] = core::array::from_fn(|i| i as u8);
+        let dec = BigUint::from_bytes_be(&bytes).to_str_radix(10);
+        let parsed = parse_public_output(&format!("[\"{}\"]", dec)).unwrap();
+        assert_eq!(parsed, bytes);
+    }
+
+    #[test]
+    fn test_parse_public_output_malformed() {
+        for content in ["", "[]", "[1]", "[\"abc\"]", "{\"a\": \"1\"}"] {
+            assert!(matches!(
+                parse_public_output(content),
+                Err(Groth16Error::MalformedPublicOutput(_))
+            ));
+        }
+        let too_large = BigUint::from_bytes_be(&[0xff; 32]).to_str_radix(10);
+        assert!(matches!(
+            parse_public_output(&format!("[\"{}\"]", too_large)),
+            Err(Groth16Error::MalformedPublicOutput(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_proof_malformed() {
+        assert!(matches!(
+            parse_proof("{}"),
+            Err(Groth16Error::MalformedProof(_))
+        ));
+        let proof = r#"{"pi_a": ["1"], "pi_b": [], "pi_c": [], "protocol": null, "curve": null}"#;
+        assert!(matches!(
+            parse_proof(proof),
+            Err(Groth16Error::MalformedProof(_))
+        ));
+    }
+
+    /// Writes a fake container runtime that logs its arguments to `dir/args` and runs `body` for
+    /// `run`, with `$dir` set to `dir`.
+    fn fake_runtime(dir: &Path, body: &str) -> Groth16ProverConfig {
+        use std::os::unix::fs::PermissionsExt;
+
+        let script_path = dir.join("fake-runtime.sh");
+        let script = format!(
+            "#!/bin/sh\n[ \"$1\" = \"--version\" ] && exit 0\ndir={}\necho \"$@\" >> \"$dir/args\"\n[ \"$1\" = \"run\" ] || exit 0\n{}\n",
+            dir.to_string_lossy(),
+            body
+        );
+        fs::write(&script_path, script).unwrap();
+        fs::set_permissions(&script_path, fs::Permissions::from_mode(0o755)).unwrap();
+        Groth16ProverConfig {
+            runtime: script_path.to_string_lossy().into_owned(),
+            work_dir: Some(dir.to_path_buf()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_run_prover_with_fake_runtime() {
+        let dir = tempdir().unwrap();
+        let mut config = fake_runtime(
+            dir.path(),
+            "echo '[\"1\"]' > \"$dir/public.json\"; echo '{}' > \"$dir/proof.json\"",
+        );
+        config.image = "example.org/prover@sha256:00".to_string();
+        config.platform = String::new();
+        config.memory = Some("16g".to_string());
+        config.cpus = Some("8".to_string());
+        config.mounts = vec!["/tmp/keys:/keys:ro".to_string()];
+
+        assert!(is_runtime_installed(&config.runtime));
+        run_prover(&config, dir.path()).unwrap();
+
+        let args = fs::read_to_string(dir.path().join("args")).unwrap();
+        let name = args.split_whitespace().nth(2).unwrap();
+        assert!(name.starts_with("--name=risc0-groth16-prover-"));
+        assert_eq!(
+            args.trim(),
+            format!(
+                "run --rm {} --memory=16g --cpus=8 -v {}:/mnt -v /tmp/keys:/keys:ro example.org/prover@sha256:00",
+                name,
+                dir.path().to_string_lossy()
+            )
+        );
+        assert_eq!(
+            parse_public_output(&fs::read_to_string(dir.path().join("public.json")).unwrap())
+                .unwrap()[30],
+            1
+        );
+    }
+
+    #[test]
+    fn test_run_prover_container_failure() {
+        let dir = tempdir().unwrap();
+        let config = fake_runtime(dir.path(), "echo 'out of memory' >&2; exit 137");
+        let err = run_prover(&config, dir.path()).unwrap_err();
+        assert!(!err.is_transient());
+        match err {
+            Groth16Error::ContainerFailed { exit_code, stderr } => {
+                assert_eq!(exit_code, Some(137));
+                assert_eq!(stderr.trim(), "out of memory");
+            }
+            other => panic!("Unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_run_prover_runtime_failure() {
+        let dir = tempdir().unwrap();
+        let config = fake_runtime(
+            dir.path(),
+            "echo 'Cannot connect to the daemon' >&2; exit 125",
+        );
+        let err = run_prover(&config, dir.path()).unwrap_err();
+        assert!(err.is_transient());
+        match err {
+            Groth16Error::RuntimeFailed(stderr) => {
+                assert_eq!(stderr.trim(), "Cannot connect to the daemon")
+            }
+            other => panic!("Unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_run_prover_timeout() {
+        let dir = tempdir().unwrap();
+        let mut config = fake_runtime(dir.path(), "sleep 10");
+        config.timeout_secs = Some(1);
+        assert!(matches!(
+            run_prover(&config, dir.path()),
+            Err(Groth16Error::Timeout(1))
+        ));
+
+        // The container is removed by name, killing the client alone would leave it running.
+        let args = fs::read_to_string(dir.path().join("args")).unwrap();
+        let lines: Vec<&str> = args.lines().collect();
+        let name = lines[0].split_whitespace().nth(2).unwrap();
+        assert_eq!(
+            lines[1],
+            format!("rm -f {}", name.trim_start_matches("--name="))
+        );
+    }
+
+    #[test]
+    fn test_container_names_are_unique() {
+        assert_ne!(container_name(), container_name());
+    }
+
+    #[test]
+    fn test_missing_runtime() {
+        assert!(!is_runtime_installed("/nonexistent/container-runtime"));
+    }
+
+    #[test]
+    fn test_transient_errors() {
+        assert!(Groth16Error::RuntimeFailed("daemon unavailable".to_string()).is_transient());
+        assert!(Groth16Error::Timeout(60).is_transient());
+        assert!(!Groth16Error::ContainerFailed {
+            exit_code: Some(1),
+            stderr: String::new(),
+        }
+        .is_transient());
+        assert!(!Groth16Error::Io(io::Error::other("disk full")).is_transient());
+        assert!(!Groth16Error::RuntimeNotInstalled("docker".to_string()).is_transient());
+        assert!(!Groth16Error::PrunedClaim("pruned".to_string()).is_transient());
+        assert!(!Groth16Error::MalformedSeal("eof".to_string()).is_transient());
+        assert!(!Groth16Error::PublicOutputMismatch {
+            expected: [0; 31],
+            actual: [1; 31],
+        }
+        .is_transient());
+    }
+}
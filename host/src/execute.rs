@@ -0,0 +1,179 @@
+use borsh::BorshSerialize;
+use risc0_to_bitvm2_core::zkvm::CycleMarker;
+use risc0_zkvm::{default_executor, AssumptionReceipt, ExecutorEnv, SessionInfo};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+/// The cycles spent in a phase of a guest, between two of its cycle markers.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PhaseCycles {
+    pub phase: String,
+    pub cycles: u64,
+}
+
+/// The result of executing a guest without proving it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ExecutionReport {
+    /// The user cycles, without the overhead of continuations or padding.
+    pub user_cycles: u64,
+    /// The proven cycles, i.e. the sum of the padded segment sizes.
+    pub total_cycles: u64,
+    /// The po2 of each segment.
+    pub segment_po2s: Vec<u32>,
+    pub phases: Vec<PhaseCycles>,
+    #[serde(with = "hex::serde")]
+    pub journal: Vec<u8>,
+}
+
+impl ExecutionReport {
+    /// Builds the report of a session, with the phases from the cycle markers in `guest_stderr`.
+    pub fn new(session_info: &SessionInfo, guest_stderr: &str) -> Self {
+        ExecutionReport {
+            user_cycles: session_info.cycles(),
+            total_cycles: session_info
+                .segments
+                .iter()
+                .map(|segment| 1u64 << segment.po2)
+                .sum(),
+            segment_po2s: session_info
+                .segments
+                .iter()
+                .map(|segment| segment.po2)
+                .collect(),
+            phases: phase_cycles(&CycleMarker::parse_all(guest_stderr)),
+            journal: session_info.journal.bytes.clone(),
+        }
+    }
+
+    pub fn segment_count(&self) -> usize {
+        self.segment_po2s.len()
+    }
+}
+
+/// Converts cycle markers into the cycles spent in each phase.
+pub fn phase_cycles(markers: &[CycleMarker]) -> Vec<PhaseCycles> {
+    let mut prev_cycle_count = 0;
+    markers
+        .iter()
+        .map(|marker| {
+            let cycles = marker.cycle_count.saturating_sub(prev_cycle_count);
+            prev_cycle_count = marker.cycle_count;
+            PhaseCycles {
+                phase: marker.phase.clone(),
+                cycles,
+            }
+        })
+        .collect()
+}
+
+/// Executes the guest with the given input and assumptions, without proving it. The assumptions
+/// may be unresolved, e.g. the claim of a header chain proof that does not exist yet.
+pub fn execute(
+    elf: &[u8],
+    input: &impl BorshSerialize,
+    assumptions: Vec<AssumptionReceipt>,
+) -> Result<ExecutionReport, Box<dyn Error>> {
+    let mut guest_stderr = vec![];
+    let session_info = {
+        let mut builder = ExecutorEnv::builder();
+        builder.write_slice(&borsh::to_vec(input)?);
+        for assumption in assumptions {
+            builder.add_assumption(assumption);
+        }
+        let env = builder.stderr(&mut guest_stderr).build()?;
+        default_executor().execute(env, elf)?
+    };
+
+    let guest_stderr = String::from_utf8_lossy(&guest_stderr);
+    // Forward anything the guest wrote to stderr besides the markers.
+    for line in guest_stderr.lines() {
+        if CycleMarker::parse_line(line).is_none() {
+            eprintln!("{}", line);
+        }
+    }
+    Ok(ExecutionReport::new(&session_info, &guest_stderr))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use borsh::BorshDeserialize;
+    use risc0_to_bitvm2_core::{
+        header_chain::{
+            network_constants, BlockHeaderCircuitOutput, CircuitBlockHeader,
+            HeaderChainCircuitInput, HeaderChainPrevProofType,
+        },
+        synthetic::mine,
+    };
+    use risc0_zkvm::compute_image_id;
+
+    const REGTEST_ELF: &[u8] = include_bytes!("../../elfs/regtest-header-chain-guest.bin");
+
+    fn genesis_input(header: CircuitBlockHeader) -> HeaderChainCircuitInput {
+        HeaderChainCircuitInput {
+            method_id: compute_image_id(REGTEST_ELF).unwrap().into(),
+            prev_proof: HeaderChainPrevProofType::GenesisBlock,
+            block_headers: vec![header],
+        }
+    }
+
+    #[test]
+    #[ignore = "the embedded ELFs predate genesis anchoring, rebuild them with REPR_GUEST_BUILD=1"]
+    fn test_header_chain_guest_rejects_forged_genesis() {
+        let genesis = network_constants("regtest")
+            .unwrap()
+            .genesis_block_header
+            .clone();
+        let report = execute(REGTEST_ELF, &genesis_input(genesis), vec![]).unwrap();
+        let output = BlockHeaderCircuitOutput::try_from_slice(&report.journal).unwrap();
+        assert_eq!(output.chain_state.block_height, 0);
+
+        // A block with a valid proof of work and no parent, which is not the genesis block.
+        let mut forged_genesis = network_constants("regtest")
+            .unwrap()
+            .genesis_block_header
+            .clone();
+        forged_genesis.merkle_root = [1; 32];
+        mine(&mut forged_genesis);
+        assert!(execute(REGTEST_ELF, &genesis_input(forged_genesis), vec![]).is_err());
+    }
+
+    #[test]
+    fn test_phase_cycles() {
+        let guest_stderr = [
+            CycleMarker {
+                phase: "read_input".to_string(),
+                cycle_count: 1000,
+            },
+            CycleMarker {
+                phase: "verify_prev_proof".to_string(),
+                cycle_count: 1000,
+            },
+            CycleMarker {
+                phase: "apply_blocks".to_string(),
+                cycle_count: 250000,
+            },
+        ]
+        .iter()
+        .map(|marker| marker.to_line())
+        .collect::<String>();
+
+        assert_eq!(
+            phase_cycles(&CycleMarker::parse_all(&guest_stderr)),
+            vec![
+                PhaseCycles {
+                    phase: "read_input".to_string(),
+                    cycles: 1000,
+                },
+                PhaseCycles {
+                    phase: "verify_prev_proof".to_string(),
+                    cycles: 0,
+                },
+                PhaseCycles {
+                    phase: "apply_blocks".to_string(),
+                    cycles: 249000,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,29 @@
+use risc0_to_bitvm2_core::header_chain::{ChainState, CircuitBlockHeader};
+use std::error::Error;
+
+/// Where the proving loop gets the headers to prove from.
+pub trait HeaderSource {
+    /// Returns the `count` headers after the best block of the chain state.
+    fn headers_after(
+        &mut self,
+        chain_state: &ChainState,
+        count: usize,
+    ) -> Result<Vec<CircuitBlockHeader>, Box<dyn Error>>;
+}
+
+/// Headers from genesis held in memory, e.g. read from a headers file.
+pub struct HeadersFromGenesis(pub Vec<CircuitBlockHeader>);
+
+impl HeaderSource for HeadersFromGenesis {
+    fn headers_after(
+        &mut self,
+        chain_state: &ChainState,
+        count: usize,
+    ) -> Result<Vec<CircuitBlockHeader>, Box<dyn Error>> {
+        let start = chain_state.block_height.wrapping_add(1) as usize;
+        if start + count > self.0.len() {
+            return Err(format!("only {} headers are available", self.0.len()).into());
+        }
+        Ok(self.0[start..start + count].to_vec())
+    }
+}
@@ -0,0 +1,164 @@
+use risc0_to_bitvm2_core::{
+    header_chain::{network_constants, network_name, NETWORK_TYPE},
+    headers_file::HeadersFile,
+};
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Tooling for headers files, see `risc0_to_bitvm2_core::headers_file`.
+#[derive(clap::Subcommand, Debug)]
+pub enum HeadersCommand {
+    /// Convert a legacy file of bare 80-byte headers into a versioned headers file.
+    Convert {
+        /// The legacy headers file path.
+        input: PathBuf,
+        /// The output headers file path.
+        output: PathBuf,
+        /// The network of the headers.
+        #[clap(long, default_value = NETWORK_TYPE)]
+        network: String,
+        /// The height of the first header.
+        #[clap(long, default_value_t = 0)]
+        start_height: u32,
+    },
+    /// Append the new headers of a source file, versioned or legacy, to a versioned headers file.
+    Append {
+        /// The versioned headers file path.
+        file: PathBuf,
+        /// The source headers file path.
+        source: PathBuf,
+        /// The height of the first header of a legacy source.
+        #[clap(long, default_value_t = 0)]
+        source_start_height: u32,
+    },
+    /// Check the checksum of a headers file and that its headers link up.
+    Validate {
+        /// The headers file path.
+        file: PathBuf,
+    },
+}
+
+pub fn run(command: HeadersCommand) -> Result<(), Box<dyn Error>> {
+    match command {
+        HeadersCommand::Convert {
+            input,
+            output,
+            network,
+            start_height,
+        } => {
+            let file = convert(&input, &output, &network, start_height)?;
+            println!(
+                "Converted {} {} headers from height {} to {:?}",
+                file.headers.len(),
+                network,
+                file.start_height,
+                output
+            );
+        }
+        HeadersCommand::Append {
+            file,
+            source,
+            source_start_height,
+        } => {
+            let appended = append(&file, &source, source_start_height)?;
+            println!("Appended {} headers to {:?}", appended, file);
+        }
+        HeadersCommand::Validate { file } => {
+            let headers_file = validate(&file)?;
+            println!(
+                "{:?} is valid: {} headers of {} from height {}",
+                file,
+                headers_file.headers.len(),
+                network_name(headers_file.network_magic).unwrap_or("an unknown network"),
+                headers_file.start_height
+            );
+        }
+    }
+    Ok(())
+}
+
+fn network_magic(network: &str) -> Result<[u8; 4], Box<dyn Error>> {
+    Ok(network_constants(network)
+        .ok_or_else(|| format!("unknown network {}", network))?
+        .magic)
+}
+
+/// Converts a legacy headers file, checking that its headers link up.
+pub fn convert(
+    input: &Path,
+    output: &Path,
+    network: &str,
+    start_height: u32,
+) -> Result<HeadersFile, Box<dyn Error>> {
+    let file = HeadersFile::from_legacy(&fs::read(input)?, network_magic(network)?, start_height)?;
+    file.validate_linkage()?;
+    fs::write(output, file.to_bytes())?;
+    Ok(file)
+}
+
+/// Appends the headers of the source that are not in the file yet. Returns their number.
+pub fn append(
+    path: &Path,
+    source: &Path,
+    source_start_height: u32,
+) -> Result<usize, Box<dyn Error>> {
+    let mut file = HeadersFile::from_bytes(&fs::read(path)?)?;
+    let source_bytes = fs::read(source)?;
+    let source = if HeadersFile::is_versioned(&source_bytes) {
+        HeadersFile::decode(&source_bytes, file.network_magic)?
+    } else {
+        HeadersFile::from_legacy(&source_bytes, file.network_magic, source_start_height)?
+    };
+    let appended = file.append(source.start_height, &source.headers)?;
+    if appended > 0 {
+        fs::write(path, file.to_bytes())?;
+    }
+    Ok(appended)
+}
+
+/// Reads a versioned headers file, checking its checksum and that its headers link up.
+pub fn validate(path: &Path) -> Result<HeadersFile, Box<dyn Error>> {
+    let bytes = fs::read(path)?;
+    if !HeadersFile::is_versioned(&bytes) {
+        return Err("not a versioned headers file, convert it with `headers convert`".into());
+    }
+    let file = HeadersFile::from_bytes(&bytes)?;
+    file.validate_linkage()?;
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REGTEST_HEADERS: &[u8] = include_bytes!("../../data/headers/regtest-headers.bin");
+
+    #[test]
+    fn test_convert_append_validate() {
+        let dir = tempfile::tempdir().unwrap();
+        let legacy = dir.path().join("legacy.bin");
+        let new_headers = dir.path().join("new.bin");
+        let path = dir.path().join("regtest.headers");
+        fs::write(&legacy, &REGTEST_HEADERS[..50 * 80]).unwrap();
+        fs::write(&new_headers, &REGTEST_HEADERS[40 * 80..]).unwrap();
+
+        let file = convert(&legacy, &path, "regtest", 0).unwrap();
+        assert_eq!(file.end_height(), 50);
+        assert_eq!(validate(&path).unwrap(), file);
+
+        // The source overlaps the file from height 40.
+        let appended = append(&path, &new_headers, 40).unwrap();
+        assert_eq!(appended, REGTEST_HEADERS.len() / 80 - 50);
+        let file = validate(&path).unwrap();
+        assert_eq!(file.headers.len() * 80, REGTEST_HEADERS.len());
+        assert_eq!(append(&path, &legacy, 0).unwrap(), 0);
+
+        // A source at the wrong height does not match the file.
+        assert!(append(&path, &new_headers, 41).is_err());
+        assert!(convert(&legacy, &path, "mainnet", 0).is_err());
+        assert!(validate(&legacy).is_err());
+    }
+}
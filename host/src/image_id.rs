@@ -0,0 +1,258 @@
+use bitcoin::{constants::genesis_block, Network};
+use risc0_to_bitvm2_core::{
+    final_circuit::FinalCircuitInput,
+    header_chain::{BlockHeaderCircuitOutput, ChainState, MAINNET_CONSTANTS},
+    merkle_tree::BlockInclusionProof,
+    mmr_native::MMRInclusionProof,
+    spv::SPV,
+    transaction::CircuitTransaction,
+};
+use risc0_zkvm::{compute_image_id, sha::Digestible, ReceiptClaim};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+use crate::execute;
+
+/// The networks with reproducibly built guests in the `elfs` folder. The testnet3 guests are not
+/// checked in, so testnet3 is only included with the `testnet3` feature.
+pub const NETWORKS: &[&str] = &[
+    "mainnet",
+    "testnet4",
+    #[cfg(feature = "testnet3")]
+    "testnet3",
+    "signet",
+    "regtest",
+];
+
+/// `elfs/{network}-image-ids.json`, written by `final-spv/build.rs` when the final circuit is
+/// built reproducibly. `header_chain_guest_id` is the method ID the final circuit verifies.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ImageIdManifest {
+    pub header_chain_guest_id: [u32; 8],
+    pub final_spv_guest_id: [u32; 8],
+    /// The challenge of a custom signet, in hex. Its files are named `signet-{magic}`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signet_challenge: Option<String>,
+    /// The pow limit of a custom signet, as hex compact bits.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signet_pow_limit: Option<String>,
+}
+
+/// Computes the method ID of `elfs/{network}-{guest}-guest.bin`.
+pub fn elf_image_id(elfs_dir: &Path, network: &str, guest: &str) -> Result<[u32; 8], String> {
+    let elf_path = elfs_dir.join(format!("{}-{}-guest.bin", network, guest));
+    let elf_bytes = fs::read(&elf_path).map_err(|e| format!("{:?}: {}", elf_path, e))?;
+    let image_id = compute_image_id(&elf_bytes).map_err(|e| format!("{:?}: {}", elf_path, e))?;
+    Ok(image_id.into())
+}
+
+/// Whether the final circuit ELF verifies header chain proofs of `method_id`, i.e. embeds it as
+/// `HEADER_CHAIN_GUEST_ID`. The ELF is executed on a header chain output of that method ID without
+/// a receipt for it, so it fails when verifying it, naming the claim it looked for.
+pub fn verifies_header_chain(final_spv_elf: &[u8], method_id: [u32; 8]) -> Result<bool, String> {
+    let block_header_circuit_output = BlockHeaderCircuitOutput {
+        method_id,
+        chain_state: ChainState::new(),
+    };
+    let claim = ReceiptClaim::ok(
+        method_id,
+        borsh::to_vec(&block_header_circuit_output).map_err(|e| e.to_string())?,
+    );
+    // Nothing after the header chain proof is reached, so the SPV proof does not need to hold.
+    let genesis = genesis_block(Network::Bitcoin);
+    let input = FinalCircuitInput {
+        block_header_circuit_output,
+        spv: SPV {
+            transaction: CircuitTransaction(genesis.txdata[0].clone()),
+            block_inclusion_proof: BlockInclusionProof::new(0, vec![]),
+            block_header: MAINNET_CONSTANTS.genesis_block_header,
+            mmr_inclusion_proof: MMRInclusionProof::new(0, 0, vec![]),
+        },
+        min_total_work: [0; 32],
+    };
+    match execute::execute(final_spv_elf, &input, vec![]) {
+        Ok(_) => Err("the final circuit did not verify the header chain proof".to_string()),
+        Err(e) => Ok(e.to_string().contains(&hex::encode(claim.digest()))),
+    }
+}
+
+/// Checks that the ELFs of the network match the method IDs in its manifest, and that the final
+/// circuit ELF verifies the current header chain ELF. Returns the list of mismatches.
+pub fn check_network(elfs_dir: &Path, network: &str) -> Result<(), Vec<String>> {
+    let manifest_path = elfs_dir.join(format!("{}-image-ids.json", network));
+    let manifest: ImageIdManifest = fs::read(&manifest_path)
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| serde_json::from_slice(&bytes).map_err(|e| e.to_string()))
+        .map_err(|e| vec![format!("{}: {:?}: {}", network, manifest_path, e)])?;
+
+    let mut errors = vec![];
+    let mut header_chain_guest_id = None;
+    for (guest, expected) in [
+        ("header-chain", manifest.header_chain_guest_id),
+        ("final-spv", manifest.final_spv_guest_id),
+    ] {
+        match elf_image_id(elfs_dir, network, guest) {
+            Ok(image_id) => {
+                if guest == "header-chain" {
+                    header_chain_guest_id = Some(image_id);
+                }
+                if image_id != expected {
+                    errors.push(format!(
+                        "{}: {} ELF has method ID {:?}, but {:?} expects {:?}",
+                        network, guest, image_id, manifest_path, expected
+                    ));
+                }
+            }
+            Err(e) => errors.push(format!("{}: {}", network, e)),
+        }
+    }
+
+    // The manifest may be stale itself, so the final circuit ELF is checked directly too.
+    let final_spv_path = elfs_dir.join(format!("{}-final-spv-guest.bin", network));
+    if let (Some(header_chain_guest_id), Ok(final_spv_elf)) =
+        (header_chain_guest_id, fs::read(&final_spv_path))
+    {
+        match verifies_header_chain(&final_spv_elf, header_chain_guest_id) {
+            Ok(true) => {}
+            Ok(false) => errors.push(format!(
+                "{}: {:?} does not verify the header chain ELF (method ID {:?}), rebuild it",
+                network, final_spv_path, header_chain_guest_id
+            )),
+            Err(e) => errors.push(format!("{}: {:?}: {}", network, final_spv_path, e)),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// The custom signets built into the folder, named `signet-{magic}` after their manifests.
+pub fn custom_signets(elfs_dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(elfs_dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| {
+            let file_name = entry.ok()?.file_name().into_string().ok()?;
+            let magic = file_name
+                .strip_prefix("signet-")?
+                .strip_suffix("-image-ids.json")?;
+            Some(format!("signet-{}", magic))
+        })
+        .collect();
+    names.sort();
+    names
+}
+
+/// Runs `check_network` for every network and custom signet.
+pub fn check_all(elfs_dir: &Path) -> Result<(), Vec<String>> {
+    let errors = NETWORKS
+        .iter()
+        .map(|network| network.to_string())
+        .chain(custom_signets(elfs_dir))
+        .filter_map(|network| check_network(elfs_dir, &network).err())
+        .flatten()
+        .collect::<Vec<String>>();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ELFS_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../elfs");
+
+    #[test]
+    fn test_committed_image_ids() {
+        check_all(Path::new(ELFS_DIR)).unwrap();
+    }
+
+    #[test]
+    fn test_diverged_image_ids() {
+        let dir = tempfile::tempdir().unwrap();
+        for network in NETWORKS {
+            for guest in ["header-chain", "final-spv"] {
+                let file_name = format!("{}-{}-guest.bin", network, guest);
+                fs::copy(
+                    Path::new(ELFS_DIR).join(&file_name),
+                    dir.path().join(&file_name),
+                )
+                .unwrap();
+            }
+            let file_name = format!("{}-image-ids.json", network);
+            fs::copy(
+                Path::new(ELFS_DIR).join(&file_name),
+                dir.path().join(&file_name),
+            )
+            .unwrap();
+        }
+        check_all(dir.path()).unwrap();
+
+        // The regtest header chain ELF was rebuilt without rebuilding the final circuit.
+        fs::copy(
+            dir.path().join("signet-header-chain-guest.bin"),
+            dir.path().join("regtest-header-chain-guest.bin"),
+        )
+        .unwrap();
+        let errors = check_all(dir.path()).unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].starts_with("regtest: header-chain ELF"));
+        assert!(errors[1].contains("does not verify the header chain ELF"));
+
+        // Updating the manifest by hand does not hide that the final circuit was not rebuilt.
+        let manifest_path = dir.path().join("regtest-image-ids.json");
+        let mut manifest: ImageIdManifest =
+            serde_json::from_slice(&fs::read(&manifest_path).unwrap()).unwrap();
+        manifest.header_chain_guest_id =
+            elf_image_id(dir.path(), "regtest", "header-chain").unwrap();
+        fs::write(&manifest_path, serde_json::to_vec(&manifest).unwrap()).unwrap();
+        let errors = check_all(dir.path()).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("does not verify the header chain ELF"));
+
+        fs::remove_file(dir.path().join("mainnet-image-ids.json")).unwrap();
+        assert_eq!(check_all(dir.path()).unwrap_err().len(), 2);
+    }
+
+    #[test]
+    fn test_custom_signet_image_ids() {
+        let dir = tempfile::tempdir().unwrap();
+        for guest in ["header-chain", "final-spv"] {
+            fs::copy(
+                Path::new(ELFS_DIR).join(format!("signet-{}-guest.bin", guest)),
+                dir.path()
+                    .join(format!("signet-54d26fbd-{}-guest.bin", guest)),
+            )
+            .unwrap();
+        }
+        let mut manifest: ImageIdManifest = serde_json::from_slice(
+            &fs::read(Path::new(ELFS_DIR).join("signet-image-ids.json")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(manifest.signet_challenge, None);
+        manifest.signet_challenge = Some("51".to_string());
+        manifest.signet_pow_limit = Some("207fffff".to_string());
+        let manifest_path = dir.path().join("signet-54d26fbd-image-ids.json");
+        fs::write(&manifest_path, serde_json::to_vec(&manifest).unwrap()).unwrap();
+
+        assert_eq!(custom_signets(dir.path()), ["signet-54d26fbd"]);
+        assert_eq!(
+            serde_json::from_slice::<ImageIdManifest>(&fs::read(&manifest_path).unwrap()).unwrap(),
+            manifest
+        );
+        check_network(dir.path(), "signet-54d26fbd").unwrap();
+
+        // The public signet files are missing, but the custom signet is checked too.
+        let errors = check_all(dir.path()).unwrap_err();
+        assert!(errors.iter().all(|e| !e.starts_with("signet-54d26fbd")));
+        fs::remove_file(dir.path().join("signet-54d26fbd-final-spv-guest.bin")).unwrap();
+        let errors = check_all(dir.path()).unwrap_err();
+        assert!(errors.iter().any(|e| e.starts_with("signet-54d26fbd")));
+    }
+}
@@ -0,0 +1,479 @@
+use borsh::BorshDeserialize;
+use crypto_bigint::Encoding;
+use num_bigint::BigUint;
+use num_traits::ToPrimitive;
+use risc0_to_bitvm2_core::{
+    final_circuit::FinalCircuitOutput,
+    header_chain::{
+        bits_to_target, network_constants, BlockHeaderCircuitOutput, ChainState,
+        HeaderChainRangeOutput, RANGE_OUTPUT_TAG,
+    },
+};
+use risc0_zkvm::{compute_image_id, sha::Digestible, InnerReceipt, Receipt};
+use serde::{Deserialize, Serialize};
+use std::{error::Error, fmt, sync::LazyLock};
+
+/// The reproducibly built guests of every network, as (network, guest, ELF).
+pub const EMBEDDED_GUESTS: &[(&str, &str, &[u8])] = &[
+    (
+        "mainnet",
+        "header-chain",
+        include_bytes!("../../elfs/mainnet-header-chain-guest.bin"),
+    ),
+    (
+        "mainnet",
+        "final-spv",
+        include_bytes!("../../elfs/mainnet-final-spv-guest.bin"),
+    ),
+    (
+        "testnet4",
+        "header-chain",
+        include_bytes!("../../elfs/testnet4-header-chain-guest.bin"),
+    ),
+    (
+        "testnet4",
+        "final-spv",
+        include_bytes!("../../elfs/testnet4-final-spv-guest.bin"),
+    ),
+    #[cfg(feature = "testnet3")]
+    (
+        "testnet3",
+        "header-chain",
+        include_bytes!("../../elfs/testnet3-header-chain-guest.bin"),
+    ),
+    #[cfg(feature = "testnet3")]
+    (
+        "testnet3",
+        "final-spv",
+        include_bytes!("../../elfs/testnet3-final-spv-guest.bin"),
+    ),
+    (
+        "signet",
+        "header-chain",
+        include_bytes!("../../elfs/signet-header-chain-guest.bin"),
+    ),
+    (
+        "signet",
+        "final-spv",
+        include_bytes!("../../elfs/signet-final-spv-guest.bin"),
+    ),
+    (
+        "regtest",
+        "header-chain",
+        include_bytes!("../../elfs/regtest-header-chain-guest.bin"),
+    ),
+    (
+        "regtest",
+        "final-spv",
+        include_bytes!("../../elfs/regtest-final-spv-guest.bin"),
+    ),
+];
+
+/// An embedded guest, identified by the image ID of a receipt.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct GuestInfo {
+    pub network: String,
+    pub guest: String,
+    pub image_id: [u32; 8],
+}
+
+/// The image IDs of `EMBEDDED_GUESTS`, computed on first use.
+static EMBEDDED_GUEST_IDS: LazyLock<Vec<GuestInfo>> = LazyLock::new(|| {
+    EMBEDDED_GUESTS
+        .iter()
+        .filter_map(|(network, guest, elf)| {
+            Some(GuestInfo {
+                network: network.to_string(),
+                guest: guest.to_string(),
+                image_id: compute_image_id(elf).ok()?.into(),
+            })
+        })
+        .collect()
+});
+
+/// Finds the embedded guest with the given image ID.
+pub fn find_guest(image_id: [u32; 8]) -> Option<GuestInfo> {
+    EMBEDDED_GUEST_IDS
+        .iter()
+        .find(|info| info.image_id == image_id)
+        .cloned()
+}
+
+/// The kind of the proof in a receipt.
+pub fn receipt_kind(receipt: &Receipt) -> &'static str {
+    match &receipt.inner {
+        InnerReceipt::Composite(_) => "composite",
+        InnerReceipt::Succinct(_) => "succinct",
+        InnerReceipt::Groth16(_) => "groth16",
+        InnerReceipt::Fake(_) => "fake",
+        _ => "unknown",
+    }
+}
+
+/// A `ChainState` in the units of block explorers.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChainSummary {
+    /// `None` before the genesis block.
+    pub block_height: Option<u32>,
+    /// The best block hash in display (reversed) byte order.
+    pub best_block_hash: String,
+    pub total_work: String,
+    pub total_work_log2: f64,
+    pub current_target_bits: String,
+    pub current_target: String,
+    pub epoch_start_time: u32,
+    pub mmr_size: u32,
+}
+
+impl ChainSummary {
+    pub fn new(chain_state: &ChainState) -> Self {
+        let mut best_block_hash = chain_state.best_block_hash;
+        best_block_hash.reverse();
+        let total_work = BigUint::from_bytes_be(&chain_state.total_work);
+        ChainSummary {
+            block_height: (chain_state.block_height != u32::MAX)
+                .then_some(chain_state.block_height),
+            best_block_hash: hex::encode(best_block_hash),
+            total_work_log2: total_work.to_f64().unwrap_or(f64::INFINITY).log2(),
+            total_work: total_work.to_string(),
+            current_target_bits: format!("{:08x}", chain_state.current_target_bits),
+            current_target: hex::encode(bits_to_target(chain_state.current_target_bits)),
+            epoch_start_time: chain_state.epoch_start_time,
+            mmr_size: chain_state.block_hashes_mmr.size,
+        }
+    }
+}
+
+/// The decoded journal of a receipt.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JournalSummary {
+    HeaderChain {
+        method_id: [u32; 8],
+        chain_state: ChainSummary,
+    },
+    HeaderChainRange {
+        method_id: [u32; 8],
+        initial_state_digest: String,
+        chain_state: ChainSummary,
+    },
+    /// The Blake3 digest of the `FinalCircuitOutput`, and the output itself if it was given.
+    FinalSpv {
+        output_digest: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        output: Option<FinalCircuitOutput>,
+        /// Whether the output meets the recommended `NetworkConstants::min_total_work`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        meets_network_min_total_work: Option<bool>,
+    },
+}
+
+/// What `host inspect` reports about a receipt.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Inspection {
+    pub guest: GuestInfo,
+    pub receipt_kind: String,
+    /// The verification error, if the receipt does not verify.
+    pub verification_error: Option<String>,
+    pub journal: JournalSummary,
+}
+
+#[derive(Debug)]
+pub enum InspectError {
+    /// The receipt has no claim, e.g. a pruned one.
+    MissingClaim(String),
+    UnknownImageId([u32; 8]),
+    MalformedJournal(String),
+    /// The given final circuit output does not decode, or is not the one the receipt commits to.
+    FinalOutput(String),
+}
+
+impl fmt::Display for InspectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InspectError::MissingClaim(e) => write!(f, "the receipt has no claim: {}", e),
+            InspectError::UnknownImageId(image_id) => write!(
+                f,
+                "the image ID {:?} is not one of the embedded guests",
+                image_id
+            ),
+            InspectError::MalformedJournal(e) => write!(f, "malformed journal: {}", e),
+            InspectError::FinalOutput(e) => write!(f, "final output: {}", e),
+        }
+    }
+}
+
+impl Error for InspectError {}
+
+/// The image ID of the guest that produced the receipt.
+pub fn receipt_image_id(receipt: &Receipt) -> Result<[u32; 8], InspectError> {
+    let claim = receipt
+        .claim()
+        .map_err(|e| InspectError::MissingClaim(e.to_string()))?;
+    let claim = claim
+        .value()
+        .map_err(|e| InspectError::MissingClaim(e.to_string()))?;
+    Ok(claim.pre.digest().into())
+}
+
+/// Detects the guest that produced the receipt, verifies it against that guest and decodes its
+/// journal. A final circuit receipt only commits to the digest of its output, so the borsh
+/// encoded `FinalCircuitOutput` can be given to be checked against it and decoded.
+pub fn inspect(receipt: &Receipt, final_output: Option<&[u8]>) -> Result<Inspection, InspectError> {
+    let image_id = receipt_image_id(receipt)?;
+    let guest = find_guest(image_id).ok_or(InspectError::UnknownImageId(image_id))?;
+    let journal = &receipt.journal.bytes;
+    let malformed = |e: std::io::Error| InspectError::MalformedJournal(e.to_string());
+
+    let journal = if guest.guest == "final-spv" {
+        let output_digest: [u8; 32] = journal.as_slice().try_into().map_err(|_| {
+            InspectError::MalformedJournal(format!("{} bytes instead of 32", journal.len()))
+        })?;
+        let output = final_output
+            .map(|bytes| FinalCircuitOutput::decode(bytes, &output_digest))
+            .transpose()
+            .map_err(|e| InspectError::FinalOutput(e.to_string()))?;
+        let meets_network_min_total_work = output.as_ref().and_then(|output| {
+            let constants = network_constants(&guest.network)?;
+            Some(
+                output
+                    .check_min_total_work(&constants.min_total_work.to_be_bytes())
+                    .is_ok(),
+            )
+        });
+        JournalSummary::FinalSpv {
+            output_digest: hex::encode(output_digest),
+            output,
+            meets_network_min_total_work,
+        }
+    } else if final_output.is_some() {
+        return Err(InspectError::FinalOutput(format!(
+            "a {} receipt has no final output",
+            guest.guest
+        )));
+    } else if journal.len() >= 32 && journal[..32] == borsh::to_vec(&RANGE_OUTPUT_TAG).unwrap() {
+        let range = HeaderChainRangeOutput::try_from_slice(journal).map_err(malformed)?;
+        JournalSummary::HeaderChainRange {
+            method_id: range.method_id,
+            initial_state_digest: hex::encode(range.initial_state_digest),
+            chain_state: ChainSummary::new(&range.chain_state),
+        }
+    } else {
+        let output = BlockHeaderCircuitOutput::try_from_slice(journal).map_err(malformed)?;
+        JournalSummary::HeaderChain {
+            method_id: output.method_id,
+            chain_state: ChainSummary::new(&output.chain_state),
+        }
+    };
+
+    Ok(Inspection {
+        receipt_kind: receipt_kind(receipt).to_string(),
+        verification_error: receipt.verify(image_id).err().map(|e| e.to_string()),
+        guest,
+        journal,
+    })
+}
+
+impl fmt::Display for ChainSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.block_height {
+            Some(height) => writeln!(f, "  block height: {}", height)?,
+            None => writeln!(f, "  block height: none (before genesis)")?,
+        }
+        writeln!(f, "  best block hash: {}", self.best_block_hash)?;
+        writeln!(
+            f,
+            "  total work: {} (2^{:.2})",
+            self.total_work, self.total_work_log2
+        )?;
+        writeln!(
+            f,
+            "  current target: {} (bits 0x{})",
+            self.current_target, self.current_target_bits
+        )?;
+        writeln!(f, "  epoch start time: {}", self.epoch_start_time)?;
+        write!(f, "  MMR size: {}", self.mmr_size)
+    }
+}
+
+impl fmt::Display for Inspection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} {} guest, image ID {:?}",
+            self.guest.network, self.guest.guest, self.guest.image_id
+        )?;
+        writeln!(f, "{} receipt", self.receipt_kind)?;
+        match &self.verification_error {
+            None => writeln!(f, "verified")?,
+            Some(e) => writeln!(f, "NOT verified: {}", e)?,
+        }
+        match &self.journal {
+            JournalSummary::HeaderChain {
+                method_id,
+                chain_state,
+            } => {
+                writeln!(f, "header chain output, method ID {:?}", method_id)?;
+                write!(f, "{}", chain_state)
+            }
+            JournalSummary::HeaderChainRange {
+                method_id,
+                initial_state_digest,
+                chain_state,
+            } => {
+                writeln!(f, "header chain range output, method ID {:?}", method_id)?;
+                writeln!(f, "  initial state digest: {}", initial_state_digest)?;
+                write!(f, "{}", chain_state)
+            }
+            JournalSummary::FinalSpv {
+                output_digest,
+                output,
+                meets_network_min_total_work,
+            } => {
+                write!(f, "final output digest: {}", output_digest)?;
+                let Some(output) = output else {
+                    return Ok(());
+                };
+                writeln!(f)?;
+                writeln!(f, "  block height: {}", output.block_height)?;
+                writeln!(
+                    f,
+                    "  best block hash: {}",
+                    hex::encode(
+                        output
+                            .best_block_hash
+                            .iter()
+                            .rev()
+                            .copied()
+                            .collect::<Vec<u8>>()
+                    )
+                )?;
+                writeln!(
+                    f,
+                    "  txid: {} in block {}",
+                    hex::encode(output.txid.iter().rev().copied().collect::<Vec<u8>>()),
+                    output.tx_block_height
+                )?;
+                write!(
+                    f,
+                    "  minimum total work: {}",
+                    BigUint::from_bytes_be(&output.min_total_work)
+                )?;
+                match meets_network_min_total_work {
+                    Some(false) => write!(f, " (below the network's recommended minimum)"),
+                    _ => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAINNET_FIRST_10: &[u8] =
+        include_bytes!("../../data/proofs/mainnet/mainnet_first_10.bin");
+
+    #[test]
+    fn test_inspect_header_chain_receipt() {
+        let receipt = Receipt::try_from_slice(MAINNET_FIRST_10).unwrap();
+        let inspection = inspect(&receipt, None).unwrap();
+        assert_eq!(inspection.guest.network, "mainnet");
+        assert_eq!(inspection.guest.guest, "header-chain");
+        assert_eq!(inspection.receipt_kind, "succinct");
+        assert_eq!(inspection.verification_error, None);
+
+        let JournalSummary::HeaderChain { chain_state, .. } = &inspection.journal else {
+            panic!("Expected a header chain output");
+        };
+        assert_eq!(chain_state.block_height, Some(10));
+        assert_eq!(
+            chain_state.best_block_hash,
+            "000000002c05cc2e78923c34df87fd108b22221ac6076c18f3ade378a4d915e9"
+        );
+        // Every early mainnet block has a work of 2^32 + 2^16 + 1.
+        assert_eq!(chain_state.total_work, (11 * 0x1_0001_0001u64).to_string());
+        assert_eq!(chain_state.current_target_bits, "1d00ffff");
+        assert_eq!(chain_state.mmr_size, 11);
+
+        let json = serde_json::to_string(&inspection).unwrap();
+        assert_eq!(
+            serde_json::from_str::<Inspection>(&json).unwrap(),
+            inspection
+        );
+    }
+
+    #[test]
+    fn test_inspect_unknown_guest() {
+        let mut receipt = Receipt::try_from_slice(MAINNET_FIRST_10).unwrap();
+        // A fake receipt of an unknown guest.
+        receipt.inner = InnerReceipt::Fake(risc0_zkvm::FakeReceipt::new(
+            risc0_zkvm::ReceiptClaim::ok([7u32; 8], receipt.journal.bytes.clone()),
+        ));
+        assert!(matches!(
+            inspect(&receipt, None),
+            Err(InspectError::UnknownImageId(image_id)) if image_id == [7; 8]
+        ));
+    }
+
+    #[test]
+    fn test_inspect_final_output() {
+        let output = FinalCircuitOutput {
+            version: risc0_to_bitvm2_core::final_circuit::FINAL_CIRCUIT_OUTPUT_VERSION,
+            network_magic: [0xf9, 0xbe, 0xb4, 0xd9],
+            genesis_block_hash: [1; 32],
+            header_chain_method_id: [2; 8],
+            best_block_hash: [3; 32],
+            block_height: 100,
+            total_work: [4; 32],
+            min_total_work: [0; 32],
+            txid: [5; 32],
+            tx_block_hash: [6; 32],
+            tx_block_height: 42,
+        };
+        let image_id = EMBEDDED_GUEST_IDS
+            .iter()
+            .find(|info| info.network == "mainnet" && info.guest == "final-spv")
+            .unwrap()
+            .image_id;
+        let journal = output.journal_digest().to_vec();
+        let receipt = Receipt::new(
+            InnerReceipt::Fake(risc0_zkvm::FakeReceipt::new(risc0_zkvm::ReceiptClaim::ok(
+                image_id,
+                journal.clone(),
+            ))),
+            journal,
+        );
+
+        let inspection = inspect(&receipt, Some(&borsh::to_vec(&output).unwrap())).unwrap();
+        assert_eq!(
+            inspection.journal,
+            JournalSummary::FinalSpv {
+                output_digest: hex::encode(output.journal_digest()),
+                output: Some(output.clone()),
+                // No minimum total work was enforced, mainnet recommends one.
+                meets_network_min_total_work: Some(false),
+            }
+        );
+        assert!(inspection
+            .to_string()
+            .contains("(below the network's recommended minimum)"));
+
+        let mut tampered = output;
+        tampered.block_height += 1;
+        assert!(matches!(
+            inspect(&receipt, Some(&borsh::to_vec(&tampered).unwrap())),
+            Err(InspectError::FinalOutput(_))
+        ));
+
+        let header_chain_receipt = Receipt::try_from_slice(MAINNET_FIRST_10).unwrap();
+        assert!(matches!(
+            inspect(
+                &header_chain_receipt,
+                Some(&borsh::to_vec(&tampered).unwrap())
+            ),
+            Err(InspectError::FinalOutput(_))
+        ));
+    }
+}
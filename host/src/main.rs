@@ -1,17 +1,46 @@
 use borsh::BorshDeserialize;
-use risc0_zkvm::{default_prover, ExecutorEnv};
+use clap::{Parser, Subcommand};
 
+use backend::{BackendConfig, ProvingBackend};
+use bundle::BitVmProofBundle;
+use docker::{stark_to_succinct, Groth16ProverConfig};
+use header_source::{HeaderSource, HeadersFromGenesis};
+use p2p::{Peer, PeerHeaderSource};
+use planner::{BatchPlan, PlanBudget};
 use risc0_circuit_recursion::control_id::BN254_IDENTITY_CONTROL_ID;
-use risc0_to_bitvm2_core::header_chain::{
-    BlockHeaderCircuitOutput, CircuitBlockHeader, HeaderChainCircuitInput, HeaderChainPrevProofType,
+use risc0_to_bitvm2_core::{
+    final_circuit::FinalCircuitInput,
+    header_chain::{
+        BlockHeaderCircuitOutput, ChainState, CircuitBlockHeader, HeaderChainCircuitInput,
+        HeaderChainPrevProofType, HeaderChainRangeOutput, NETWORK_CONSTANTS, NETWORK_TYPE,
+        RANGE_OUTPUT_TAG,
+    },
+    headers_file::HeadersFile,
 };
 use risc0_zkvm::{compute_image_id, sha::Digestible};
-use risc0_zkvm::{ProverOpts, Receipt, SuccinctReceiptVerifierParameters, SystemState};
+use risc0_zkvm::{Receipt, ReceiptClaim, SuccinctReceiptVerifierParameters, SystemState};
 use sha2::Digest;
 use sha2::Sha256;
-use std::{env, fs};
+use std::{
+    error::Error,
+    fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
 
+pub mod backend;
+pub mod bundle;
 pub mod docker;
+pub mod execute;
+pub mod header_source;
+pub mod headers;
+pub mod image_id;
+pub mod inspect;
+pub mod p2p;
+pub mod planner;
+pub mod validate_headers;
+pub mod verify;
 
 const HEADER_CHAIN_GUEST_ELF: &[u8] = {
     match option_env!("BITCOIN_NETWORK") {
@@ -21,8 +50,20 @@ const HEADER_CHAIN_GUEST_ELF: &[u8] = {
         Some(network) if matches!(network.as_bytes(), b"testnet4") => {
             include_bytes!("../../elfs/testnet4-header-chain-guest.bin")
         }
+        #[cfg(feature = "testnet3")]
+        Some(network) if matches!(network.as_bytes(), b"testnet3") => {
+            include_bytes!("../../elfs/testnet3-header-chain-guest.bin")
+        }
+        #[cfg(not(feature = "testnet3"))]
+        Some(network) if matches!(network.as_bytes(), b"testnet3") => {
+            panic!("Building for testnet3 needs the `testnet3` feature")
+        }
         Some(network) if matches!(network.as_bytes(), b"signet") => {
-            include_bytes!("../../elfs/signet-header-chain-guest.bin")
+            include_bytes!(concat!(
+                "../../elfs/",
+                env!("SIGNET_ELF_NAME"),
+                "-header-chain-guest.bin"
+            ))
         }
         Some(network) if matches!(network.as_bytes(), b"regtest") => {
             include_bytes!("../../elfs/regtest-header-chain-guest.bin")
@@ -32,6 +73,37 @@ const HEADER_CHAIN_GUEST_ELF: &[u8] = {
     }
 };
 
+const FINAL_SPV_GUEST_ELF: &[u8] = {
+    match option_env!("BITCOIN_NETWORK") {
+        Some(network) if matches!(network.as_bytes(), b"mainnet") => {
+            include_bytes!("../../elfs/mainnet-final-spv-guest.bin")
+        }
+        Some(network) if matches!(network.as_bytes(), b"testnet4") => {
+            include_bytes!("../../elfs/testnet4-final-spv-guest.bin")
+        }
+        #[cfg(feature = "testnet3")]
+        Some(network) if matches!(network.as_bytes(), b"testnet3") => {
+            include_bytes!("../../elfs/testnet3-final-spv-guest.bin")
+        }
+        #[cfg(not(feature = "testnet3"))]
+        Some(network) if matches!(network.as_bytes(), b"testnet3") => {
+            panic!("Building for testnet3 needs the `testnet3` feature")
+        }
+        Some(network) if matches!(network.as_bytes(), b"signet") => {
+            include_bytes!(concat!(
+                "../../elfs/",
+                env!("SIGNET_ELF_NAME"),
+                "-final-spv-guest.bin"
+            ))
+        }
+        Some(network) if matches!(network.as_bytes(), b"regtest") => {
+            include_bytes!("../../elfs/regtest-final-spv-guest.bin")
+        }
+        None => include_bytes!("../../elfs/mainnet-final-spv-guest.bin"),
+        _ => panic!("Invalid path or ELF file"),
+    }
+};
+
 const HEADERS: &[u8] = {
     match option_env!("BITCOIN_NETWORK") {
         Some(network) if matches!(network.as_bytes(), b"mainnet") => {
@@ -40,8 +112,20 @@ const HEADERS: &[u8] = {
         Some(network) if matches!(network.as_bytes(), b"testnet4") => {
             include_bytes!("../../data/headers/testnet4-headers.bin")
         }
+        #[cfg(feature = "testnet3")]
+        Some(network) if matches!(network.as_bytes(), b"testnet3") => {
+            include_bytes!("../../data/headers/testnet3-headers.bin")
+        }
+        #[cfg(not(feature = "testnet3"))]
+        Some(network) if matches!(network.as_bytes(), b"testnet3") => {
+            panic!("Building for testnet3 needs the `testnet3` feature")
+        }
         Some(network) if matches!(network.as_bytes(), b"signet") => {
-            include_bytes!("../../data/headers/signet-headers.bin")
+            include_bytes!(concat!(
+                "../../data/headers/",
+                env!("SIGNET_ELF_NAME"),
+                "-headers.bin"
+            ))
         }
         Some(network) if matches!(network.as_bytes(), b"regtest") => {
             include_bytes!("../../data/headers/regtest-headers.bin")
@@ -51,99 +135,763 @@ const HEADERS: &[u8] = {
     }
 };
 
-fn main() {
-    // Parse command-line arguments
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 4 {
-        eprintln!("Usage: <program> <input_proof> <output_file_path> <batch_size>");
-        return;
+/// Arguments of the host CLI.
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    /// The JSON file selecting the proving backend. Defaults to the prover risc0 selects from the
+    /// environment.
+    #[clap(long, env = "HOST_BACKEND_CONFIG", global = true)]
+    backend_config: Option<PathBuf>,
+    /// Download the headers to prove from this Bitcoin node over the P2P protocol, validating
+    /// them natively, instead of using the embedded headers file.
+    #[clap(long, env = "HOST_BITCOIN_PEER", global = true)]
+    peer: Option<SocketAddr>,
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Prove the next batch of Bitcoin headers.
+    Prove {
+        /// The previous proof file path (`None` if starting from genesis).
+        input_proof: String,
+        /// The output proof file path.
+        output_file_path: PathBuf,
+        /// The number of headers to prove.
+        batch_size: usize,
+    },
+    /// Prove a plan made by `plan`, saving a receipt per batch. Existing receipts are reused.
+    ProvePlan {
+        /// The plan file path.
+        plan: PathBuf,
+        /// The output directory for the receipts.
+        output_dir: PathBuf,
+    },
+    /// Prove a range of headers on top of its natively computed prior state, without a proof of
+    /// that state. Ranges can be proven in parallel and composed with `aggregate`.
+    ProveRange {
+        /// The previous proof file path (`None` if starting from genesis).
+        input_proof: String,
+        /// The height of the first header of the range.
+        start_height: usize,
+        /// The number of headers to prove.
+        count: usize,
+        /// The output range proof file path.
+        output_file_path: PathBuf,
+    },
+    /// Compose adjacent range proofs, in order, with the previous proof into one proof.
+    Aggregate {
+        /// The previous proof file path (`None` if starting from genesis).
+        input_proof: String,
+        /// The output proof file path.
+        output_file_path: PathBuf,
+        /// The range proof file paths, in order.
+        #[clap(required = true)]
+        ranges: Vec<PathBuf>,
+    },
+    /// Plan the batches to prove the next headers in, by executing the header chain guest.
+    Plan {
+        /// The previous proof file path (`None` if starting from genesis).
+        input_proof: String,
+        /// The number of headers to plan.
+        count: usize,
+        /// The output plan file path.
+        output: PathBuf,
+        #[clap(flatten)]
+        budget: PlanBudget,
+    },
+    /// Execute a guest without proving it and report its cycles and journal.
+    Execute {
+        #[clap(subcommand)]
+        guest: ExecuteGuest,
+        /// Also write the report as JSON to this path.
+        #[clap(long, global = true)]
+        report: Option<PathBuf>,
+    },
+    /// Convert a succinct final-spv receipt into a Groth16 proof and save the BitVM proof bundle.
+    Groth16 {
+        /// The succinct receipt file path.
+        receipt: PathBuf,
+        /// The output directory for `bundle.borsh` and `bundle.json`.
+        output_dir: PathBuf,
+        #[clap(flatten)]
+        prover_config: Groth16ProverConfig,
+    },
+    /// Validate a BitVM proof bundle (`.json` or borsh).
+    ValidateBundle {
+        /// The bundle file path.
+        bundle: PathBuf,
+    },
+    /// Detect the guest that produced a receipt, verify it and decode its journal.
+    Inspect {
+        /// The receipt file path.
+        receipt: PathBuf,
+        /// Print the result as JSON.
+        #[clap(long)]
+        json: bool,
+        /// The borsh encoded `FinalCircuitOutput` of a final circuit receipt, to check against
+        /// its journal and decode.
+        #[clap(long)]
+        final_output: Option<PathBuf>,
+    },
+    /// Verify a receipt against the embedded guest that produced it, and optionally the chain
+    /// it proves. Exits with 3 if the receipt is invalid, 4 if it is of the wrong kind and 5 if
+    /// an assertion fails.
+    Verify {
+        /// The receipt file path.
+        receipt: PathBuf,
+        #[clap(flatten)]
+        options: verify::VerifyOptions,
+    },
+    /// Run the consensus checks natively over a range of a headers file, without proving, and
+    /// report the resulting chain state, retargets and throughput. Fails at the first invalid
+    /// header.
+    ValidateHeaders {
+        #[clap(flatten)]
+        args: validate_headers::ValidateHeadersArgs,
+    },
+    /// Convert, extend and validate headers files.
+    Headers {
+        #[clap(subcommand)]
+        command: headers::HeadersCommand,
+    },
+    /// Method ID tooling for the reproducibly built guests.
+    ImageId {
+        #[clap(subcommand)]
+        command: ImageIdCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ExecuteGuest {
+    /// Execute the header chain guest on the next batch of Bitcoin headers.
+    HeaderChain {
+        /// The previous proof file path (`None` if starting from genesis).
+        input_proof: String,
+        /// The number of headers to execute.
+        batch_size: usize,
+    },
+    /// Execute the final circuit guest.
+    FinalSpv {
+        /// The borsh encoded `FinalCircuitInput` file path.
+        input: PathBuf,
+        /// The header chain proof the input was built from.
+        header_chain_proof: PathBuf,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ImageIdCommand {
+    /// Fail if a final circuit ELF was not built against the current header chain ELF.
+    Check {
+        /// The folder with the reproducibly built ELFs.
+        #[clap(long, default_value = "elfs")]
+        elfs_dir: PathBuf,
+    },
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let backend = match args
+        .backend_config
+        .as_deref()
+        .map_or(Ok(BackendConfig::default()), BackendConfig::load)
+        .and_then(|config| config.backend())
+    {
+        Ok(backend) => backend,
+        Err(e) => {
+            eprintln!("Invalid proving backend config: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let backend = backend.as_ref();
+    let peer = args.peer;
+    match args.command {
+        Command::Prove {
+            input_proof,
+            output_file_path,
+            batch_size,
+        } => {
+            if let Err(e) = header_source(peer).and_then(|mut source| {
+                prove_headers(
+                    backend,
+                    source.as_mut(),
+                    &input_proof,
+                    &output_file_path,
+                    batch_size,
+                )
+            }) {
+                eprintln!("Proving failed: {}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+        Command::ProveRange {
+            input_proof,
+            start_height,
+            count,
+            output_file_path,
+        } => {
+            if let Err(e) = prove_range(
+                backend,
+                &input_proof,
+                start_height,
+                count,
+                &output_file_path,
+            ) {
+                eprintln!("Proving the range failed: {}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+        Command::Aggregate {
+            input_proof,
+            output_file_path,
+            ranges,
+        } => {
+            if let Err(e) = aggregate(backend, &input_proof, &ranges, &output_file_path) {
+                eprintln!("Aggregation failed: {}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+        Command::ProvePlan { plan, output_dir } => {
+            if let Err(e) = header_source(peer)
+                .and_then(|mut source| prove_plan(backend, source.as_mut(), &plan, &output_dir))
+            {
+                eprintln!("Proving the plan failed: {}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+        Command::Plan {
+            input_proof,
+            count,
+            output,
+            budget,
+        } => {
+            if let Err(e) = plan(&input_proof, count, &output, budget) {
+                eprintln!("Planning failed: {}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+        Command::Execute { guest, report } => {
+            if let Err(e) = execute_guest(peer, guest, report.as_deref()) {
+                eprintln!("Execution failed: {}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+        Command::Groth16 {
+            receipt,
+            output_dir,
+            prover_config,
+        } => {
+            if let Err(e) = groth16(&receipt, &output_dir, &prover_config) {
+                eprintln!("Groth16 proving failed: {}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+        Command::ValidateBundle { bundle } => {
+            let result = BitVmProofBundle::load(&bundle)
+                .map_err(|e| e.to_string())
+                .and_then(|bundle| bundle.validate().map_err(|e| e.to_string()));
+            match result {
+                Ok(()) => println!("Bundle {:?} is valid", bundle),
+                Err(e) => {
+                    eprintln!("Bundle {:?} is invalid: {}", bundle, e);
+                    return ExitCode::FAILURE;
+                }
+            }
+        }
+        Command::Inspect {
+            receipt,
+            json,
+            final_output,
+        } => {
+            if let Err(e) = inspect_receipt(&receipt, json, final_output.as_deref()) {
+                eprintln!("Inspecting {:?} failed: {}", receipt, e);
+                return ExitCode::FAILURE;
+            }
+        }
+        Command::Verify { receipt, options } => {
+            let result = fs::read(&receipt)
+                .map_err(|e| e.to_string())
+                .and_then(|bytes| Receipt::try_from_slice(&bytes).map_err(|e| e.to_string()))
+                .map_err(verify::VerifyError::Io)
+                .and_then(|receipt| {
+                    verify::verify(&receipt, &options, |network| {
+                        let default_path =
+                            PathBuf::from(format!("data/headers/{}-headers.bin", network));
+                        verify::read_headers(
+                            options.headers.as_ref().unwrap_or(&default_path),
+                            network,
+                        )
+                    })
+                });
+            match result {
+                Ok(inspection) => println!("{}\n{:?} is valid", inspection, receipt),
+                Err(e) => {
+                    eprintln!("{:?}: {}", receipt, e);
+                    return e.exit_code();
+                }
+            }
+        }
+        Command::ValidateHeaders { args } => match validate_headers::run(&args) {
+            Ok(report) => {
+                if args.json {
+                    match serde_json::to_string_pretty(&report) {
+                        Ok(json) => println!("{}", json),
+                        Err(e) => {
+                            eprintln!("Serializing the report failed: {}", e);
+                            return ExitCode::FAILURE;
+                        }
+                    }
+                } else {
+                    println!("{}", report);
+                }
+                if report.invalid_header.is_some() {
+                    return ExitCode::FAILURE;
+                }
+            }
+            Err(e) => {
+                eprintln!("Validating {:?} failed: {}", args.headers, e);
+                return ExitCode::FAILURE;
+            }
+        },
+        Command::Headers { command } => {
+            if let Err(e) = headers::run(command) {
+                eprintln!("{}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+        Command::ImageId {
+            command: ImageIdCommand::Check { elfs_dir },
+        } => match image_id::check_all(&elfs_dir) {
+            Ok(()) => println!("Image IDs in {:?} are consistent", elfs_dir),
+            Err(errors) => {
+                for error in errors {
+                    eprintln!("{}", error);
+                }
+                return ExitCode::FAILURE;
+            }
+        },
     }
+    ExitCode::SUCCESS
+}
 
-    let input_proof = &args[1];
-    let output_file_path = &args[2];
-    let batch_size: usize = args[3].parse().expect("Batch size should be a number");
+fn prove_headers(
+    backend: &dyn ProvingBackend,
+    header_source: &mut dyn HeaderSource,
+    input_proof: &str,
+    output_file_path: &Path,
+    batch_size: usize,
+) -> Result<(), Box<dyn Error>> {
+    let prev_receipt = load_prev_receipt(input_proof)?;
+    let input = header_chain_input(header_source, prev_receipt.as_ref(), batch_size)?;
+    let receipt = prove_header_chain(backend, &input, prev_receipt.into_iter().collect())?;
 
-    let headers = HEADERS
-        .chunks(80)
-        .map(|header| CircuitBlockHeader::try_from_slice(header).unwrap())
-        .collect::<Vec<CircuitBlockHeader>>();
+    // Extract journal of receipt
+    let output = BlockHeaderCircuitOutput::try_from_slice(&receipt.journal.bytes)?;
 
-    let header_chain_guest_id: [u32; 8] = compute_image_id(HEADER_CHAIN_GUEST_ELF)
-        .unwrap()
-        .as_words()
-        .try_into()
-        .unwrap();
+    println!("Output: {:#?}", output.method_id);
 
-    // Set the previous proof type based on input_proof argument
-    let prev_receipt = if input_proof.to_lowercase() == "none" {
-        None
-    } else {
-        let proof_bytes = fs::read(input_proof).expect("Failed to read input proof file");
-        let receipt: Receipt = Receipt::try_from_slice(&proof_bytes).unwrap();
-        println!("Previous Receipt Journal: {:?}", receipt.journal);
+    // Save the receipt to the specified output file path
+    fs::write(output_file_path, borsh::to_vec(&receipt)?)?;
+    println!("Receipt saved to {:?}", output_file_path);
+    Ok(())
+}
 
-        Some(receipt)
+/// Proves the header chain guest on the input, with the given proofs as assumptions.
+fn prove_header_chain(
+    backend: &dyn ProvingBackend,
+    input: &HeaderChainCircuitInput,
+    assumptions: Vec<Receipt>,
+) -> Result<Receipt, Box<dyn Error>> {
+    let receipt = backend.prove(HEADER_CHAIN_GUEST_ELF, &borsh::to_vec(input)?, assumptions)?;
+    println!("New Receipt Journal: {:?}", receipt.journal);
+    Ok(receipt)
+}
+
+/// Proves `count` headers from `start_height` on top of their natively computed prior state, so
+/// ranges can be proven in parallel and composed with `aggregate` later.
+fn prove_range(
+    backend: &dyn ProvingBackend,
+    input_proof: &str,
+    start_height: usize,
+    count: usize,
+    output_file_path: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let prev_receipt = load_prev_receipt(input_proof)?;
+    let mut chain_state = match &prev_receipt {
+        Some(receipt) => {
+            BlockHeaderCircuitOutput::try_from_slice(&receipt.journal.bytes)?.chain_state
+        }
+        None => ChainState::new(),
+    };
+    let prev_end = chain_state.block_height.wrapping_add(1) as usize;
+    if start_height < prev_end {
+        return Err(format!(
+            "the range at height {} starts before the previous proof ends at height {}",
+            start_height, prev_end
+        )
+        .into());
+    }
+    let headers = headers();
+    if start_height + count > headers.len() {
+        return Err(format!("only {} headers are available", headers.len()).into());
+    }
+    chain_state.apply_blocks(headers[prev_end..start_height].to_vec());
+
+    let input = HeaderChainCircuitInput {
+        method_id: header_chain_guest_id(),
+        prev_proof: HeaderChainPrevProofType::UnknownPrevState(chain_state),
+        block_headers: headers[start_height..start_height + count].to_vec(),
     };
+    let receipt = prove_header_chain(backend, &input, vec![])?;
+    fs::write(output_file_path, borsh::to_vec(&receipt)?)?;
+    println!("Range receipt saved to {:?}", output_file_path);
+    Ok(())
+}
+
+/// Composes adjacent range proofs with the previous proof into a header chain proof.
+fn aggregate(
+    backend: &dyn ProvingBackend,
+    input_proof: &str,
+    range_paths: &[PathBuf],
+    output_file_path: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let prev_receipt = load_prev_receipt(input_proof)?;
+    let prev_proof = prev_receipt
+        .as_ref()
+        .map(|receipt| BlockHeaderCircuitOutput::try_from_slice(&receipt.journal.bytes))
+        .transpose()?;
+    let mut chain_state = prev_proof
+        .as_ref()
+        .map_or_else(ChainState::new, |output| output.chain_state.clone());
+
+    let method_id = header_chain_guest_id();
+    let mut range_receipts = vec![];
+    let mut ranges = vec![];
+    for range_path in range_paths {
+        let receipt = Receipt::try_from_slice(&fs::read(range_path)?)?;
+        let range = HeaderChainRangeOutput::try_from_slice(&receipt.journal.bytes)?;
+        if range.tag != RANGE_OUTPUT_TAG || range.method_id != method_id {
+            return Err(format!("{:?} is not a range proof of this guest", range_path).into());
+        }
+        if range.initial_state_digest != chain_state.digest() {
+            return Err(format!(
+                "{:?} does not start at height {}",
+                range_path,
+                chain_state.block_height.wrapping_add(1)
+            )
+            .into());
+        }
+        chain_state = range.chain_state.clone();
+        range_receipts.push(receipt);
+        ranges.push(range);
+    }
+
+    let input = HeaderChainCircuitInput {
+        method_id,
+        prev_proof: HeaderChainPrevProofType::RangeProofs { prev_proof, ranges },
+        block_headers: vec![],
+    };
+    let receipt = prove_header_chain(
+        backend,
+        &input,
+        prev_receipt.into_iter().chain(range_receipts).collect(),
+    )?;
+    let output = BlockHeaderCircuitOutput::try_from_slice(&receipt.journal.bytes)?;
+    println!(
+        "Aggregated up to height {}",
+        output.chain_state.block_height
+    );
+    fs::write(output_file_path, borsh::to_vec(&receipt)?)?;
+    println!("Receipt saved to {:?}", output_file_path);
+    Ok(())
+}
+
+/// Reads the previous proof (`None` if starting from genesis).
+fn load_prev_receipt(input_proof: &str) -> Result<Option<Receipt>, Box<dyn Error>> {
+    // Set the previous proof type based on input_proof argument
+    if input_proof.to_lowercase() == "none" {
+        return Ok(None);
+    }
+    let proof_bytes = fs::read(input_proof)
+        .map_err(|e| format!("failed to read input proof {}: {}", input_proof, e))?;
+    let receipt = Receipt::try_from_slice(&proof_bytes)
+        .map_err(|e| format!("invalid input proof {}: {}", input_proof, e))?;
+    println!("Previous Receipt Journal: {:?}", receipt.journal);
+    Ok(Some(receipt))
+}
+
+/// The embedded headers, from genesis. The headers file can be legacy or versioned.
+fn headers() -> Vec<CircuitBlockHeader> {
+    let file = HeadersFile::decode(HEADERS, NETWORK_CONSTANTS.magic)
+        .expect("Invalid embedded headers file");
+    assert_eq!(
+        file.start_height, 0,
+        "The embedded headers must start at genesis"
+    );
+    file.headers
+}
 
-    let mut start = 0;
-    let prev_proof = match prev_receipt.clone() {
+/// The source of the headers to prove: the peer if one is given, otherwise the embedded headers.
+fn header_source(peer: Option<SocketAddr>) -> Result<Box<dyn HeaderSource>, Box<dyn Error>> {
+    Ok(match peer {
+        Some(addr) => Box::new(PeerHeaderSource::new(
+            Peer::connect(addr, NETWORK_CONSTANTS.magic)?,
+            &NETWORK_CONSTANTS,
+        )),
+        None => Box::new(HeadersFromGenesis(headers())),
+    })
+}
+
+fn header_chain_guest_id() -> [u32; 8] {
+    compute_image_id(HEADER_CHAIN_GUEST_ELF)
+        .unwrap()
+        .as_words()
+        .try_into()
+        .unwrap()
+}
+
+/// Prepares the header chain input for the next `batch_size` headers after the previous proof.
+fn header_chain_input(
+    header_source: &mut dyn HeaderSource,
+    prev_receipt: Option<&Receipt>,
+    batch_size: usize,
+) -> Result<HeaderChainCircuitInput, Box<dyn Error>> {
+    let (prev_proof, chain_state) = match prev_receipt {
         Some(receipt) => {
-            let output =
-                BlockHeaderCircuitOutput::try_from_slice(&receipt.journal.bytes.clone()).unwrap();
-            start = output.chain_state.block_height as usize + 1;
-            HeaderChainPrevProofType::PrevProof(output)
+            let output = BlockHeaderCircuitOutput::try_from_slice(&receipt.journal.bytes)?;
+            let chain_state = output.chain_state.clone();
+            (HeaderChainPrevProofType::PrevProof(output), chain_state)
         }
-        None => HeaderChainPrevProofType::GenesisBlock,
+        None => (HeaderChainPrevProofType::GenesisBlock, ChainState::new()),
     };
 
     // Prepare the input for the circuit
-    let input = HeaderChainCircuitInput {
-        method_id: header_chain_guest_id,
+    Ok(HeaderChainCircuitInput {
+        method_id: header_chain_guest_id(),
         prev_proof,
-        block_headers: headers[start..start + batch_size].to_vec(),
+        block_headers: header_source.headers_after(&chain_state, batch_size)?,
+    })
+}
+
+/// Plans the batches for the next `count` headers after the previous proof and saves the plan.
+fn plan(
+    input_proof: &str,
+    count: usize,
+    output: &Path,
+    budget: PlanBudget,
+) -> Result<(), Box<dyn Error>> {
+    let prev_receipt = load_prev_receipt(input_proof)?;
+    let prev_output = prev_receipt
+        .as_ref()
+        .map(|receipt| BlockHeaderCircuitOutput::try_from_slice(&receipt.journal.bytes))
+        .transpose()?;
+    let start = prev_output
+        .as_ref()
+        .map_or(0, |output| output.chain_state.block_height as usize + 1);
+    let headers = headers();
+    let end = start + count;
+    if end > headers.len() {
+        return Err(format!("only {} headers are available", headers.len()).into());
+    }
+
+    let method_id = header_chain_guest_id();
+    let batches = planner::plan_batches(
+        &headers[start..end],
+        prev_output,
+        method_id,
+        &NETWORK_CONSTANTS,
+        &budget,
+        |input| {
+            // The previous output of a planned batch is not proven yet, so its claim is added as
+            // an unresolved assumption.
+            let assumptions = match &input.prev_proof {
+                HeaderChainPrevProofType::PrevProof(output) => {
+                    vec![ReceiptClaim::ok(method_id, borsh::to_vec(output)?).into()]
+                }
+                _ => vec![],
+            };
+            let report = execute::execute(HEADER_CHAIN_GUEST_ELF, input, assumptions)?;
+            println!(
+                "  {} headers: {} cycles in {} segments",
+                input.block_headers.len(),
+                report.total_cycles,
+                report.segment_count()
+            );
+            Ok(report)
+        },
+    )?;
+
+    for batch in &batches {
+        println!(
+            "Batch at height {}: {} headers, {} cycles in {} segments",
+            batch.start_height, batch.batch_size, batch.total_cycles, batch.segments
+        );
+    }
+    let plan = BatchPlan {
+        version: planner::BATCH_PLAN_VERSION,
+        network: NETWORK_TYPE.to_string(),
+        header_chain_method_id: method_id,
+        input_proof: prev_receipt.map(|_| input_proof.to_string()),
+        budget,
+        batches,
     };
+    plan.save(output)?;
+    println!("Plan saved to {:?}", output);
+    Ok(())
+}
 
-    // Build ENV
-    let mut binding = ExecutorEnv::builder();
-    let mut env = binding.write_slice(&borsh::to_vec(&input).unwrap());
-    if let Some(receipt) = prev_receipt {
-        env = env.add_assumption(receipt);
+/// Proves the batches of a plan in order, continuing from the last existing receipt.
+fn prove_plan(
+    backend: &dyn ProvingBackend,
+    header_source: &mut dyn HeaderSource,
+    plan_path: &Path,
+    output_dir: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let plan = BatchPlan::load(plan_path)?;
+    if plan.network != NETWORK_TYPE {
+        return Err(format!("the plan is for {}, not {}", plan.network, NETWORK_TYPE).into());
+    }
+    if plan.header_chain_method_id != header_chain_guest_id() {
+        return Err("the plan was made for a different header chain guest".into());
     }
-    let env = env.build().unwrap();
+    fs::create_dir_all(output_dir)?;
 
-    // Obtain the default prover.
-    let prover = default_prover();
+    let mut prev_receipt = match plan.input_proof.as_deref() {
+        Some(input_proof) => load_prev_receipt(input_proof)?,
+        None => None,
+    };
+    for batch in &plan.batches {
+        let end_height = batch.start_height as usize + batch.batch_size - 1;
+        let receipt_path = output_dir.join(format!("{}_{}.bin", plan.network, end_height));
+        if receipt_path.exists() {
+            println!("Reusing {:?}", receipt_path);
+            prev_receipt = Some(Receipt::try_from_slice(&fs::read(&receipt_path)?)?);
+            continue;
+        }
 
-    // Produce a receipt by proving the specified ELF binary.
-    let receipt = prover
-        .prove_with_opts(env, HEADER_CHAIN_GUEST_ELF, &ProverOpts::succinct())
-        .unwrap();
+        let start_height = match &prev_receipt {
+            Some(receipt) => {
+                BlockHeaderCircuitOutput::try_from_slice(&receipt.journal.bytes)?
+                    .chain_state
+                    .block_height
+                    + 1
+            }
+            None => 0,
+        };
+        if start_height != batch.start_height {
+            return Err(format!(
+                "the batch at height {} does not continue the previous proof at height {}",
+                batch.start_height, start_height
+            )
+            .into());
+        }
 
-    println!("New Receipt: {:?}", receipt.stats);
-    let receipt = receipt.receipt;
-    println!("New Receipt Journal: {:?}", receipt.journal);
+        let input = header_chain_input(header_source, prev_receipt.as_ref(), batch.batch_size)?;
+        let receipt = prove_header_chain(backend, &input, prev_receipt.into_iter().collect())?;
+        fs::write(&receipt_path, borsh::to_vec(&receipt)?)?;
+        println!("Receipt saved to {:?}", receipt_path);
+        prev_receipt = Some(receipt);
+    }
+    Ok(())
+}
 
-    // Extract journal of receipt
-    let output = BlockHeaderCircuitOutput::try_from_slice(&receipt.journal.bytes).unwrap();
+/// Executes a guest, prints its report and optionally saves it as JSON.
+fn execute_guest(
+    peer: Option<SocketAddr>,
+    guest: ExecuteGuest,
+    report_path: Option<&Path>,
+) -> Result<(), Box<dyn Error>> {
+    let report = match guest {
+        ExecuteGuest::HeaderChain {
+            input_proof,
+            batch_size,
+        } => {
+            let prev_receipt = load_prev_receipt(&input_proof)?;
+            let input = header_chain_input(
+                header_source(peer)?.as_mut(),
+                prev_receipt.as_ref(),
+                batch_size,
+            )?;
+            let report = execute::execute(
+                HEADER_CHAIN_GUEST_ELF,
+                &input,
+                prev_receipt.into_iter().map(Into::into).collect(),
+            )?;
+            let output = BlockHeaderCircuitOutput::try_from_slice(&report.journal)?;
+            println!("Header chain guest:");
+            println!("  block height: {}", output.chain_state.block_height);
+            report
+        }
+        ExecuteGuest::FinalSpv {
+            input,
+            header_chain_proof,
+        } => {
+            let input = FinalCircuitInput::try_from_slice(&fs::read(input)?)?;
+            let receipt = Receipt::try_from_slice(&fs::read(header_chain_proof)?)?;
+            println!("Final SPV guest:");
+            execute::execute(FINAL_SPV_GUEST_ELF, &input, vec![receipt.into()])?
+        }
+    };
 
-    println!("Output: {:#?}", output.method_id);
+    println!("  user cycles: {}", report.user_cycles);
+    println!(
+        "  total cycles: {} ({} segments with po2 {:?})",
+        report.total_cycles,
+        report.segment_count(),
+        report.segment_po2s
+    );
+    for phase in &report.phases {
+        println!("  {}: {} cycles", phase.phase, phase.cycles);
+    }
+    println!("  journal: {}", hex::encode(&report.journal));
 
-    // Save the receipt to the specified output file path
-    let receipt_bytes = borsh::to_vec(&receipt).unwrap();
-    fs::write(output_file_path, &receipt_bytes).expect("Failed to write receipt to output file");
-    println!("Receipt saved to {}", output_file_path);
+    if let Some(report_path) = report_path {
+        fs::write(report_path, serde_json::to_vec_pretty(&report)?)?;
+        println!("Report saved to {:?}", report_path);
+    }
+    Ok(())
+}
+
+/// Prints what `inspect::inspect` finds out about a saved receipt.
+fn inspect_receipt(
+    receipt_path: &Path,
+    json: bool,
+    final_output_path: Option<&Path>,
+) -> Result<(), Box<dyn Error>> {
+    let receipt = Receipt::try_from_slice(&fs::read(receipt_path)?)?;
+    let final_output = final_output_path.map(fs::read).transpose()?;
+    let inspection = inspect::inspect(&receipt, final_output.as_deref())?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&inspection)?);
+    } else {
+        println!("{}", inspection);
+    }
+    Ok(())
+}
+
+/// Runs the Groth16 prover over a saved succinct receipt and saves the BitVM proof bundle.
+fn groth16(
+    receipt_path: &Path,
+    output_dir: &Path,
+    prover_config: &Groth16ProverConfig,
+) -> Result<(), Box<dyn Error>> {
+    let receipt = Receipt::try_from_slice(&fs::read(receipt_path)?)?;
+    let succinct_receipt = receipt.inner.succinct()?.clone();
+    let output = stark_to_succinct(succinct_receipt, &receipt.journal.bytes, prover_config)?;
+
+    let bundle = BitVmProofBundle::new(&receipt, &output)?;
+    bundle.save(output_dir)?;
+    println!("BitVM proof bundle saved to {:?}", output_dir);
+    Ok(())
 }
 
 /// Sha256(control_root, pre_state_digest, post_state_digest, id_bn254_fr)
 pub fn calculate_succinct_output_prefix(method_id: &[u8]) -> [u8; 32] {
     let succinct_verifier_params = SuccinctReceiptVerifierParameters::default();
-    let succinct_control_root = succinct_verifier_params.control_root;
-    let mut succinct_control_root_bytes: [u8; 32] =
-        succinct_control_root.as_bytes().try_into().unwrap();
-    for byte in succinct_control_root_bytes.iter_mut() {
-        *byte = byte.reverse_bits();
-    }
-    let pre_state_bytes = method_id.to_vec();
+    let succinct_control_root_bytes: [u8; 32] = succinct_verifier_params.control_root.into();
     let control_id_bytes: [u8; 32] = BN254_IDENTITY_CONTROL_ID.into();
 
     // Expected post state for an execution that halted successfully
@@ -153,11 +901,32 @@ pub fn calculate_succinct_output_prefix(method_id: &[u8]) -> [u8; 32] {
     };
     let post_state_bytes: [u8; 32] = post_state.digest().into();
 
+    succinct_output_prefix(
+        succinct_control_root_bytes,
+        method_id,
+        post_state_bytes,
+        control_id_bytes,
+    )
+}
+
+/// Sha256(control_root, pre_state_digest, post_state_digest, id_bn254_fr) for the given constants.
+/// The bits of each byte of the control root are reversed, as in the Groth16 circuit.
+pub fn succinct_output_prefix(
+    control_root: [u8; 32],
+    pre_state_digest: &[u8],
+    post_state_digest: [u8; 32],
+    control_id: [u8; 32],
+) -> [u8; 32] {
+    let mut succinct_control_root_bytes = control_root;
+    for byte in succinct_control_root_bytes.iter_mut() {
+        *byte = byte.reverse_bits();
+    }
+
     let mut hasher = Sha256::new();
     hasher.update(&succinct_control_root_bytes);
-    hasher.update(&pre_state_bytes);
-    hasher.update(&post_state_bytes);
-    hasher.update(&control_id_bytes);
+    hasher.update(pre_state_digest);
+    hasher.update(&post_state_digest);
+    hasher.update(&control_id);
     let result: [u8; 32] = hasher
         .finalize()
         .try_into()
@@ -169,22 +938,20 @@ pub fn calculate_succinct_output_prefix(method_id: &[u8]) -> [u8; 32] {
 #[cfg(test)]
 mod tests {
 
-    use ark_bn254::{Bn254, Fq, Fq2, G1Affine, G2Affine};
-    use ark_ff::{Field, PrimeField};
-    use ark_groth16::{Proof, VerifyingKey};
+    use ark_ff::PrimeField;
+    use crypto_bigint::Encoding;
     use risc0_to_bitvm2_core::{
-        final_circuit::FinalCircuitInput, header_chain::BlockHeaderCircuitOutput,
-        merkle_tree::BitcoinMerkleTree, mmr_native::MMRNative, spv::SPV,
+        final_circuit::{FinalCircuitInput, FinalCircuitOutput},
+        header_chain::{BlockHeaderCircuitOutput, MAINNET_CONSTANTS},
+        merkle_tree::BitcoinMerkleTree,
+        mmr_native::MMRNative,
+        spv::SPV,
         transaction::CircuitTransaction,
     };
-    use std::str::FromStr;
-
-    use risc0_groth16::{Fr, Seal, VerifyingKeyJson};
 
-    use docker::stark_to_succinct;
+    use bundle::{get_ark_verifying_key, proof_from_uncompressed};
     use hex_literal::hex;
-    use risc0_zkp::verify;
-    use risc0_zkvm::compute_image_id;
+    use risc0_zkvm::{compute_image_id, default_prover, ExecutorEnv, ProverOpts};
 
     const MAINNET_BLOCK_HASHES: [[u8; 32]; 11] = [
         hex!("6fe28c0ab6f1b372c1a6a246ae63f74f931e8365e15a089c68d6190000000000"),
@@ -200,169 +967,16 @@ mod tests {
         hex!("e915d9a478e3adf3186c07c61a22228b10fd87df343c92782ecc052c00000000"),
     ];
 
-    fn get_ark_verifying_key() -> ark_groth16::VerifyingKey<Bn254> {
-        let alpha_g1 = G1Affine::new(
-            Fq::from_str(
-                "20491192805390485299153009773594534940189261866228447918068658471970481763042",
-            )
-            .unwrap(),
-            Fq::from_str(
-                "9383485363053290200918347156157836566562967994039712273449902621266178545958",
-            )
-            .unwrap(),
-        );
-
-        let beta_g2 = G2Affine::new(
-            Fq2::new(
-                Fq::from_str(
-                    "6375614351688725206403948262868962793625744043794305715222011528459656738731",
-                )
-                .unwrap(),
-                Fq::from_str(
-                    "4252822878758300859123897981450591353533073413197771768651442665752259397132",
-                )
-                .unwrap(),
-            ),
-            Fq2::new(
-                Fq::from_str(
-                    "10505242626370262277552901082094356697409835680220590971873171140371331206856",
-                )
-                .unwrap(),
-                Fq::from_str(
-                    "21847035105528745403288232691147584728191162732299865338377159692350059136679",
-                )
-                .unwrap(),
-            ),
-        );
-
-        let gamma_g2 = G2Affine::new(
-            Fq2::new(
-                Fq::from_str(
-                    "10857046999023057135944570762232829481370756359578518086990519993285655852781",
-                )
-                .unwrap(),
-                Fq::from_str(
-                    "11559732032986387107991004021392285783925812861821192530917403151452391805634",
-                )
-                .unwrap(),
-            ),
-            Fq2::new(
-                Fq::from_str(
-                    "8495653923123431417604973247489272438418190587263600148770280649306958101930",
-                )
-                .unwrap(),
-                Fq::from_str(
-                    "4082367875863433681332203403145435568316851327593401208105741076214120093531",
-                )
-                .unwrap(),
-            ),
-        );
-
-        let delta_g2 = G2Affine::new(
-            Fq2::new(
-                Fq::from_str(
-                    "19928663713463533589216209779412278386769407450988172849262535478593422929698",
-                )
-                .unwrap(),
-                Fq::from_str(
-                    "19916519943909223643323234301580053157586699704876134064841182937085943926141",
-                )
-                .unwrap(),
-            ),
-            Fq2::new(
-                Fq::from_str(
-                    "4584600978911428195337731119171761277167808711062125916470525050324985708782",
-                )
-                .unwrap(),
-                Fq::from_str(
-                    "903010326261527050999816348900764705196723158942686053018929539519969664840",
-                )
-                .unwrap(),
-            ),
-        );
-
-        let gamma_abc_g1 = vec![
-            G1Affine::new(
-                Fq::from_str(
-                    "6698887085900109660417671413804888867145870700073340970189635830129386206569",
-                )
-                .unwrap(),
-                Fq::from_str(
-                    "10431087902009508261375793061696708147989126018612269070732549055898651692604",
-                )
-                .unwrap(),
-            ),
-            G1Affine::new(
-                Fq::from_str(
-                    "20225609417084538563062516991929114218412992453664808591983416996515711931386",
-                )
-                .unwrap(),
-                Fq::from_str(
-                    "3236310410959095762960658876334609343091075204896196791007975095263664214628",
-                )
-                .unwrap(),
-            ),
-        ];
-
-        VerifyingKey::<Bn254> {
-            alpha_g1,
-            beta_g2,
-            gamma_g2,
-            delta_g2,
-            gamma_abc_g1,
-        }
-    }
-
-    // fn from_seal(seal: &[u8; 256]) -> Proof<Bn254> {
-    fn from_seal(seal: Seal) -> Proof<Bn254> {
-        let seal_bytes: [u8; 256] = seal.to_vec().try_into().unwrap();
-
-        let a = G1Affine::new(
-            ark_bn254::Fq::from_be_bytes_mod_order(&seal_bytes[0..32]),
-            ark_bn254::Fq::from_be_bytes_mod_order(&seal_bytes[32..64]),
-        );
-
-        let b = G2Affine::new(
-            ark_bn254::Fq2::from_base_prime_field_elems([
-                ark_bn254::Fq::from_be_bytes_mod_order(&seal_bytes[96..128]),
-                ark_bn254::Fq::from_be_bytes_mod_order(&seal_bytes[64..96]),
-            ])
-            .unwrap(),
-            ark_bn254::Fq2::from_base_prime_field_elems([
-                ark_bn254::Fq::from_be_bytes_mod_order(&seal_bytes[160..192]),
-                ark_bn254::Fq::from_be_bytes_mod_order(&seal_bytes[128..160]),
-            ])
-            .unwrap(),
-        );
+    use super::*;
 
-        let c = G1Affine::new(
-            ark_bn254::Fq::from_be_bytes_mod_order(&seal_bytes[192..224]),
-            ark_bn254::Fq::from_be_bytes_mod_order(&seal_bytes[224..256]),
-        );
+    const FINAL_CIRCUIT_ELF: &[u8] = include_bytes!("../../elfs/mainnet-final-spv-guest.bin");
+    const HEADER_CHAIN_CIRCUIT_ELF: &[u8] =
+        include_bytes!("../../elfs/mainnet-header-chain-guest.bin");
 
-        Proof {
-            a: a.into(),
-            b: b.into(),
-            c: c.into(),
-        }
-    }
-
-    use super::*;
-    // #[ignore = "This is to only test final proof generation"]
-    /// Run this test only when build for the mainnet
-    #[test]
-    fn test_final_circuit() {
-        let final_circuit_elf = include_bytes!("../../elfs/mainnet-final-spv-guest.bin");
-        let header_chain_circuit_elf = include_bytes!("../../elfs/mainnet-header-chain-guest.bin");
-        println!(
-            "Header chain circuit id: {:#?}",
-            compute_image_id(header_chain_circuit_elf)
-                .unwrap()
-                .as_words()
-        );
+    /// The final circuit input proving the genesis coinbase against the first 10 mainnet blocks,
+    /// and the header chain receipt it assumes.
+    fn mainnet_final_circuit_input() -> (FinalCircuitInput, Receipt) {
         let final_proof = include_bytes!("../../data/proofs/mainnet/mainnet_first_10.bin");
-        let final_circuit_id = compute_image_id(final_circuit_elf).unwrap();
-
         let receipt: Receipt = Receipt::try_from_slice(final_proof).unwrap();
 
         let mut mmr_native = MMRNative::new();
@@ -385,7 +999,50 @@ mod tests {
         let final_circuit_input: FinalCircuitInput = FinalCircuitInput {
             block_header_circuit_output: output,
             spv: spv,
+            // The first 10 mainnet blocks are far below `MAINNET_CONSTANTS.min_total_work`.
+            min_total_work: [0; 32],
         };
+        (final_circuit_input, receipt)
+    }
+
+    #[test]
+    #[ignore = "the embedded ELFs predate the versioned output, rebuild them with REPR_GUEST_BUILD=1"]
+    fn test_final_circuit_output() {
+        let (final_circuit_input, receipt) = mainnet_final_circuit_input();
+        let expected_output = FinalCircuitOutput::new(
+            &final_circuit_input,
+            compute_image_id(HEADER_CHAIN_CIRCUIT_ELF).unwrap().into(),
+            0,
+        );
+        let report = execute::execute(
+            FINAL_CIRCUIT_ELF,
+            &final_circuit_input,
+            vec![receipt.into()],
+        )
+        .unwrap();
+        let final_circuit_output =
+            FinalCircuitOutput::decode(&borsh::to_vec(&expected_output).unwrap(), &report.journal)
+                .unwrap();
+        assert_eq!(final_circuit_output, expected_output);
+    }
+
+    // #[ignore = "This is to only test final proof generation"]
+    /// Run this test only when build for the mainnet
+    #[test]
+    fn test_final_circuit() {
+        println!(
+            "Header chain circuit id: {:#?}",
+            compute_image_id(HEADER_CHAIN_CIRCUIT_ELF)
+                .unwrap()
+                .as_words()
+        );
+        let final_circuit_id = compute_image_id(FINAL_CIRCUIT_ELF).unwrap();
+        let (final_circuit_input, receipt) = mainnet_final_circuit_input();
+        let expected_output = FinalCircuitOutput::new(
+            &final_circuit_input,
+            compute_image_id(HEADER_CHAIN_CIRCUIT_ELF).unwrap().into(),
+            0,
+        );
         let env = ExecutorEnv::builder()
             .write_slice(&borsh::to_vec(&final_circuit_input).unwrap())
             .add_assumption(receipt)
@@ -395,7 +1052,7 @@ mod tests {
         let prover = default_prover();
 
         let receipt = prover
-            .prove_with_opts(env, final_circuit_elf, &ProverOpts::succinct())
+            .prove_with_opts(env, FINAL_CIRCUIT_ELF, &ProverOpts::succinct())
             .unwrap()
             .receipt;
 
@@ -403,8 +1060,24 @@ mod tests {
         let receipt_claim = succinct_receipt.clone().claim;
         println!("Receipt claim: {:#?}", receipt_claim);
         let journal: [u8; 32] = receipt.journal.bytes.clone().try_into().unwrap();
-        let (proof, output_json_bytes) =
-            stark_to_succinct(succinct_receipt, &receipt.journal.bytes);
+        let final_circuit_output = FinalCircuitOutput::decode(
+            &borsh::to_vec(&expected_output).unwrap(),
+            &receipt.journal.bytes,
+        )
+        .unwrap();
+        assert_eq!(final_circuit_output.tx_block_hash, MAINNET_BLOCK_HASHES[0]);
+        final_circuit_output.check_min_total_work(&[0; 32]).unwrap();
+        assert!(final_circuit_output
+            .check_min_total_work(&MAINNET_CONSTANTS.min_total_work.to_be_bytes())
+            .is_err());
+        let groth16_output = stark_to_succinct(
+            succinct_receipt,
+            &receipt.journal.bytes,
+            &Groth16ProverConfig::default(),
+        )
+        .unwrap();
+        let proof = groth16_output.seal.clone();
+        let output_json_bytes = groth16_output.public_output;
         println!("Proof: {:?}", proof);
         let constants_digest = calculate_succinct_output_prefix(final_circuit_id.as_bytes());
         println!("Constants digest: {:?}", constants_digest);
@@ -418,7 +1091,7 @@ mod tests {
         let final_output_trimmed: [u8; 31] = final_output_bytes[..31].try_into().unwrap();
         assert_eq!(final_output_trimmed, output_json_bytes);
 
-        let ark_proof = from_seal(proof);
+        let ark_proof = proof_from_uncompressed(&proof.to_vec().try_into().unwrap()).unwrap();
         let public_input_scalar = ark_bn254::Fr::from_be_bytes_mod_order(&final_output_trimmed);
         println!("Public input scalar: {:?}", public_input_scalar);
         let ark_vk = get_ark_verifying_key();
@@ -433,5 +1106,34 @@ mod tests {
 
         println!("Verification result: {:?}", res);
         assert!(res, "Verification failed");
+
+        let bundle = BitVmProofBundle::new(&receipt, &groth16_output).unwrap();
+        assert_eq!(bundle.method_id, final_circuit_id.as_words());
+        assert_eq!(bundle.public_input[1..], final_output_trimmed);
+        let bundle_dir = tempfile::tempdir().unwrap();
+        bundle.save(bundle_dir.path()).unwrap();
+        for file_name in ["bundle.borsh", "bundle.json"] {
+            let loaded = BitVmProofBundle::load(&bundle_dir.path().join(file_name)).unwrap();
+            assert_eq!(loaded, bundle);
+            loaded.validate().unwrap();
+        }
+
+        let mut tampered = bundle.clone();
+        tampered.journal[0] ^= 1;
+        assert!(matches!(
+            tampered.validate(),
+            Err(bundle::BundleError::PublicInputMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_load_prev_receipt_errors() {
+        assert!(load_prev_receipt("None").unwrap().is_none());
+        assert!(load_prev_receipt("/nonexistent/receipt.bin").is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("receipt.bin");
+        fs::write(&path, b"not a receipt").unwrap();
+        assert!(load_prev_receipt(path.to_str().unwrap()).is_err());
     }
 }
@@ -0,0 +1,441 @@
+//! A minimal Bitcoin P2P client that downloads headers headers-first from a single peer, for
+//! environments without RPC access to a full node.
+
+use bitcoin::{
+    consensus::{deserialize, serialize},
+    hashes::Hash,
+    p2p::{
+        message::{NetworkMessage, RawNetworkMessage},
+        message_blockdata::GetHeadersMessage,
+        message_network::VersionMessage,
+        Address, Magic, ServiceFlags,
+    },
+    BlockHash,
+};
+use risc0_to_bitvm2_core::{
+    header_chain::{ChainState, CircuitBlockHeader, NetworkConstants},
+    mmr_native::MMRNative,
+};
+use std::{
+    error::Error,
+    fmt, io,
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::header_source::HeaderSource;
+
+/// The most headers a peer sends in one `headers` message.
+pub const MAX_HEADERS_PER_MESSAGE: usize = 2000;
+
+/// The limit of Bitcoin Core on the payload of a message.
+const MAX_PAYLOAD_LEN: usize = 4_000_000;
+
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+const USER_AGENT: &str = "/risc0-to-bitvm2:0.1.0/";
+
+#[derive(Debug)]
+pub enum P2pError {
+    Io(io::Error),
+    /// The peer sent a message that does not decode, or is of another network.
+    Malformed(String),
+    /// The peer does not follow the protocol, e.g. it sent headers of another chain.
+    Protocol(String),
+    InvalidHeader {
+        height: u32,
+        reason: String,
+    },
+}
+
+impl fmt::Display for P2pError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            P2pError::Io(e) => write!(f, "connection error: {}", e),
+            P2pError::Malformed(e) => write!(f, "malformed message: {}", e),
+            P2pError::Protocol(e) => write!(f, "protocol error: {}", e),
+            P2pError::InvalidHeader { height, reason } => {
+                write!(f, "invalid header at height {}: {}", height, reason)
+            }
+        }
+    }
+}
+
+impl Error for P2pError {}
+
+impl From<io::Error> for P2pError {
+    fn from(e: io::Error) -> Self {
+        P2pError::Io(e)
+    }
+}
+
+/// A connection to a Bitcoin node that speaks just enough of the P2P protocol to download headers.
+pub struct Peer {
+    stream: TcpStream,
+    magic: Magic,
+    /// The `version` message of the peer.
+    pub version: Option<VersionMessage>,
+}
+
+impl Peer {
+    /// Connects to the node and performs the version handshake.
+    pub fn connect(addr: SocketAddr, network_magic: [u8; 4]) -> Result<Self, P2pError> {
+        let stream = TcpStream::connect_timeout(&addr, TIMEOUT)?;
+        let mut peer = Peer::new(stream, network_magic)?;
+        peer.handshake()?;
+        Ok(peer)
+    }
+
+    fn new(stream: TcpStream, network_magic: [u8; 4]) -> Result<Self, P2pError> {
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        Ok(Peer {
+            stream,
+            magic: Magic::from_bytes(network_magic),
+            version: None,
+        })
+    }
+
+    fn handshake(&mut self) -> Result<(), P2pError> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let mut version = VersionMessage::new(
+            ServiceFlags::NONE,
+            now.as_secs() as i64,
+            Address::new(&self.stream.peer_addr()?, ServiceFlags::NONE),
+            Address::new(&SocketAddr::from(([0, 0, 0, 0], 0)), ServiceFlags::NONE),
+            now.as_nanos() as u64,
+            USER_AGENT.to_string(),
+            0,
+        );
+        version.relay = false;
+        self.send(NetworkMessage::Version(version))?;
+
+        let mut verack = false;
+        while self.version.is_none() || !verack {
+            match self.receive()? {
+                NetworkMessage::Version(version) => {
+                    if self.version.is_some() {
+                        return Err(P2pError::Protocol("duplicate version message".to_string()));
+                    }
+                    self.version = Some(version);
+                    self.send(NetworkMessage::Verack)?;
+                }
+                NetworkMessage::Verack => verack = true,
+                // E.g. feature negotiation messages, which are optional.
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, message: NetworkMessage) -> Result<(), P2pError> {
+        self.stream
+            .write_all(&serialize(&RawNetworkMessage::new(self.magic, message)))?;
+        Ok(())
+    }
+
+    /// Receives the next message, answering pings on the way.
+    fn receive(&mut self) -> Result<NetworkMessage, P2pError> {
+        loop {
+            // The magic, command, payload length and checksum.
+            let mut bytes = vec![0u8; 24];
+            self.stream.read_exact(&mut bytes)?;
+            if bytes[..4] != self.magic.to_bytes() {
+                return Err(P2pError::Malformed(format!(
+                    "message with the magic {} of another network",
+                    hex::encode(&bytes[..4])
+                )));
+            }
+            let payload_len = u32::from_le_bytes(bytes[16..20].try_into().unwrap()) as usize;
+            if payload_len > MAX_PAYLOAD_LEN {
+                return Err(P2pError::Malformed(format!(
+                    "payload of {} bytes",
+                    payload_len
+                )));
+            }
+            bytes.resize(24 + payload_len, 0);
+            self.stream.read_exact(&mut bytes[24..])?;
+            let message: RawNetworkMessage =
+                deserialize(&bytes).map_err(|e| P2pError::Malformed(e.to_string()))?;
+            match message.into_payload() {
+                NetworkMessage::Ping(nonce) => self.send(NetworkMessage::Pong(nonce))?,
+                message => return Ok(message),
+            }
+        }
+    }
+
+    /// Requests the headers after the first block of the locator the peer knows, or after
+    /// genesis if it knows none. The peer sends at most `MAX_HEADERS_PER_MESSAGE`.
+    pub fn get_headers(
+        &mut self,
+        locator: &[[u8; 32]],
+    ) -> Result<Vec<CircuitBlockHeader>, P2pError> {
+        let locator_hashes = locator
+            .iter()
+            .map(|hash| BlockHash::from_byte_array(*hash))
+            .collect();
+        self.send(NetworkMessage::GetHeaders(GetHeadersMessage::new(
+            locator_hashes,
+            BlockHash::all_zeros(),
+        )))?;
+        loop {
+            if let NetworkMessage::Headers(headers) = self.receive()? {
+                if headers.len() > MAX_HEADERS_PER_MESSAGE {
+                    return Err(P2pError::Protocol(format!(
+                        "{} headers in one message",
+                        headers.len()
+                    )));
+                }
+                return Ok(headers.into_iter().map(CircuitBlockHeader::from).collect());
+            }
+        }
+    }
+}
+
+/// Builds a block locator from the block hashes from genesis: the 10 newest hashes, then hashes
+/// exponentially further back, down to the genesis block.
+pub fn block_locator(block_hashes: &[[u8; 32]]) -> Vec<[u8; 32]> {
+    let mut locator = vec![];
+    let Some(mut height) = block_hashes.len().checked_sub(1) else {
+        return locator;
+    };
+    let mut step = 1;
+    loop {
+        locator.push(block_hashes[height]);
+        if height == 0 {
+            return locator;
+        }
+        if locator.len() >= 10 {
+            step *= 2;
+        }
+        height = height.saturating_sub(step);
+    }
+}
+
+/// Downloads headers from a peer, validating them natively as they arrive.
+pub struct PeerHeaderSource {
+    peer: Peer,
+    params: &'static NetworkConstants,
+    /// The MMR of the blocks from genesis to the last validated header. Its leaves are the block
+    /// hashes the locators are built from.
+    mmr: MMRNative,
+}
+
+impl PeerHeaderSource {
+    pub fn new(peer: Peer, params: &'static NetworkConstants) -> Self {
+        PeerHeaderSource {
+            peer,
+            params,
+            mmr: MMRNative::new(),
+        }
+    }
+
+    /// Starts the MMR from known headers from genesis, e.g. a headers file, so that locators can
+    /// fall back to earlier blocks if the peer is on another branch.
+    pub fn with_known_headers(mut self, headers: &[CircuitBlockHeader]) -> Self {
+        for header in headers {
+            self.mmr.append(header.compute_block_hash());
+        }
+        self
+    }
+
+    /// The locator of the chain state. Without the hashes of its blocks, the peer has to know
+    /// its best block.
+    fn locator(&self, chain_state: &ChainState) -> Vec<[u8; 32]> {
+        let block_hashes = &self.mmr.nodes[0];
+        if block_hashes.len() == chain_state.block_height.wrapping_add(1) as usize
+            && block_hashes.last() == Some(&chain_state.best_block_hash)
+        {
+            block_locator(block_hashes)
+        } else {
+            vec![chain_state.best_block_hash]
+        }
+    }
+
+    fn accept(
+        &mut self,
+        chain_state: &mut ChainState,
+        header: CircuitBlockHeader,
+    ) -> Result<CircuitBlockHeader, P2pError> {
+        let height = chain_state.block_height.wrapping_add(1);
+        chain_state
+            .try_apply_block(&header, self.params)
+            .map_err(|e| P2pError::InvalidHeader {
+                height,
+                reason: e.to_string(),
+            })?;
+        // The MMR only follows the chain state if it has all of its blocks.
+        if self.mmr.nodes[0].len() == height as usize {
+            self.mmr.append(chain_state.best_block_hash);
+        }
+        Ok(header)
+    }
+}
+
+impl HeaderSource for PeerHeaderSource {
+    fn headers_after(
+        &mut self,
+        chain_state: &ChainState,
+        count: usize,
+    ) -> Result<Vec<CircuitBlockHeader>, Box<dyn Error>> {
+        let start_height = chain_state.block_height.wrapping_add(1);
+        let mut chain_state = chain_state.clone();
+        let mut headers = vec![];
+        if chain_state.block_height == u32::MAX && count > 0 {
+            // Peers never send the genesis block, which every node has.
+            let genesis = self.params.genesis_block_header.clone();
+            headers.push(self.accept(&mut chain_state, genesis)?);
+        }
+        while headers.len() < count {
+            let received = self.peer.get_headers(&self.locator(&chain_state))?;
+            if received.is_empty() {
+                break;
+            }
+            for header in received.into_iter().take(count - headers.len()) {
+                headers.push(self.accept(&mut chain_state, header)?);
+            }
+        }
+        if headers.len() < count {
+            return Err(format!(
+                "the peer has only {} of the {} headers from height {}",
+                headers.len(),
+                count,
+                start_height
+            )
+            .into());
+        }
+        Ok(headers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use borsh::BorshDeserialize;
+    use risc0_to_bitvm2_core::header_chain::REGTEST_CONSTANTS;
+    use std::{net::TcpListener, thread};
+
+    const REGTEST_HEADERS: &[u8] = include_bytes!("../../data/headers/regtest-headers.bin");
+
+    fn regtest_headers() -> Vec<CircuitBlockHeader> {
+        REGTEST_HEADERS
+            .chunks(80)
+            .map(|header| CircuitBlockHeader::try_from_slice(header).unwrap())
+            .collect()
+    }
+
+    /// Serves the headers to one connection like a node would, sending at most `batch` headers
+    /// per message, until the connection is closed.
+    fn fake_peer(headers: Vec<CircuitBlockHeader>, batch: usize) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut peer = Peer::new(stream, REGTEST_CONSTANTS.magic).unwrap();
+            while let Ok(message) = peer.receive() {
+                match message {
+                    NetworkMessage::Version(version) => {
+                        peer.send(NetworkMessage::Version(version)).unwrap();
+                        peer.send(NetworkMessage::SendHeaders).unwrap();
+                        peer.send(NetworkMessage::Ping(7)).unwrap();
+                        peer.send(NetworkMessage::Verack).unwrap();
+                    }
+                    NetworkMessage::GetHeaders(request) => {
+                        let start = request
+                            .locator_hashes
+                            .iter()
+                            .find_map(|hash| {
+                                headers.iter().position(|header| {
+                                    header.compute_block_hash() == hash.to_byte_array()
+                                })
+                            })
+                            .unwrap_or(0)
+                            + 1;
+                        let end = (start + batch).min(headers.len()).max(start);
+                        let response = headers[start.min(end)..end]
+                            .iter()
+                            .map(|header| header.clone().into())
+                            .collect();
+                        peer.send(NetworkMessage::Headers(response)).unwrap();
+                    }
+                    _ => {}
+                }
+            }
+        });
+        addr
+    }
+
+    fn connect(headers: Vec<CircuitBlockHeader>, batch: usize) -> PeerHeaderSource {
+        let peer = Peer::connect(fake_peer(headers, batch), REGTEST_CONSTANTS.magic).unwrap();
+        assert_eq!(peer.version.as_ref().unwrap().user_agent, USER_AGENT);
+        PeerHeaderSource::new(peer, &REGTEST_CONSTANTS)
+    }
+
+    #[test]
+    fn test_block_locator() {
+        let block_hashes: Vec<[u8; 32]> = (0..100u8).map(|height| [height; 32]).collect();
+        let heights: Vec<u8> = block_locator(&block_hashes)
+            .iter()
+            .map(|hash| hash[0])
+            .collect();
+        assert_eq!(
+            heights,
+            [99, 98, 97, 96, 95, 94, 93, 92, 91, 90, 88, 84, 76, 60, 28, 0]
+        );
+        assert_eq!(block_locator(&block_hashes[..1]), vec![[0; 32]]);
+        assert!(block_locator(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_download_from_genesis() {
+        let headers = regtest_headers();
+        // Several `getheaders` round trips are needed.
+        let mut source = connect(headers.clone(), 30);
+        let downloaded = source
+            .headers_after(
+                &ChainState::new_with_params(&REGTEST_CONSTANTS),
+                headers.len(),
+            )
+            .unwrap();
+        assert_eq!(downloaded, headers);
+
+        // The peer has no more headers.
+        let mut chain_state = ChainState::new_with_params(&REGTEST_CONSTANTS);
+        chain_state.apply_blocks_with_params(headers.clone(), &REGTEST_CONSTANTS);
+        assert!(source.headers_after(&chain_state, 1).is_err());
+    }
+
+    #[test]
+    fn test_download_after_chain_state() {
+        let headers = regtest_headers();
+        let mut chain_state = ChainState::new_with_params(&REGTEST_CONSTANTS);
+        chain_state.apply_blocks_with_params(headers[..50].to_vec(), &REGTEST_CONSTANTS);
+
+        let mut source = connect(headers.clone(), 2000);
+        assert_eq!(
+            source.headers_after(&chain_state, 20).unwrap(),
+            headers[50..70]
+        );
+        // With the known headers, the locator goes back to genesis.
+        let source = connect(headers.clone(), 2000).with_known_headers(&headers[..50]);
+        assert_eq!(source.locator(&chain_state).len(), 15);
+    }
+
+    #[test]
+    fn test_invalid_header() {
+        let mut headers = regtest_headers();
+        headers[40].bits = 0x1d00ffff;
+        let mut source = connect(headers, 2000);
+        let err = source
+            .headers_after(&ChainState::new_with_params(&REGTEST_CONSTANTS), 100)
+            .unwrap_err();
+        let err = err.downcast::<P2pError>().unwrap();
+        assert!(
+            matches!(*err, P2pError::InvalidHeader { height: 40, .. }),
+            "{}",
+            err
+        );
+    }
+}
@@ -0,0 +1,337 @@
+use risc0_to_bitvm2_core::header_chain::{
+    BlockHeaderCircuitOutput, ChainState, CircuitBlockHeader, HeaderChainCircuitInput,
+    HeaderChainPrevProofType, NetworkConstants,
+};
+use serde::{Deserialize, Serialize};
+use std::{error::Error, fmt, fs, path::Path};
+
+use crate::execute::ExecutionReport;
+
+/// The current version of `BatchPlan`.
+pub const BATCH_PLAN_VERSION: u32 = 1;
+
+/// The limits of a single header chain receipt. A batch fits if it is within all of the limits.
+#[derive(clap::Args, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PlanBudget {
+    /// The maximum number of proven cycles (including padding) per receipt.
+    #[clap(long)]
+    pub max_total_cycles: Option<u64>,
+    /// The maximum number of segments per receipt.
+    #[clap(long)]
+    pub max_segments: Option<usize>,
+    /// The maximum number of headers per receipt.
+    #[clap(long, default_value_t = 10000)]
+    pub max_batch_size: usize,
+}
+
+impl PlanBudget {
+    pub fn fits(&self, report: &ExecutionReport) -> bool {
+        self.max_total_cycles
+            .is_none_or(|max| report.total_cycles <= max)
+            && self
+                .max_segments
+                .is_none_or(|max| report.segment_count() <= max)
+    }
+}
+
+/// A batch of headers to prove in one receipt, with its measured cost.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PlannedBatch {
+    /// The height of the first header of the batch.
+    pub start_height: u32,
+    pub batch_size: usize,
+    pub user_cycles: u64,
+    pub total_cycles: u64,
+    pub segments: usize,
+}
+
+/// The batches to prove a range of headers in, consumed by `host prove-plan`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BatchPlan {
+    pub version: u32,
+    pub network: String,
+    pub header_chain_method_id: [u32; 8],
+    /// The proof the first batch continues from, if it does not start at genesis.
+    pub input_proof: Option<String>,
+    pub budget: PlanBudget,
+    pub batches: Vec<PlannedBatch>,
+}
+
+#[derive(Debug)]
+pub enum PlanError {
+    /// A single header does not fit in the budget.
+    HeaderTooLarge {
+        height: u32,
+    },
+    Execution(Box<dyn Error>),
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for PlanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlanError::HeaderTooLarge { height } => {
+                write!(
+                    f,
+                    "the header at height {} alone exceeds the budget",
+                    height
+                )
+            }
+            PlanError::Execution(e) => write!(f, "execution failed: {}", e),
+            PlanError::Io(e) => write!(f, "{}", e),
+            PlanError::Json(e) => write!(f, "malformed plan: {}", e),
+            PlanError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported plan version {} (expected {})",
+                version, BATCH_PLAN_VERSION
+            ),
+        }
+    }
+}
+
+impl Error for PlanError {}
+
+impl BatchPlan {
+    pub fn save(&self, path: &Path) -> Result<(), PlanError> {
+        let json = serde_json::to_vec_pretty(self).map_err(PlanError::Json)?;
+        fs::write(path, json).map_err(PlanError::Io)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, PlanError> {
+        let plan: BatchPlan = serde_json::from_slice(&fs::read(path).map_err(PlanError::Io)?)
+            .map_err(PlanError::Json)?;
+        if plan.version != BATCH_PLAN_VERSION {
+            return Err(PlanError::UnsupportedVersion(plan.version));
+        }
+        Ok(plan)
+    }
+}
+
+/// Splits `headers` into batches that fit in the budget, continuing from `prev_output` (or
+/// genesis). The chain state is advanced natively between batches, and `measure` executes the
+/// header chain guest on a candidate batch. Batch sizes are assumed to cost monotonically more.
+pub fn plan_batches(
+    headers: &[CircuitBlockHeader],
+    prev_output: Option<BlockHeaderCircuitOutput>,
+    method_id: [u32; 8],
+    params: &NetworkConstants,
+    budget: &PlanBudget,
+    mut measure: impl FnMut(&HeaderChainCircuitInput) -> Result<ExecutionReport, Box<dyn Error>>,
+) -> Result<Vec<PlannedBatch>, PlanError> {
+    let mut chain_state = prev_output
+        .as_ref()
+        .map(|output| output.chain_state.clone())
+        .unwrap_or_else(|| ChainState::new_with_params(params));
+    let mut prev_proof = match prev_output {
+        Some(output) => HeaderChainPrevProofType::PrevProof(output),
+        None => HeaderChainPrevProofType::GenesisBlock,
+    };
+
+    let mut batches = vec![];
+    let mut start = 0;
+    let mut guess = budget.max_batch_size.min(100);
+    while start < headers.len() {
+        let start_height = chain_state.block_height.wrapping_add(1);
+        let max_size = budget.max_batch_size.min(headers.len() - start);
+        let mut measure_batch = |batch_size: usize| {
+            measure(&HeaderChainCircuitInput {
+                method_id,
+                prev_proof: prev_proof.clone(),
+                block_headers: headers[start..start + batch_size].to_vec(),
+            })
+            .map_err(PlanError::Execution)
+        };
+
+        // Double the batch size until it does not fit, then binary search between the largest
+        // size that fits and the smallest one that does not.
+        let mut fitting: Option<(usize, ExecutionReport)> = None;
+        let mut too_large: Option<usize> = None;
+        let mut batch_size = guess.clamp(1, max_size);
+        loop {
+            let report = measure_batch(batch_size)?;
+            if budget.fits(&report) {
+                fitting = Some((batch_size, report));
+            } else {
+                too_large = Some(batch_size);
+            }
+            let lower = fitting.as_ref().map_or(0, |(size, _)| *size);
+            batch_size = match too_large {
+                Some(upper) if upper == lower + 1 => break,
+                Some(upper) => (lower + upper) / 2,
+                None if lower == max_size => break,
+                None => (lower * 2).min(max_size),
+            };
+        }
+
+        let (batch_size, report) = fitting.ok_or(PlanError::HeaderTooLarge {
+            height: start_height,
+        })?;
+        batches.push(PlannedBatch {
+            start_height,
+            batch_size,
+            user_cycles: report.user_cycles,
+            total_cycles: report.total_cycles,
+            segments: report.segment_count(),
+        });
+
+        chain_state.apply_blocks_with_params(headers[start..start + batch_size].to_vec(), params);
+        prev_proof = HeaderChainPrevProofType::PrevProof(BlockHeaderCircuitOutput {
+            method_id,
+            chain_state: chain_state.clone(),
+        });
+        start += batch_size;
+        guess = batch_size;
+    }
+    Ok(batches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use borsh::BorshDeserialize;
+    use risc0_to_bitvm2_core::header_chain::REGTEST_CONSTANTS;
+
+    const REGTEST_HEADERS: &[u8] = include_bytes!("../../data/headers/regtest-headers.bin");
+
+    fn regtest_headers() -> Vec<CircuitBlockHeader> {
+        REGTEST_HEADERS
+            .chunks(80)
+            .map(|header| CircuitBlockHeader::try_from_slice(header).unwrap())
+            .collect()
+    }
+
+    /// A cost model where every header costs 1000 cycles, except every tenth one that costs 5000,
+    /// in segments of 2^12 cycles.
+    fn fake_report(input: &HeaderChainCircuitInput, chain_state: &ChainState) -> ExecutionReport {
+        let first_height = chain_state.block_height.wrapping_add(1) as u64;
+        let user_cycles: u64 = (first_height..first_height + input.block_headers.len() as u64)
+            .map(|height| if height % 10 == 9 { 5000 } else { 1000 })
+            .sum();
+        let segments = user_cycles.div_ceil(1 << 12) as usize;
+        ExecutionReport {
+            user_cycles,
+            total_cycles: (segments as u64) << 12,
+            segment_po2s: vec![12; segments],
+            phases: vec![],
+            journal: vec![],
+        }
+    }
+
+    fn plan(budget: &PlanBudget) -> Result<Vec<PlannedBatch>, PlanError> {
+        let mut executions = 0;
+        let result = plan_batches(
+            &regtest_headers(),
+            None,
+            [0; 8],
+            &REGTEST_CONSTANTS,
+            budget,
+            |input| {
+                executions += 1;
+                let chain_state = match &input.prev_proof {
+                    HeaderChainPrevProofType::GenesisBlock => {
+                        ChainState::new_with_params(&REGTEST_CONSTANTS)
+                    }
+                    HeaderChainPrevProofType::PrevProof(output) => output.chain_state.clone(),
+                    _ => unreachable!("The planner only continues from proofs"),
+                };
+                Ok(fake_report(input, &chain_state))
+            },
+        );
+        // The search takes logarithmically many executions per batch.
+        assert!(executions < 101);
+        result
+    }
+
+    #[test]
+    fn test_plan_batches() {
+        let budget = PlanBudget {
+            max_total_cycles: None,
+            max_segments: Some(4),
+            max_batch_size: 1000,
+        };
+        let batches = plan(&budget).unwrap();
+        assert_eq!(
+            batches.iter().map(|batch| batch.batch_size).sum::<usize>(),
+            101
+        );
+
+        let mut next_height = 0;
+        for (i, batch) in batches.iter().enumerate() {
+            assert_eq!(batch.start_height, next_height);
+            assert!(batch.segments <= 4);
+            // Every batch but the last one is as large as possible.
+            if i + 1 < batches.len() {
+                let next_cost = if (next_height + batch.batch_size as u32) % 10 == 9 {
+                    5000
+                } else {
+                    1000
+                };
+                assert!((batch.user_cycles + next_cost).div_ceil(1 << 12) > 4);
+            }
+            next_height += batch.batch_size as u32;
+        }
+    }
+
+    #[test]
+    fn test_plan_batches_max_batch_size() {
+        let budget = PlanBudget {
+            max_total_cycles: Some(u64::MAX),
+            max_segments: None,
+            max_batch_size: 30,
+        };
+        let batch_sizes = plan(&budget)
+            .unwrap()
+            .iter()
+            .map(|batch| batch.batch_size)
+            .collect::<Vec<usize>>();
+        assert_eq!(batch_sizes, vec![30, 30, 30, 11]);
+    }
+
+    #[test]
+    fn test_plan_batches_header_too_large() {
+        let budget = PlanBudget {
+            max_total_cycles: Some(1 << 11),
+            max_segments: None,
+            max_batch_size: 1000,
+        };
+        assert!(matches!(
+            plan(&budget),
+            Err(PlanError::HeaderTooLarge { height: 0 })
+        ));
+    }
+
+    #[test]
+    fn test_plan_file_roundtrip() {
+        let plan = BatchPlan {
+            version: BATCH_PLAN_VERSION,
+            network: "regtest".to_string(),
+            header_chain_method_id: [1, 2, 3, 4, 5, 6, 7, 8],
+            input_proof: None,
+            budget: PlanBudget {
+                max_total_cycles: Some(1 << 22),
+                max_segments: None,
+                max_batch_size: 1000,
+            },
+            batches: plan(&PlanBudget {
+                max_total_cycles: Some(1 << 14),
+                max_segments: None,
+                max_batch_size: 1000,
+            })
+            .unwrap(),
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("plan.json");
+        plan.save(&path).unwrap();
+        assert_eq!(BatchPlan::load(&path).unwrap(), plan);
+
+        let mut future = plan;
+        future.version += 1;
+        future.save(&path).unwrap();
+        assert!(matches!(
+            BatchPlan::load(&path),
+            Err(PlanError::UnsupportedVersion(2))
+        ));
+    }
+}
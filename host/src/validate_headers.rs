@@ -0,0 +1,415 @@
+use crate::inspect::ChainSummary;
+use borsh::BorshDeserialize;
+use num_bigint::BigUint;
+use num_traits::ToPrimitive;
+use risc0_to_bitvm2_core::{
+    header_chain::{
+        bits_to_target, network_constants, signet_constants_from_hex, BlockHeaderCircuitOutput,
+        ChainState, CircuitBlockHeader, NetworkConstants, NETWORK_TYPE,
+    },
+    headers_file::HeadersFile,
+};
+use risc0_zkvm::Receipt;
+use serde::{Deserialize, Serialize};
+use std::{error::Error, fmt, fs, path::PathBuf, time::Instant};
+
+const BLOCKS_PER_EPOCH: u32 = 2016;
+
+/// How many min-difficulty block heights the text report lists.
+const MIN_DIFFICULTY_BLOCKS_SHOWN: usize = 10;
+
+#[derive(clap::Args, Debug)]
+pub struct ValidateHeadersArgs {
+    /// The headers file path, versioned or legacy.
+    pub headers: PathBuf,
+    /// The network of the headers.
+    #[clap(long, default_value = NETWORK_TYPE)]
+    pub network: String,
+    /// Start after the chain state of this header chain proof instead of before genesis.
+    #[clap(long)]
+    pub input_proof: Option<PathBuf>,
+    /// Validate at most this many headers.
+    #[clap(long)]
+    pub count: Option<usize>,
+    /// Print the report as JSON.
+    #[clap(long)]
+    pub json: bool,
+    /// The challenge script of a custom signet, in hex. Defaults to the one of the public signet.
+    #[clap(long)]
+    pub signet_challenge: Option<String>,
+    /// The pow limit of a custom signet, as hex compact bits. Defaults to the one of the public
+    /// signet.
+    #[clap(long)]
+    pub signet_pow_limit: Option<String>,
+}
+
+/// The difficulty adjustment at the end of an epoch.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Retarget {
+    /// The height of the first block of the new epoch.
+    pub height: u32,
+    /// The seconds between the first and the last block of the previous epoch.
+    pub timespan: i64,
+    pub old_bits: String,
+    pub new_bits: String,
+    /// The old target divided by the new one, i.e. above 1 if the difficulty went up.
+    pub difficulty_change: f64,
+}
+
+/// The first header that fails the consensus checks.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct InvalidHeader {
+    pub height: u32,
+    /// The block hash in display (reversed) byte order.
+    pub block_hash: String,
+    pub reason: String,
+}
+
+/// What `host validate-headers` reports about a range of headers.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ValidationReport {
+    pub network: String,
+    /// The height of the first header of the range.
+    pub start_height: u32,
+    /// The number of valid headers, up to the first invalid one.
+    pub valid_headers: usize,
+    /// The state after the last valid header.
+    pub chain_state: ChainSummary,
+    pub retargets: Vec<Retarget>,
+    /// The heights of the blocks that used the minimum difficulty of testnet3 and testnet4.
+    pub min_difficulty_blocks: Vec<u32>,
+    pub elapsed_secs: f64,
+    pub headers_per_sec: f64,
+    pub invalid_header: Option<InvalidHeader>,
+}
+
+pub fn run(args: &ValidateHeadersArgs) -> Result<ValidationReport, Box<dyn Error>> {
+    let custom_signet;
+    let params = if args.signet_challenge.is_some() || args.signet_pow_limit.is_some() {
+        if args.network != "signet" {
+            return Err(format!("{} is not a signet", args.network).into());
+        }
+        custom_signet = signet_constants_from_hex(
+            args.signet_challenge.as_deref(),
+            args.signet_pow_limit.as_deref(),
+        )?;
+        &custom_signet
+    } else {
+        network_constants(&args.network)
+            .ok_or_else(|| format!("unknown network {}", args.network))?
+    };
+    let file = HeadersFile::decode(&fs::read(&args.headers)?, params.magic)?;
+    let chain_state = match &args.input_proof {
+        Some(path) => {
+            let receipt = Receipt::try_from_slice(&fs::read(path)?)?;
+            BlockHeaderCircuitOutput::try_from_slice(&receipt.journal.bytes)?.chain_state
+        }
+        None => ChainState::new_with_params(params),
+    };
+
+    let start_height = chain_state.block_height.wrapping_add(1);
+    if start_height < file.start_height || start_height > file.end_height() {
+        return Err(format!(
+            "the headers file starts at height {} and ends before {}, but the range starts at {}",
+            file.start_height,
+            file.end_height(),
+            start_height
+        )
+        .into());
+    }
+    let headers = &file.headers[(start_height - file.start_height) as usize..];
+    let count = args.count.unwrap_or(headers.len()).min(headers.len());
+    Ok(validate_headers(
+        chain_state,
+        &headers[..count],
+        params,
+        &args.network,
+    ))
+}
+
+/// Applies the headers one by one with the consensus checks of the circuit, stopping at the first
+/// invalid one.
+pub fn validate_headers(
+    mut chain_state: ChainState,
+    headers: &[CircuitBlockHeader],
+    params: &NetworkConstants,
+    network: &str,
+) -> ValidationReport {
+    let start_height = chain_state.block_height.wrapping_add(1);
+    let mut retargets = vec![];
+    let mut min_difficulty_blocks = vec![];
+    let mut invalid_header = None;
+    let mut valid_headers = 0;
+
+    let start = Instant::now();
+    for header in headers {
+        let height = chain_state.block_height.wrapping_add(1);
+        let min_difficulty = chain_state.is_min_difficulty_block(header.time, params);
+        let old_bits = chain_state.current_target_bits;
+        if let Err(e) = chain_state.try_apply_block(header, params) {
+            invalid_header = Some(InvalidHeader {
+                height,
+                block_hash: display_hash(header.compute_block_hash()),
+                reason: e.to_string(),
+            });
+            break;
+        }
+        valid_headers += 1;
+
+        if min_difficulty {
+            min_difficulty_blocks.push(height);
+        }
+        if !params.pow_no_retargeting && height % BLOCKS_PER_EPOCH == BLOCKS_PER_EPOCH - 1 {
+            retargets.push(Retarget {
+                height: height + 1,
+                timespan: header.time as i64 - chain_state.epoch_start_time as i64,
+                old_bits: format!("{:08x}", old_bits),
+                new_bits: format!("{:08x}", chain_state.current_target_bits),
+                difficulty_change: target(old_bits) / target(chain_state.current_target_bits),
+            });
+        }
+    }
+    let elapsed_secs = start.elapsed().as_secs_f64();
+
+    ValidationReport {
+        network: network.to_string(),
+        start_height,
+        valid_headers,
+        chain_state: ChainSummary::new(&chain_state),
+        retargets,
+        min_difficulty_blocks,
+        elapsed_secs,
+        headers_per_sec: valid_headers as f64 / elapsed_secs.max(f64::MIN_POSITIVE),
+        invalid_header,
+    }
+}
+
+fn target(bits: u32) -> f64 {
+    BigUint::from_bytes_be(&bits_to_target(bits))
+        .to_f64()
+        .unwrap_or(f64::INFINITY)
+}
+
+fn display_hash(mut hash: [u8; 32]) -> String {
+    hash.reverse();
+    hex::encode(hash)
+}
+
+impl fmt::Display for Retarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "  height {}: bits {} -> {} (difficulty x{:.4}, timespan {}s)",
+            self.height, self.old_bits, self.new_bits, self.difficulty_change, self.timespan
+        )
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Validated {} {} headers from height {} in {:.2}s ({:.0} headers/s)",
+            self.valid_headers,
+            self.network,
+            self.start_height,
+            self.elapsed_secs,
+            self.headers_per_sec
+        )?;
+        writeln!(f, "Chain state:\n{}", self.chain_state)?;
+        if !self.retargets.is_empty() {
+            writeln!(f, "Retargets:")?;
+            for retarget in &self.retargets {
+                writeln!(f, "{}", retarget)?;
+            }
+        }
+        if !self.min_difficulty_blocks.is_empty() {
+            let shown = &self.min_difficulty_blocks[..self
+                .min_difficulty_blocks
+                .len()
+                .min(MIN_DIFFICULTY_BLOCKS_SHOWN)];
+            writeln!(
+                f,
+                "Min-difficulty blocks: {} (heights {}{})",
+                self.min_difficulty_blocks.len(),
+                shown
+                    .iter()
+                    .map(|height| height.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                if shown.len() < self.min_difficulty_blocks.len() {
+                    ", ..."
+                } else {
+                    ""
+                }
+            )?;
+        }
+        match &self.invalid_header {
+            Some(invalid) => write!(
+                f,
+                "Invalid header at height {} ({}): {}",
+                invalid.height, invalid.block_hash, invalid.reason
+            ),
+            None => write!(f, "All headers are valid"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use risc0_to_bitvm2_core::{
+        header_chain::{REGTEST_CONSTANTS, SIGNET_CONSTANTS, TESTNET4_CONSTANTS},
+        synthetic::{mine, ChainBuilder},
+    };
+
+    const REGTEST_FIRST_9: &[u8] = include_bytes!("../../data/proofs/regtest/regtest_first_9.bin");
+    const REGTEST_HEADERS: &[u8] = include_bytes!("../../data/headers/regtest-headers.bin");
+    const TESTNET4_HEADERS: &[u8] = include_bytes!("../../data/headers/testnet4-headers.bin");
+    const SIGNET_HEADERS: &[u8] = include_bytes!("../../data/headers/signet-headers.bin");
+
+    fn decode(bytes: &[u8]) -> Vec<CircuitBlockHeader> {
+        bytes
+            .chunks(80)
+            .map(|header| CircuitBlockHeader::try_from_slice(header).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_validate_regtest() {
+        let headers = decode(REGTEST_HEADERS);
+        let report = validate_headers(
+            ChainState::new_with_params(&REGTEST_CONSTANTS),
+            &headers,
+            &REGTEST_CONSTANTS,
+            "regtest",
+        );
+        assert_eq!(report.start_height, 0);
+        assert_eq!(report.valid_headers, headers.len());
+        assert_eq!(
+            report.chain_state.block_height,
+            Some(headers.len() as u32 - 1)
+        );
+        assert!(report.retargets.is_empty());
+        assert!(report.min_difficulty_blocks.is_empty());
+        assert_eq!(report.invalid_header, None);
+
+        // From the state of a proof of the first 10 headers.
+        let dir = tempfile::tempdir().unwrap();
+        let proof = dir.path().join("regtest_first_9.bin");
+        let headers_path = dir.path().join("regtest-headers.bin");
+        fs::write(&proof, REGTEST_FIRST_9).unwrap();
+        fs::write(&headers_path, REGTEST_HEADERS).unwrap();
+        let args = ValidateHeadersArgs {
+            headers: headers_path,
+            network: "regtest".to_string(),
+            input_proof: Some(proof),
+            count: Some(20),
+            json: false,
+            signet_challenge: None,
+            signet_pow_limit: None,
+        };
+        let from_proof = run(&args).unwrap();
+        assert_eq!(from_proof.start_height, 10);
+        assert_eq!(from_proof.valid_headers, 20);
+        assert_eq!(from_proof.chain_state.block_height, Some(29));
+    }
+
+    #[test]
+    fn test_validate_stops_at_invalid_header() {
+        let mut headers = decode(REGTEST_HEADERS);
+        headers[30].time = 0;
+        mine(&mut headers[30]);
+        let report = validate_headers(
+            ChainState::new_with_params(&REGTEST_CONSTANTS),
+            &headers,
+            &REGTEST_CONSTANTS,
+            "regtest",
+        );
+        assert_eq!(report.valid_headers, 30);
+        assert_eq!(report.chain_state.block_height, Some(29));
+        let invalid = report.invalid_header.unwrap();
+        assert_eq!(invalid.height, 30);
+        assert_eq!(invalid.reason, "Timestamp is not valid");
+
+        headers = decode(REGTEST_HEADERS);
+        headers[0].nonce += 1;
+        let report = validate_headers(
+            ChainState::new_with_params(&REGTEST_CONSTANTS),
+            &headers,
+            &REGTEST_CONSTANTS,
+            "regtest",
+        );
+        assert_eq!(report.valid_headers, 0);
+        assert_eq!(report.chain_state.block_height, None);
+        assert_eq!(report.invalid_header.unwrap().height, 0);
+    }
+
+    #[test]
+    fn test_validate_testnet4_min_difficulty_blocks() {
+        let report = validate_headers(
+            ChainState::new_with_params(&TESTNET4_CONSTANTS),
+            &decode(TESTNET4_HEADERS),
+            &TESTNET4_CONSTANTS,
+            "testnet4",
+        );
+        assert_eq!(report.invalid_header, None);
+        // Block 1 was mined more than 20 minutes after the genesis block.
+        assert_eq!(report.min_difficulty_blocks, vec![1]);
+    }
+
+    #[test]
+    fn test_validate_custom_signet() {
+        // The public signet headers are valid on a signet with an easier pow limit, since the
+        // first epoch keeps the bits of the genesis block.
+        let params = signet_constants_from_hex(None, Some("207fffff")).unwrap();
+        let headers = decode(SIGNET_HEADERS);
+        let report = validate_headers(
+            ChainState::new_with_params(&params),
+            &headers,
+            &params,
+            "signet",
+        );
+        assert_eq!(report.invalid_header, None);
+        assert_eq!(report.valid_headers, headers.len());
+
+        let private = signet_constants_from_hex(Some("51"), None).unwrap();
+        assert_ne!(private.magic, SIGNET_CONSTANTS.magic);
+        assert!(signet_constants_from_hex(Some("5"), None).is_err());
+        assert!(signet_constants_from_hex(None, Some("1d00ffff")).is_err());
+    }
+
+    #[test]
+    fn test_validate_retarget() {
+        // Regtest with retargeting, so that a whole epoch can be mined quickly. Its target is too
+        // large to scale without overflowing, so the chain stops before the new target is used.
+        let params = NetworkConstants {
+            pow_no_retargeting: false,
+            ..REGTEST_CONSTANTS
+        };
+        let mut builder = ChainBuilder::new(&params);
+        builder.extend(BLOCKS_PER_EPOCH as usize - 1);
+        let chain_state = builder.chain_state();
+
+        let report = validate_headers(
+            ChainState::new_with_params(&params),
+            builder.headers(),
+            &params,
+            "regtest",
+        );
+        assert_eq!(report.invalid_header, None);
+        assert_eq!(report.retargets.len(), 1);
+        let retarget = &report.retargets[0];
+        assert_eq!(retarget.height, BLOCKS_PER_EPOCH);
+        assert_eq!(retarget.timespan, 600 * (BLOCKS_PER_EPOCH as i64 - 1));
+        assert_eq!(retarget.old_bits, "207fffff");
+        assert_eq!(
+            retarget.new_bits,
+            format!("{:08x}", chain_state.current_target_bits)
+        );
+        assert_eq!(
+            retarget.difficulty_change,
+            target(0x207fffff) / target(chain_state.current_target_bits)
+        );
+    }
+}
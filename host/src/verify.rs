@@ -0,0 +1,487 @@
+use borsh::BorshDeserialize;
+use crypto_bigint::Encoding;
+use risc0_to_bitvm2_core::{
+    final_circuit::FinalCircuitOutput,
+    header_chain::{network_constants, BlockHeaderCircuitOutput, ChainState, CircuitBlockHeader},
+    headers_file::HeadersFile,
+    mmr_native::MMRNative,
+    serde_utils::parse_work,
+};
+use risc0_zkvm::Receipt;
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use crate::inspect::{self, InspectError, Inspection, JournalSummary};
+
+/// The receipt kinds `verify` can require.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpectedKind {
+    Succinct,
+    Composite,
+    Groth16,
+    /// Any kind that verifies.
+    Any,
+}
+
+/// A block hash at a height, in display byte order, e.g. `10:000000002c05...`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockAtHeight {
+    pub height: u32,
+    /// In internal byte order.
+    pub block_hash: [u8; 32],
+}
+
+/// Parses a block hash in display byte order.
+pub fn parse_block_hash(hash: &str) -> Result<[u8; 32], String> {
+    let mut block_hash: [u8; 32] = hex::decode(hash)
+        .map_err(|e| e.to_string())?
+        .try_into()
+        .map_err(|_| "a block hash is 32 bytes".to_string())?;
+    block_hash.reverse();
+    Ok(block_hash)
+}
+
+fn parse_block_at_height(arg: &str) -> Result<BlockAtHeight, String> {
+    let (height, hash) = arg
+        .split_once(':')
+        .ok_or("expected <height>:<block hash>")?;
+    Ok(BlockAtHeight {
+        height: height.parse().map_err(|e| format!("{}", e))?,
+        block_hash: parse_block_hash(hash)?,
+    })
+}
+
+/// The checks of `host verify` besides the receipt itself.
+#[derive(clap::Args, Debug, Clone)]
+pub struct VerifyOptions {
+    /// The required receipt kind.
+    #[clap(long, value_enum, default_value = "succinct")]
+    pub kind: ExpectedKind,
+    /// Fail unless the header chain is at least this high.
+    #[clap(long)]
+    pub min_height: Option<u32>,
+    /// Fail unless this is the best block hash of the header chain (in display byte order).
+    #[clap(long, value_parser = parse_block_hash)]
+    pub best_block_hash: Option<[u8; 32]>,
+    /// Fail unless the MMR of the header chain contains this block hash at this height, given as
+    /// `<height>:<block hash>`. Can be repeated.
+    #[clap(long, value_parser = parse_block_at_height)]
+    pub mmr_contains: Vec<BlockAtHeight>,
+    /// The headers file to build the MMR inclusion proofs from. Defaults to
+    /// `data/headers/<network>-headers.bin`.
+    #[clap(long)]
+    pub headers: Option<PathBuf>,
+    /// The borsh encoded `FinalCircuitOutput` of a final circuit receipt, checked against its
+    /// journal and the minimum total work.
+    #[clap(long)]
+    pub final_output: Option<PathBuf>,
+    /// Fail unless the final circuit enforced at least this minimum total work, in hex with a
+    /// `0x` prefix or in decimal. Defaults to the recommended minimum of the network.
+    #[clap(long, value_parser = parse_work)]
+    pub min_total_work: Option<[u8; 32]>,
+}
+
+#[derive(Debug)]
+pub enum VerifyError {
+    /// The receipt file cannot be read.
+    Io(String),
+    /// The receipt is not of an embedded guest, or does not verify.
+    InvalidReceipt(String),
+    WrongKind {
+        expected: ExpectedKind,
+        actual: String,
+    },
+    /// The receipt is valid, but does not prove the expected chain facts.
+    AssertionFailed(String),
+}
+
+impl VerifyError {
+    /// The exit code of `host verify`, for monitoring. Usage errors exit with 2.
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            VerifyError::Io(_) => ExitCode::from(1),
+            VerifyError::InvalidReceipt(_) => ExitCode::from(3),
+            VerifyError::WrongKind { .. } => ExitCode::from(4),
+            VerifyError::AssertionFailed(_) => ExitCode::from(5),
+        }
+    }
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::Io(e) => write!(f, "{}", e),
+            VerifyError::InvalidReceipt(e) => write!(f, "invalid receipt: {}", e),
+            VerifyError::WrongKind { expected, actual } => {
+                write!(f, "expected a {:?} receipt, got a {} one", expected, actual)
+            }
+            VerifyError::AssertionFailed(e) => write!(f, "assertion failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/// Verifies the receipt against the embedded guest that produced it, and checks its kind and
+/// the expected chain facts. `headers` provides the headers for MMR inclusion checks.
+pub fn verify(
+    receipt: &Receipt,
+    options: &VerifyOptions,
+    headers: impl FnOnce(&str) -> Result<Vec<CircuitBlockHeader>, String>,
+) -> Result<Inspection, VerifyError> {
+    let final_output = options
+        .final_output
+        .as_ref()
+        .map(|path| fs::read(path).map_err(|e| VerifyError::Io(format!("{:?}: {}", path, e))))
+        .transpose()?;
+    let inspection = inspect::inspect(receipt, final_output.as_deref()).map_err(|e| match e {
+        InspectError::FinalOutput(_) => VerifyError::AssertionFailed(e.to_string()),
+        _ => VerifyError::InvalidReceipt(e.to_string()),
+    })?;
+    if let Some(e) = &inspection.verification_error {
+        return Err(VerifyError::InvalidReceipt(e.clone()));
+    }
+    let kind_matches = match options.kind {
+        ExpectedKind::Succinct => inspection.receipt_kind == "succinct",
+        ExpectedKind::Composite => inspection.receipt_kind == "composite",
+        ExpectedKind::Groth16 => inspection.receipt_kind == "groth16",
+        ExpectedKind::Any => true,
+    };
+    if !kind_matches {
+        return Err(VerifyError::WrongKind {
+            expected: options.kind,
+            actual: inspection.receipt_kind.clone(),
+        });
+    }
+
+    if let JournalSummary::FinalSpv { output, .. } = &inspection.journal {
+        check_final_output(output.as_ref(), &inspection.guest.network, options)?;
+    }
+
+    if options.min_height.is_none()
+        && options.best_block_hash.is_none()
+        && options.mmr_contains.is_empty()
+    {
+        return Ok(inspection);
+    }
+    let journal = &receipt.journal.bytes;
+    let chain_state = match &inspection.journal {
+        JournalSummary::HeaderChain { .. } => {
+            BlockHeaderCircuitOutput::try_from_slice(journal).map(|output| output.chain_state)
+        }
+        // A range starts from an unproven state, so its chain state proves nothing on its own.
+        JournalSummary::HeaderChainRange { .. } => {
+            return Err(VerifyError::AssertionFailed(
+                "a header chain range receipt only proves a transition, compose it with \
+                 `host aggregate` to check chain facts"
+                    .to_string(),
+            ))
+        }
+        JournalSummary::FinalSpv { .. } => {
+            return Err(VerifyError::AssertionFailed(
+                "a final circuit receipt has no chain state".to_string(),
+            ))
+        }
+    }
+    .map_err(|e| VerifyError::InvalidReceipt(e.to_string()))?;
+
+    check_chain_state(&chain_state, options, || headers(&inspection.guest.network))?;
+    Ok(inspection)
+}
+
+/// Checks the minimum total work a final circuit output commits to against `--min-total-work`,
+/// or the recommended minimum of the network.
+fn check_final_output(
+    output: Option<&FinalCircuitOutput>,
+    network: &str,
+    options: &VerifyOptions,
+) -> Result<(), VerifyError> {
+    let required = match options.min_total_work {
+        Some(min_total_work) => min_total_work,
+        None => network_constants(network)
+            .ok_or_else(|| VerifyError::Io(format!("unknown network {}", network)))?
+            .min_total_work
+            .to_be_bytes(),
+    };
+    match output {
+        Some(output) => output
+            .check_min_total_work(&required)
+            .map_err(|e| VerifyError::AssertionFailed(e.to_string())),
+        None if required == [0; 32] => Ok(()),
+        // The journal is only the digest of the output, so it says nothing about the work.
+        None => Err(VerifyError::AssertionFailed(
+            "a final circuit receipt only commits to its output digest, pass --final-output to \
+             check its minimum total work"
+                .to_string(),
+        )),
+    }
+}
+
+fn check_chain_state(
+    chain_state: &ChainState,
+    options: &VerifyOptions,
+    headers: impl FnOnce() -> Result<Vec<CircuitBlockHeader>, String>,
+) -> Result<(), VerifyError> {
+    let height = chain_state.block_height;
+    if let Some(min_height) = options.min_height {
+        if height == u32::MAX {
+            return Err(VerifyError::AssertionFailed(
+                "the chain has no blocks".to_string(),
+            ));
+        }
+        if height < min_height {
+            return Err(VerifyError::AssertionFailed(format!(
+                "the chain is at height {}, below {}",
+                height, min_height
+            )));
+        }
+    }
+    if let Some(best_block_hash) = options.best_block_hash {
+        if chain_state.best_block_hash != best_block_hash {
+            return Err(VerifyError::AssertionFailed(format!(
+                "the best block hash is {}",
+                hex::encode(
+                    chain_state
+                        .best_block_hash
+                        .iter()
+                        .rev()
+                        .copied()
+                        .collect::<Vec<u8>>()
+                )
+            )));
+        }
+    }
+    if options.mmr_contains.is_empty() {
+        return Ok(());
+    }
+
+    // The MMR only keeps its subroots, so the inclusion proofs are built from the headers.
+    let headers = headers().map_err(VerifyError::Io)?;
+    let mmr_size = chain_state.block_hashes_mmr.size as usize;
+    if headers.len() < mmr_size {
+        return Err(VerifyError::Io(format!(
+            "the headers file has {} headers, the MMR {}",
+            headers.len(),
+            mmr_size
+        )));
+    }
+    let mut mmr = MMRNative::new();
+    for header in &headers[..mmr_size] {
+        mmr.append(header.compute_block_hash());
+    }
+    for block in &options.mmr_contains {
+        let included = (block.height as usize) < mmr_size && {
+            let (leaf, proof) = mmr.generate_proof(block.height);
+            leaf == block.block_hash
+                && chain_state
+                    .block_hashes_mmr
+                    .verify_proof(block.block_hash, &proof)
+        };
+        if !included {
+            return Err(VerifyError::AssertionFailed(format!(
+                "the MMR does not contain block {} at height {}",
+                hex::encode(block.block_hash.iter().rev().copied().collect::<Vec<u8>>()),
+                block.height
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Reads a legacy or versioned headers file of the network from genesis for `verify`.
+pub fn read_headers(path: &Path, network: &str) -> Result<Vec<CircuitBlockHeader>, String> {
+    let bytes = fs::read(path).map_err(|e| format!("{:?}: {}", path, e))?;
+    let magic = network_constants(network)
+        .ok_or_else(|| format!("unknown network {}", network))?
+        .magic;
+    let file = HeadersFile::decode(&bytes, magic).map_err(|e| format!("{:?}: {}", path, e))?;
+    if file.start_height != 0 {
+        return Err(format!("{:?} does not start at genesis", path));
+    }
+    Ok(file.headers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex_literal::hex;
+    use risc0_to_bitvm2_core::{
+        final_circuit::FINAL_CIRCUIT_OUTPUT_VERSION, header_chain::MAINNET_CONSTANTS,
+    };
+
+    const MAINNET_FIRST_10: &[u8] =
+        include_bytes!("../../data/proofs/mainnet/mainnet_first_10.bin");
+
+    // Mainnet block headers from 0 to 11
+    const MAINNET_BLOCK_HEADERS: [[u8; 80]; 12] = [
+        hex!("0100000000000000000000000000000000000000000000000000000000000000000000003ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a29ab5f49ffff001d1dac2b7c"),
+        hex!("010000006fe28c0ab6f1b372c1a6a246ae63f74f931e8365e15a089c68d6190000000000982051fd1e4ba744bbbe680e1fee14677ba1a3c3540bf7b1cdb606e857233e0e61bc6649ffff001d01e36299"),
+        hex!("010000004860eb18bf1b1620e37e9490fc8a427514416fd75159ab86688e9a8300000000d5fdcc541e25de1c7a5addedf24858b8bb665c9f36ef744ee42c316022c90f9bb0bc6649ffff001d08d2bd61"),
+        hex!("01000000bddd99ccfda39da1b108ce1a5d70038d0a967bacb68b6b63065f626a0000000044f672226090d85db9a9f2fbfe5f0f9609b387af7be5b7fbb7a1767c831c9e995dbe6649ffff001d05e0ed6d"),
+        hex!("010000004944469562ae1c2c74d9a535e00b6f3e40ffbad4f2fda3895501b582000000007a06ea98cd40ba2e3288262b28638cec5337c1456aaf5eedc8e9e5a20f062bdf8cc16649ffff001d2bfee0a9"),
+        hex!("0100000085144a84488ea88d221c8bd6c059da090e88f8a2c99690ee55dbba4e00000000e11c48fecdd9e72510ca84f023370c9a38bf91ac5cae88019bee94d24528526344c36649ffff001d1d03e477"),
+        hex!("01000000fc33f596f822a0a1951ffdbf2a897b095636ad871707bf5d3162729b00000000379dfb96a5ea8c81700ea4ac6b97ae9a9312b2d4301a29580e924ee6761a2520adc46649ffff001d189c4c97"),
+        hex!("010000008d778fdc15a2d3fb76b7122a3b5582bea4f21f5a0c693537e7a03130000000003f674005103b42f984169c7d008370967e91920a6a5d64fd51282f75bc73a68af1c66649ffff001d39a59c86"),
+        hex!("010000004494c8cf4154bdcc0720cd4a59d9c9b285e4b146d45f061d2b6c967100000000e3855ed886605b6d4a99d5fa2ef2e9b0b164e63df3c4136bebf2d0dac0f1f7a667c86649ffff001d1c4b5666"),
+        hex!("01000000c60ddef1b7618ca2348a46e868afc26e3efc68226c78aa47f8488c4000000000c997a5e56e104102fa209c6a852dd90660a20b2d9c352423edce25857fcd37047fca6649ffff001d28404f53"),
+        hex!("010000000508085c47cc849eb80ea905cc7800a3be674ffc57263cf210c59d8d00000000112ba175a1e04b14ba9e7ea5f76ab640affeef5ec98173ac9799a852fa39add320cd6649ffff001d1e2de565"),
+        hex!("01000000e915d9a478e3adf3186c07c61a22228b10fd87df343c92782ecc052c000000006e06373c80de397406dc3d19c90d71d230058d28293614ea58d6a57f8f5d32f8b8ce6649ffff001d173807f8"),
+    ];
+
+    fn options() -> VerifyOptions {
+        VerifyOptions {
+            kind: ExpectedKind::Succinct,
+            min_height: None,
+            best_block_hash: None,
+            mmr_contains: vec![],
+            headers: None,
+            final_output: None,
+            min_total_work: None,
+        }
+    }
+
+    fn mainnet_headers(network: &str) -> Result<Vec<CircuitBlockHeader>, String> {
+        assert_eq!(network, "mainnet");
+        Ok(MAINNET_BLOCK_HEADERS
+            .iter()
+            .map(|header| CircuitBlockHeader::try_from_slice(header).unwrap())
+            .collect())
+    }
+
+    fn block_at_height(height: u32) -> BlockAtHeight {
+        BlockAtHeight {
+            height,
+            block_hash: mainnet_headers("mainnet").unwrap()[height as usize].compute_block_hash(),
+        }
+    }
+
+    fn verify_first_10(options: &VerifyOptions) -> Result<Inspection, VerifyError> {
+        let receipt = Receipt::try_from_slice(MAINNET_FIRST_10).unwrap();
+        verify(&receipt, options, mainnet_headers)
+    }
+
+    #[test]
+    fn test_parse_block_at_height() {
+        let mut block_hash = [0u8; 32];
+        block_hash[0] = 0xe9;
+        block_hash[31] = 0x01;
+        assert_eq!(
+            parse_block_at_height(
+                "10:01000000000000000000000000000000000000000000000000000000000000e9"
+            ),
+            Ok(BlockAtHeight {
+                height: 10,
+                block_hash
+            })
+        );
+        assert!(parse_block_at_height("10").is_err());
+        assert!(parse_block_at_height("10:abcd").is_err());
+    }
+
+    #[test]
+    fn test_verify_receipt() {
+        verify_first_10(&options()).unwrap();
+        let err = verify_first_10(&VerifyOptions {
+            kind: ExpectedKind::Composite,
+            ..options()
+        })
+        .unwrap_err();
+        assert!(matches!(err, VerifyError::WrongKind { .. }));
+        assert_eq!(err.exit_code(), ExitCode::from(4));
+
+        let mut receipt = Receipt::try_from_slice(MAINNET_FIRST_10).unwrap();
+        receipt.journal.bytes[0] ^= 1;
+        let err = verify(&receipt, &options(), mainnet_headers).unwrap_err();
+        assert!(matches!(err, VerifyError::InvalidReceipt(_)));
+        assert_eq!(err.exit_code(), ExitCode::from(3));
+    }
+
+    #[test]
+    fn test_verify_chain_facts() {
+        verify_first_10(&VerifyOptions {
+            min_height: Some(10),
+            best_block_hash: Some(block_at_height(10).block_hash),
+            mmr_contains: vec![block_at_height(0), block_at_height(4), block_at_height(10)],
+            ..options()
+        })
+        .unwrap();
+
+        let wrong_block = BlockAtHeight {
+            height: 5,
+            ..block_at_height(4)
+        };
+        for options in [
+            VerifyOptions {
+                min_height: Some(11),
+                ..options()
+            },
+            VerifyOptions {
+                best_block_hash: Some(block_at_height(9).block_hash),
+                ..options()
+            },
+            VerifyOptions {
+                mmr_contains: vec![wrong_block],
+                ..options()
+            },
+            VerifyOptions {
+                // Beyond the proven chain.
+                mmr_contains: vec![block_at_height(11)],
+                ..options()
+            },
+        ] {
+            let err = verify_first_10(&options).unwrap_err();
+            assert!(matches!(err, VerifyError::AssertionFailed(_)), "{}", err);
+            assert_eq!(err.exit_code(), ExitCode::from(5));
+        }
+    }
+
+    #[test]
+    fn test_check_final_output() {
+        let mut output = FinalCircuitOutput {
+            version: FINAL_CIRCUIT_OUTPUT_VERSION,
+            network_magic: MAINNET_CONSTANTS.magic,
+            genesis_block_hash: MAINNET_CONSTANTS.genesis_block_hash,
+            header_chain_method_id: [1; 8],
+            best_block_hash: [2; 32],
+            block_height: 100,
+            total_work: [3; 32],
+            min_total_work: [0; 32],
+            txid: [4; 32],
+            tx_block_hash: [5; 32],
+            tx_block_height: 42,
+        };
+        // Mainnet recommends a minimum total work, the test networks leave it to the verifier.
+        check_final_output(Some(&output), "mainnet", &options()).unwrap_err();
+        check_final_output(None, "mainnet", &options()).unwrap_err();
+        check_final_output(None, "testnet4", &options()).unwrap();
+        check_final_output(Some(&output), "testnet4", &options()).unwrap();
+
+        output.min_total_work = MAINNET_CONSTANTS.min_total_work.to_be_bytes();
+        check_final_output(Some(&output), "mainnet", &options()).unwrap();
+
+        let mut required = output.min_total_work;
+        required[31] += 1;
+        let err = check_final_output(
+            Some(&output),
+            "mainnet",
+            &VerifyOptions {
+                min_total_work: Some(required),
+                ..options()
+            },
+        )
+        .unwrap_err();
+        assert!(matches!(err, VerifyError::AssertionFailed(_)), "{}", err);
+        check_final_output(
+            None,
+            "mainnet",
+            &VerifyOptions {
+                min_total_work: Some([0; 32]),
+                ..options()
+            },
+        )
+        .unwrap();
+    }
+}